@@ -1,4 +1,5 @@
 use config::{Config, ConfigError, File};
+use domain::domain::{EventType, Interval, PriceAdjustment};
 use serde::Deserialize;
 use std::{collections::HashMap, env};
 
@@ -8,6 +9,21 @@ pub struct AppConfig {
     pub strategies: Vec<Strategy>,
     pub hist_data_range: i64,
     pub backtest_range: i64,
+    pub execution_delay: ExecutionDelay,
+    // How simulated orders are filled, in backtests and paper accounts alike
+    pub fills: FillConfig,
+    pub quote_synthesis: QuoteSynthesis,
+    // Bars replayed by backtests; strategy history remains daily
+    pub bar_interval: Interval,
+    // Prices replayed by backtests
+    pub backtest_prices: PriceAdjustment,
+    pub historical_data: HistoricalDataConfig,
+    pub market_data: MarketDataConfig,
+    pub walk_forward: Option<WalkForward>,
+    // Broker accounts by name, which strategies choose between
+    pub accounts: HashMap<String, AccountConfig>,
+    // How capital is shared among strategies
+    pub allocation: Allocation,
 }
 
 impl AppConfig {
@@ -17,8 +33,27 @@ impl AppConfig {
             .flat_map(|s| s.symbols.clone())
             .collect()
     }
+
+    // The account a strategy trades in, by name. Without one of its own, a strategy trades in the
+    // default account, which unless configured is the Tradier account given by the environment.
+    pub fn account(&self, strategy: &Strategy) -> Result<(String, AccountConfig), String> {
+        let name = strategy
+            .account
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+        match self.accounts.get(&name) {
+            Some(account) => Ok((name, account.clone())),
+            None if name == DEFAULT_ACCOUNT => Ok((name, AccountConfig::tradier(self.sandbox))),
+            None => Err(format!(
+                "Strategy {} trades in unknown account {}",
+                strategy.name, name
+            )),
+        }
+    }
 }
 
+pub const DEFAULT_ACCOUNT: &str = "default";
+
 impl From<ConfigHolder> for AppConfig {
     fn from(holder: ConfigHolder) -> Self {
         AppConfig {
@@ -26,6 +61,16 @@ impl From<ConfigHolder> for AppConfig {
             strategies: holder.strategies.into_iter().map(|s| s.into()).collect(),
             hist_data_range: holder.hist_data_range,
             backtest_range: holder.backtest_range,
+            execution_delay: holder.execution_delay,
+            fills: holder.fills,
+            quote_synthesis: holder.quote_synthesis,
+            bar_interval: holder.bar_interval,
+            backtest_prices: holder.backtest_prices,
+            historical_data: holder.historical_data,
+            market_data: holder.market_data,
+            walk_forward: holder.walk_forward,
+            accounts: holder.accounts,
+            allocation: holder.allocation,
         }
     }
 }
@@ -34,7 +79,18 @@ impl From<ConfigHolder> for AppConfig {
 pub struct Strategy {
     pub name: String,
     pub symbols: Vec<String>,
+    // The most held of each symbol, when allocated by capital
     pub capital: HashMap<String, i64>,
+    // What the strategy is given when allocated by fixed amount or percent of equity
+    pub budget: f64,
+    pub budget_percent: f64,
+    pub params: HashMap<String, f64>,
+    // Prices in the history the strategy sees
+    pub prices: PriceAdjustment,
+    // The name of the account its orders go to, or the default account
+    pub account: Option<String>,
+    // How many shares a buy signal orders, up to the symbol's capital
+    pub sizing: Sizing,
 }
 
 impl From<StrategyHolder> for Strategy {
@@ -50,23 +106,734 @@ impl From<StrategyHolder> for Strategy {
             name: holder.name,
             symbols: holder.symbols,
             capital,
+            budget: holder.budget,
+            budget_percent: holder.budget_percent,
+            params: holder.params,
+            prices: holder.prices,
+            account: holder.account,
+            sizing: holder.sizing,
+        }
+    }
+}
+
+// Where daily history comes from
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HistoricalDataConfig {
+    pub source: HistoricalSource,
+    // For the file source: a directory holding one file per symbol, e.g. data/AAPL.csv
+    pub path: String,
+    pub format: FileFormat,
+    // chrono format string for the date column
+    pub date_format: String,
+    pub columns: ColumnMapping,
+    pub cache: CacheConfig,
+    pub corporate_actions: CorporateActionsConfig,
+    pub validation: ValidationConfig,
+    pub fetch: FetchConfig,
+}
+
+impl Default for HistoricalDataConfig {
+    fn default() -> Self {
+        HistoricalDataConfig {
+            source: HistoricalSource::Tradier,
+            path: "data".to_string(),
+            format: FileFormat::Csv,
+            date_format: "%Y-%m-%d".to_string(),
+            columns: ColumnMapping::default(),
+            cache: CacheConfig::default(),
+            corporate_actions: CorporateActionsConfig::default(),
+            validation: ValidationConfig::default(),
+            fetch: FetchConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoricalSource {
+    #[default]
+    Tradier,
+    File,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl FileFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// Names of the columns holding each field
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub date: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            date: "date".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            volume: "volume".to_string(),
+        }
+    }
+}
+
+// On-disk cache of series fetched from Tradier
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub path: String,
+    // Serve only what is cached, without touching the network
+    pub offline: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: false,
+            path: "cache".to_string(),
+            offline: false,
+        }
+    }
+}
+
+// Where splits and dividends come from, for adjusting prices
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CorporateActionsConfig {
+    pub source: CorporateActionSource,
+    // For the file source: a CSV file with columns symbol, ex_date, action ("split" or "dividend") and
+    // value (the split ratio or cash amount)
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorporateActionSource {
+    #[default]
+    None,
+    File,
+    Tradier,
+}
+
+// How history is fetched from Tradier: symbols are fetched concurrently, with requests limited to
+// Tradier's rate limit of 120 a minute for market data
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FetchConfig {
+    pub concurrency: usize,
+    pub requests_per_second: f64,
+    pub burst: f64,
+    // Further attempts after a failed request, with the delay doubling each time
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            concurrency: 4,
+            requests_per_second: 2.0,
+            burst: 10.0,
+            retries: 3,
+            retry_delay_ms: 500,
+        }
+    }
+}
+
+// Checks on daily history, and what to do about problems found
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ValidationConfig {
+    pub policy: ValidationPolicy,
+    // A close more than this fraction away from both neighbouring closes is an outlier
+    pub max_return: f64,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            policy: ValidationPolicy::Drop,
+            max_return: 0.5,
         }
     }
 }
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValidationPolicy {
+    // Remove bad days
+    #[default]
+    Drop,
+    // Replace bad and missing days with the previous good close
+    ForwardFill,
+    // Exclude the symbol altogether
+    Fail,
+}
+
+// Live market data: recording it to disk, or replaying a recording in its place
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MarketDataConfig {
+    // Where quotes come from; see provider()
+    pub provider: Option<MarketDataProvider>,
+    // Event types requested from Tradier's stream
+    pub events: Vec<EventType>,
+    // Tradier's streaming endpoints, which tests point at a local stand-in
+    pub session_url: String,
+    pub stream_url: String,
+    pub recording: RecordingConfig,
+    pub bars: BarConfig,
+    // How quotes queue for subscribers, such as strategies, that don't name their own policy
+    pub subscriptions: SubscriptionConfig,
+    pub health: FeedHealthConfig,
+    // When set, the server trades against the recording rather than Tradier's feed, and backtests
+    // replay its quotes rather than synthesizing them from bars
+    pub replay: Option<ReplayConfig>,
+    // For the websocket provider
+    pub websocket: Option<WebsocketConfig>,
+}
+
+impl MarketDataConfig {
+    // The provider named, or else a replay when a recording is given and Tradier otherwise
+    pub fn provider(&self) -> MarketDataProvider {
+        match (self.provider, &self.replay) {
+            (Some(provider), _) => provider,
+            (None, Some(_)) => MarketDataProvider::Replay,
+            (None, None) => MarketDataProvider::Tradier,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarketDataProvider {
+    Tradier,
+    // Any vendor streaming JSON over a websocket, as described by [market_data.websocket]
+    Websocket,
+    // The recording given by [market_data.replay]
+    Replay,
+}
+
+impl Default for MarketDataConfig {
+    fn default() -> Self {
+        MarketDataConfig {
+            provider: None,
+            events: vec![EventType::Quote],
+            session_url: "https://api.tradier.com/v1/markets/events/session".to_string(),
+            stream_url: "wss://ws.tradier.com/v1/markets/events".to_string(),
+            recording: RecordingConfig::default(),
+            bars: BarConfig::default(),
+            subscriptions: SubscriptionConfig::default(),
+            health: FeedHealthConfig::default(),
+            replay: None,
+            websocket: None,
+        }
+    }
+}
+
+// Bars built from live quotes
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct BarConfig {
+    // None are built unless intervals are given, e.g. ["1sec", "1min", "5min"]
+    pub intervals: Vec<Interval>,
+    // Store completed bars through the persistence service
+    pub persist: bool,
+}
+
+// When quotes aren't fit to trade on, and when the feed has gone quiet
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FeedHealthConfig {
+    // Quotes whose newer side is older than this are stale; 0 turns the check off
+    pub max_lag_secs: f64,
+    // An alert is raised for a symbol without quotes for this long during the regular session
+    pub max_silence_secs: f64,
+    // Whether quotes with the bid equal to the ask may be traded on
+    pub allow_locked: bool,
+}
+
+impl Default for FeedHealthConfig {
+    fn default() -> Self {
+        FeedHealthConfig {
+            max_lag_secs: 10.0,
+            max_silence_secs: 300.0,
+            allow_locked: false,
+        }
+    }
+}
+
+// A subscriber's queue of quotes
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct SubscriptionConfig {
+    // Quotes held for the subscriber, without limit if not given
+    pub capacity: Option<usize>,
+    // What happens once they're all taken
+    pub policy: Backpressure,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backpressure {
+    // The feed waits for the subscriber
+    #[default]
+    Block,
+    // The oldest quote queued is discarded
+    DropOldest,
+    // The quotes queued are reduced to the latest for each symbol
+    Conflate,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub enabled: bool,
+    pub path: String,
+    // Files are rotated after this much uncompressed data, and at the start of each day
+    pub max_file_mb: u64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig {
+            enabled: false,
+            path: "recordings".to_string(),
+            max_file_mb: 100,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReplayConfig {
+    // A recording file, or a directory of them replayed in name order
+    pub path: String,
+    pub speed: ReplaySpeed,
+    // For the accelerated speed: how many times faster than real time
+    pub factor: f64,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        ReplayConfig {
+            path: "recordings".to_string(),
+            speed: ReplaySpeed::RealTime,
+            factor: 10.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplaySpeed {
+    // With the gaps between messages as recorded
+    #[default]
+    RealTime,
+    Accelerated,
+    AsFastAsPossible,
+}
+
+// A vendor's websocket feed. In the messages sent, "{symbols}" is replaced by the symbols as a JSON
+// array and "{token}" by the provider's access token.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct WebsocketConfig {
+    // May also hold "{token}"
+    pub url: String,
+    // Sent once connected, before subscribing
+    pub login: Option<String>,
+    pub subscribe: String,
+    // Sent for symbols no longer wanted; without it, the full subscription is sent again instead
+    pub unsubscribe: Option<String>,
+    pub mapping: QuoteMapping,
+}
+
+// Where a quote's fields are found in a message, as dotted paths such as "data.bid". A message may
+// be a single object or an array of them.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct QuoteMapping {
+    // When set, only messages with `quote_type` in this field are quotes, and others are ignored
+    pub type_field: Option<String>,
+    pub quote_type: String,
+    pub symbol: String,
+    pub bid: String,
+    pub ask: String,
+    // Optional fields: sizes default to zero, exchanges to empty and times to when the message arrived
+    pub bid_size: Option<String>,
+    pub ask_size: Option<String>,
+    pub bid_exchange: Option<String>,
+    pub ask_exchange: Option<String>,
+    pub bid_time: Option<String>,
+    pub ask_time: Option<String>,
+    pub time_format: TimeFormat,
+}
+
+impl Default for QuoteMapping {
+    fn default() -> Self {
+        QuoteMapping {
+            type_field: None,
+            quote_type: "quote".to_string(),
+            symbol: "symbol".to_string(),
+            bid: "bid".to_string(),
+            ask: "ask".to_string(),
+            bid_size: None,
+            ask_size: None,
+            bid_exchange: None,
+            ask_exchange: None,
+            bid_time: None,
+            ask_time: None,
+            time_format: TimeFormat::EpochMillis,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeFormat {
+    // Numbers, or strings of digits
+    #[default]
+    EpochMillis,
+    EpochSeconds,
+    Rfc3339,
+}
+
+// A broker account orders can be routed to. Secrets stay in the environment, so the account id and token
+// are given as the names of the variables holding them.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AccountConfig {
+    pub broker: BrokerKind,
+    // For Tradier: the API host, e.g. "sandbox.tradier.com", or a full URL
+    pub host: String,
+    pub account_id_env: String,
+    pub token_env: String,
+    // For the paper broker: the cash the account starts with
+    pub cash: f64,
+}
+
+impl AccountConfig {
+    // The Tradier account given by ACCOUNT_ID, in the sandbox or live as configured
+    pub fn tradier(sandbox: bool) -> AccountConfig {
+        AccountConfig {
+            broker: BrokerKind::Tradier,
+            host: if sandbox {
+                "sandbox.tradier.com"
+            } else {
+                "api.tradier.com"
+            }
+            .to_string(),
+            account_id_env: "ACCOUNT_ID".to_string(),
+            token_env: if sandbox {
+                "SANDBOX_TOKEN"
+            } else {
+                "ACCESS_TOKEN"
+            }
+            .to_string(),
+            cash: 0.0,
+        }
+    }
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        AccountConfig {
+            cash: 100000.0,
+            ..AccountConfig::tradier(true)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BrokerKind {
+    #[default]
+    Tradier,
+    // Fills every order in full at its price, held in memory
+    Paper,
+}
+
+// Only the fields of the model chosen are used; lots, minimums and tranches apply to every model
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Sizing {
+    pub model: SizingModel,
+    // fixed-shares
+    pub shares: i64,
+    // fixed-notional
+    pub notional: f64,
+    // percent-of-equity
+    pub percent: f64,
+    // volatility-target: the percent of equity risked on a move of one unit of volatility
+    pub risk_percent: f64,
+    pub volatility: Volatility,
+    pub atr_days: usize,
+    // kelly: the fraction of the full Kelly bet made, given the odds of winning and the average win
+    // as a multiple of the average loss
+    pub kelly_fraction: f64,
+    pub win_rate: f64,
+    pub payoff_ratio: f64,
+    // The position is built up over this many buy signals
+    pub tranches: u32,
+    // Orders are rounded down to a multiple of this many shares
+    pub lot_size: i64,
+    // Smaller orders aren't placed
+    pub min_shares: i64,
+    pub min_notional: f64,
+}
+
+impl Default for Sizing {
+    fn default() -> Self {
+        Sizing {
+            model: SizingModel::default(),
+            shares: 0,
+            notional: 0.0,
+            percent: 0.0,
+            risk_percent: 1.0,
+            volatility: Volatility::default(),
+            atr_days: 14,
+            kelly_fraction: 0.5,
+            win_rate: 0.0,
+            payoff_ratio: 1.0,
+            tranches: 1,
+            lot_size: 1,
+            min_shares: 1,
+            min_notional: 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizingModel {
+    // As much as the symbol's capital buys
+    #[default]
+    Capital,
+    FixedShares,
+    FixedNotional,
+    PercentOfEquity,
+    VolatilityTarget,
+    Kelly,
+}
+
+// The dollar volatility of a share, for volatility targeting
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Volatility {
+    // The average true range over atr_days
+    #[default]
+    Atr,
+    // The standard deviation of closes over the strategy's history
+    StdDev,
+}
+
+// How capital is shared among strategies, reallocated at the start of each day. Each strategy's budget
+// is split among its symbols to limit what it holds of each.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Allocation {
+    pub method: AllocationMethod,
+    // inverse-volatility: the percent of an account's equity shared among the strategies trading in it
+    pub percent: f64,
+    // Days of returns over which volatility is measured
+    pub volatility_days: usize,
+    // The equity allocated where there's no account to read it from, as in backtests
+    pub equity: f64,
+}
+
+impl Default for Allocation {
+    fn default() -> Self {
+        Allocation {
+            method: AllocationMethod::default(),
+            percent: 100.0,
+            volatility_days: 20,
+            equity: 100000.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllocationMethod {
+    // Each strategy's capital for each symbol, as configured
+    #[default]
+    Capital,
+    // Each strategy's budget, split evenly among its symbols
+    Fixed,
+    // Each strategy's budget_percent of its account's equity, split evenly among its symbols
+    PercentOfEquity,
+    // Strategies, and the symbols within each, are weighted by the inverse of their volatility
+    InverseVolatility,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct FillConfig {
+    // Buys fill this many basis points above the price quoted, and sells this many below
+    pub slippage_bps: f64,
+    pub commission: CommissionConfig,
+}
+
+// Charged per order as per_order plus per_share for each share, but no less than minimum
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct CommissionConfig {
+    pub per_order: f64,
+    pub per_share: f64,
+    pub minimum: f64,
+}
+
+// When backtest orders are filled, relative to the quote that generated them
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExecutionDelay {
+    // At the signalling quote's price
+    #[default]
+    None,
+    // At the open of the next trading day
+    NextOpen,
+}
+
+// How backtest quotes are generated from daily bars
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct QuoteSynthesis {
+    pub mode: SynthesisMode,
+    pub path: IntradayPath,
+    // Only used by the random path
+    pub seed: u64,
+    // Synthetic bid/ask spread around each price, in basis points
+    pub spread_bps: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SynthesisMode {
+    // A single quote at the close
+    #[default]
+    Close,
+    // Quotes at the open, the high and low in the order given by the path, and the close
+    Ohlc,
+}
+
+// The order in which the day's high and low are visited
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum IntradayPath {
+    HighFirst,
+    LowFirst,
+    // Whichever of the high and low is nearer the open is visited first
+    #[default]
+    NearestFirst,
+    // Chosen per symbol and day from the seed, so runs are repeatable
+    Random,
+}
+
+// Walk-forward analysis: parameters are optimized over each in-sample window and then applied to the
+// out-of-sample window that follows it
+#[derive(Deserialize, Debug, Clone)]
+pub struct WalkForward {
+    pub in_sample_days: i64,
+    pub out_of_sample_days: i64,
+    pub metric: WalkForwardMetric,
+    // Parameter name -> candidate values; every combination is tried in-sample
+    pub grid: HashMap<String, Vec<f64>>,
+}
+
+// What parameters are optimized for in-sample
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WalkForwardMetric {
+    #[serde(rename = "pnl")]
+    PnL,
+    Sharpe,
+    ReturnOverDrawdown,
+}
+
 #[derive(Deserialize)]
 struct ConfigHolder {
     pub sandbox: bool,
     pub strategies: Vec<StrategyHolder>,
     pub hist_data_range: i64,
     pub backtest_range: i64,
+    #[serde(default)]
+    pub execution_delay: ExecutionDelay,
+    #[serde(default)]
+    pub fills: FillConfig,
+    #[serde(default)]
+    pub allocation: Allocation,
+    #[serde(default)]
+    pub quote_synthesis: QuoteSynthesis,
+    #[serde(default)]
+    pub bar_interval: Interval,
+    #[serde(default)]
+    pub backtest_prices: PriceAdjustment,
+    #[serde(default)]
+    pub historical_data: HistoricalDataConfig,
+    #[serde(default)]
+    pub market_data: MarketDataConfig,
+    pub walk_forward: Option<WalkForward>,
+    #[serde(default)]
+    pub accounts: HashMap<String, AccountConfig>,
 }
 
 #[derive(Deserialize)]
 struct StrategyHolder {
     pub name: String,
     pub symbols: Vec<String>,
+    #[serde(default)]
     pub capital: Vec<i64>,
+    #[serde(default)]
+    pub budget: f64,
+    #[serde(default)]
+    pub budget_percent: f64,
+    #[serde(default)]
+    pub params: HashMap<String, f64>,
+    #[serde(default)]
+    pub prices: PriceAdjustment,
+    pub account: Option<String>,
+    #[serde(default)]
+    pub sizing: Sizing,
+}
+
+impl StrategyHolder {
+    // Capital is given for every symbol or, unless allocated by capital, for none
+    fn validate(&self, allocation: &Allocation) -> Result<(), String> {
+        let required = allocation.method == AllocationMethod::Capital;
+        if self.capital.len() != self.symbols.len() && (required || !self.capital.is_empty()) {
+            return Err(format!(
+                "Strategy {} has {} symbols but capital for {}",
+                self.name,
+                self.symbols.len(),
+                self.capital.len()
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl AppConfig {
@@ -79,6 +846,11 @@ impl AppConfig {
             .add_source(File::with_name("app_config/local").required(false))
             .build()?
             .try_deserialize::<ConfigHolder>()?;
+        for strategy in &holder.strategies {
+            strategy
+                .validate(&holder.allocation)
+                .map_err(ConfigError::Message)?;
+        }
         Ok(holder.into())
     }
 }
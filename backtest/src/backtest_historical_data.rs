@@ -1,6 +1,7 @@
 use chrono::NaiveDate;
-use domain::domain::Day;
+use domain::domain::{CorporateAction, Day, PriceAdjustment};
 use log::*;
+use services::corporate_actions::{self, Adjustment};
 use services::historical_data::HistoricalDataService;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -14,15 +15,48 @@ pub fn new(
     range: i64,
     hist_data_range: i64,
     underlying: Arc<impl HistoricalDataService + 'static + Send + Sync>,
+    prices: PriceAdjustment,
 ) -> Arc<impl BacktestHistoricalDataManager> {
+    let adjustments = match prices {
+        PriceAdjustment::Raw => None,
+        PriceAdjustment::Adjusted => Some(adjustments(end, underlying.as_ref())),
+    };
     Arc::new(implementation::BacktestHistoricalData {
         end,
         range,
         hist_data_range,
         underlying,
+        adjustments,
     })
 }
 
+// Adjusted backtests see all prices as of the end of the backtest, so adjustments are computed once
+// over the whole history, with every action up to the end. Daily history, bars and replayed quotes
+// all share them.
+pub fn adjustments(
+    end: NaiveDate,
+    underlying: &impl HistoricalDataService,
+) -> HashMap<String, Vec<Adjustment>> {
+    let history = underlying.fetch_as_of(end + chrono::Duration::days(1));
+    let actions = underlying.corporate_actions();
+    history
+        .iter()
+        .map(|(symbol, days)| {
+            let known: Vec<CorporateAction> = actions
+                .get(symbol)
+                .map(|actions| {
+                    actions
+                        .iter()
+                        .filter(|a| a.ex_date <= end)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            (symbol.clone(), corporate_actions::adjustments(days, &known))
+        })
+        .collect()
+}
+
 mod implementation {
     use super::*;
 
@@ -31,38 +65,65 @@ mod implementation {
         pub range: i64,
         pub hist_data_range: i64,
         pub underlying: Arc<H>,
+        // Per symbol, when prices are adjusted
+        pub adjustments: Option<HashMap<String, Vec<Adjustment>>>,
+    }
+
+    impl<H: HistoricalDataService + 'static + Send + Sync> BacktestHistoricalData<H> {
+        fn adjust(&self, symbol: &str, days: Vec<Day>) -> Vec<Day> {
+            match self.adjustments.as_ref().and_then(|a| a.get(symbol)) {
+                Some(adjustments) => corporate_actions::adjust(&days, adjustments),
+                None => days,
+            }
+        }
     }
 
     impl<H: HistoricalDataService + 'static + Send + Sync> BacktestHistoricalDataManager
         for BacktestHistoricalData<H>
     {
         fn all(&self) -> Arc<HashMap<String, Vec<Day>>> {
-            self.underlying.fetch(self.end).clone()
+            // Everything up to and including the last day of the backtest
+            let history = self
+                .underlying
+                .fetch_as_of(self.end + chrono::Duration::days(1))
+                .iter()
+                .map(|(symbol, days)| (symbol.clone(), self.adjust(symbol, days.clone())))
+                .collect();
+            Arc::new(history)
         }
     }
 
     impl<H: HistoricalDataService + 'static + Send + Sync> HistoricalDataService
         for BacktestHistoricalData<H>
     {
-        fn fetch(&self, end: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
-            // end is some past trading day. We want to fetch from end - range - hist_data_range to end
-            let start = end - chrono::Duration::days(self.hist_data_range);
-            info!("Fetching from {} to {}", start, end);
+        fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+            // as_of is some past trading day. We want the hist_data_range days before it, excluding as_of
+            // itself, whose close is the price being traded.
+            let start = as_of - chrono::Duration::days(self.hist_data_range);
+            info!("Fetching from {} to {} (exclusive)", start, as_of);
             let data = self
                 .underlying
-                .fetch(end)
+                .fetch_as_of(as_of)
                 .iter()
                 .map(|(symbol, days)| {
                     let data = days
                         .iter()
-                        .filter(|day| day.date >= start && day.date <= end)
+                        .filter(|day| day.date >= start && day.date < as_of)
                         .cloned()
                         .collect();
-                    (symbol.clone(), data)
+                    (symbol.clone(), self.adjust(symbol, data))
                 })
                 .collect();
             Arc::new(data)
         }
+
+        fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+            // Already applied to adjusted prices
+            match self.adjustments {
+                Some(_) => Arc::new(HashMap::new()),
+                None => self.underlying.corporate_actions(),
+            }
+        }
     }
 }
 
@@ -102,7 +163,6 @@ mod implementation {
 // }
 
 #[cfg(test)]
+#[allow(clippy::into_iter_on_ref, clippy::useless_vec)]
 #[path = "./tests/backtest_historical_data_test.rs"]
 mod backtest_historical_data_test;
-#[path = "./tests/mock_historical_data_service.rs"]
-mod mock_historical_data_service;
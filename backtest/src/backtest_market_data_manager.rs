@@ -1,16 +1,25 @@
-use crate::backtest_historical_data::BacktestHistoricalDataManager;
+use app_config::app_config::{IntradayPath, QuoteSynthesis, SynthesisMode};
 use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 use core::util::print_map;
-use domain::domain::{Day, Quote};
+use domain::domain::{Bar, Day, Interval, Quote};
 use log::*;
-use services::market_data::MarketDataService;
+use services::market_data_recorder::Record;
 use std::{collections::HashMap, sync::Arc};
 
 pub trait BacktestMarketDataManager {
-    fn service_for_date(
-        &self,
-        date: NaiveDate,
-    ) -> Result<Arc<impl MarketDataService + 'static + Send + Sync>, String>;
+    // The day's quotes in the order they are to be replayed: by time, then by symbol
+    fn quotes_for_date(&self, date: NaiveDate) -> Result<&[Quote], String>;
+
+    // Each symbol's opening price on the given date
+    fn opens_for_date(&self, date: NaiveDate) -> Result<&HashMap<String, f64>, String>;
+}
+
+pub enum QuoteSource {
+    // Quotes are synthesized from each symbol's bars
+    Bars(Arc<HashMap<String, Vec<Bar>>>),
+    // Quotes recorded from the live feed, replayed in the order they arrived. Each symbol opens at
+    // the mid of its first quote of the day.
+    Recorded(Vec<Record>),
 }
 
 pub fn new(
@@ -18,88 +27,193 @@ pub fn new(
     symbols: Vec<String>,
     backtest_range: i64,
     end: NaiveDate,
-    backtest_historical_data: Arc<impl BacktestHistoricalDataManager + Send + Sync>,
+    synthesis: QuoteSynthesis,
+    interval: Interval,
+    source: QuoteSource,
 ) -> Arc<impl BacktestMarketDataManager> {
-    // We need to turn a map of symbol->days into a map of date->quotes
-    let history: Vec<Day> = backtest_historical_data
-        .all()
+    let (quotes, opens) = match source {
+        QuoteSource::Bars(bars) => from_bars(&bars, interval, &synthesis),
+        QuoteSource::Recorded(recorded) => from_recorded(recorded, &symbols),
+    };
+    print_map("Quotes", &quotes);
+
+    Arc::new(implementation::BacktestMarketData { quotes, opens })
+}
+
+type Quotes = HashMap<NaiveDate, Vec<Quote>>;
+type Opens = HashMap<NaiveDate, HashMap<String, f64>>;
+
+fn from_bars(
+    bars: &HashMap<String, Vec<Bar>>,
+    interval: Interval,
+    synthesis: &QuoteSynthesis,
+) -> (Quotes, Opens) {
+    // We need to turn a map of symbol->bars into a map of date->quotes
+    let history: Vec<Vec<Bar>> = bars
         .values()
-        .flatten()
-        .cloned()
+        .map(|bars| {
+            let mut bars = bars.clone();
+            bars.sort_by_key(|bar| bar.timestamp);
+            bars
+        })
         .collect();
     info!("\n\nBacktestMarketDataManager: history:\n{:?}", history);
 
-    let mut quotes: HashMap<NaiveDate, Vec<Quote>> = HashMap::new();
-    for day in history {
-        let date: DateTime<Local> = day
-            .date
-            .and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-            .and_local_timezone(Local)
-            .earliest()
-            .expect("Failed to convert date to datetime");
-        quotes.entry(day.date).or_default().push(Quote {
-            symbol: day.symbol.expect("Missing symbol").clone(),
-            bid: day.close,
-            ask: day.close,
-            biddate: date,
-            askdate: date,
-        })
+    let mut quotes: Quotes = HashMap::new();
+    let mut opens: Opens = HashMap::new();
+    for bar in history.iter().flatten() {
+        opens
+            .entry(bar.date())
+            .or_default()
+            .entry(bar.symbol.clone())
+            .or_insert(bar.open);
+        quotes
+            .entry(bar.date())
+            .or_default()
+            .extend(synthesize(bar, interval, synthesis));
     }
-    print_map("Quotes", &quotes);
 
-    Arc::new(implementation::BacktestMarketData { quotes })
+    // History arrives keyed by symbol in no particular order; replay must not depend on that order.
+    // The sort is stable, so each symbol's quotes keep their order within a timestamp.
+    quotes.values_mut().for_each(|quotes| {
+        quotes.sort_by(|a, b| {
+            a.biddate
+                .cmp(&b.biddate)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        })
+    });
+    (quotes, opens)
 }
 
-mod implementation {
-    use super::*;
-    use chrono::NaiveDate;
-    use crossbeam_channel::Receiver;
-    use std::collections::HashMap;
-
-    pub struct BacktestMarketData {
-        pub quotes: HashMap<NaiveDate, Vec<Quote>>,
+// Grouped by the date each quote was received, as a quote's own dates may be stale
+fn from_recorded(recorded: Vec<Record>, symbols: &[String]) -> (Quotes, Opens) {
+    let mut quotes: Quotes = HashMap::new();
+    let mut opens: Opens = HashMap::new();
+    for (date, quote) in recorded
+        .into_iter()
+        .filter_map(|record| Some((record.received.date_naive(), record.quote?)))
+        .filter(|(_, quote)| symbols.contains(&quote.symbol))
+    {
+        opens
+            .entry(date)
+            .or_default()
+            .entry(quote.symbol.clone())
+            .or_insert((quote.bid + quote.ask) / 2.0);
+        quotes.entry(date).or_default().push(quote);
     }
+    (quotes, opens)
+}
 
-    impl BacktestMarketDataManager for BacktestMarketData {
-        fn service_for_date(
-            &self,
-            date: NaiveDate,
-        ) -> Result<Arc<impl MarketDataService + 'static + Send + Sync>, String> {
-            match self.quotes.get(&date) {
-                Some(quotes) => Ok(Arc::new(BacktestMarketDataService {
-                    quotes: quotes.clone(),
-                })),
-                None => Err(format!("No quotes for date {}", date)),
+pub fn daily_bars(history: &HashMap<String, Vec<Day>>) -> Arc<HashMap<String, Vec<Bar>>> {
+    Arc::new(
+        history
+            .iter()
+            .map(|(symbol, days)| {
+                let bars = days.iter().cloned().map(Bar::from).collect();
+                (symbol.clone(), bars)
+            })
+            .collect(),
+    )
+}
+
+// Quotes standing in for the bar's path. Daily bars are spread over notional times through the
+// session; intraday bars over the bar's own span.
+pub fn synthesize(bar: &Bar, interval: Interval, synthesis: &QuoteSynthesis) -> Vec<Quote> {
+    let prices = match synthesis.mode {
+        SynthesisMode::Close => vec![(3, bar.close)],
+        SynthesisMode::Ohlc => {
+            let (first, second) = if high_first(bar, synthesis) {
+                (bar.high, bar.low)
+            } else {
+                (bar.low, bar.high)
+            };
+            vec![(0, bar.open), (1, first), (2, second), (3, bar.close)]
+        }
+    };
+
+    let half_spread = synthesis.spread_bps / 10000.0 / 2.0;
+    prices
+        .into_iter()
+        .map(|(step, px)| {
+            let time = time_of(bar, interval, step);
+            Quote {
+                symbol: bar.symbol.clone(),
+                bid: px * (1.0 - half_spread),
+                ask: px * (1.0 + half_spread),
+                biddate: time,
+                askdate: time,
+                bidsz: 0,
+                asksz: 0,
+                bidexch: String::new(),
+                askexch: String::new(),
             }
+        })
+        .collect()
+}
+
+// Time of the given step of 0 (open) through 3 (close) within the bar
+fn time_of(bar: &Bar, interval: Interval, step: i32) -> DateTime<Local> {
+    match interval {
+        Interval::Daily => {
+            let (hour, minute) = [(9, 30), (11, 30), (14, 0), (16, 0)][step as usize];
+            bar.date()
+                .and_time(NaiveTime::from_hms_opt(hour, minute, 0).unwrap())
+                .and_local_timezone(Local)
+                .earliest()
+                .expect("Failed to convert date to datetime")
         }
+        _ => bar.timestamp + interval.duration() * step / 3,
     }
+}
 
-    pub struct BacktestMarketDataService {
-        quotes: Vec<Quote>,
+fn high_first(bar: &Bar, synthesis: &QuoteSynthesis) -> bool {
+    match synthesis.path {
+        IntradayPath::HighFirst => true,
+        IntradayPath::LowFirst => false,
+        IntradayPath::NearestFirst => bar.high - bar.open < bar.open - bar.low,
+        IntradayPath::Random => {
+            // Hashed rather than drawn from a generator so the choice doesn't depend on replay order
+            path_hash(synthesis.seed, &bar.symbol, bar.timestamp.timestamp()) & 1 == 0
+        }
     }
+}
 
-    impl MarketDataService for BacktestMarketDataService {
-        fn init(
-            &self,
-            shutdown: Arc<std::sync::atomic::AtomicBool>,
-            symbols: Vec<String>,
-        ) -> Result<std::thread::JoinHandle<()>, String> {
-            Err("Please don't call this method".to_string())
-        }
+// FNV-1a over the seed, symbol and time, finished with SplitMix64's mixer so that every bit is usable.
+// Written out here, unlike std's hashers, so a seed gives the same paths on any toolchain.
+fn path_hash(seed: u64, symbol: &str, timestamp: i64) -> u64 {
+    let bytes = seed
+        .to_le_bytes()
+        .into_iter()
+        .chain(symbol.bytes())
+        .chain(timestamp.to_le_bytes());
+    let hash = bytes.fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
 
-        fn subscribe(&self) -> Result<Receiver<Quote>, String> {
-            let (sender, receiver) = crossbeam_channel::unbounded();
+mod implementation {
+    use super::*;
+
+    pub struct BacktestMarketData {
+        pub quotes: HashMap<NaiveDate, Vec<Quote>>,
+        pub opens: HashMap<NaiveDate, HashMap<String, f64>>,
+    }
+
+    impl BacktestMarketDataManager for BacktestMarketData {
+        fn quotes_for_date(&self, date: NaiveDate) -> Result<&[Quote], String> {
             self.quotes
-                .iter()
-                .for_each(|quote| match sender.send(quote.clone()) {
-                    Ok(_) => (),
-                    Err(e) => info!("Error sending quote to subscriber: {}", e),
-                });
-            Ok(receiver)
+                .get(&date)
+                .map(|quotes| quotes.as_slice())
+                .ok_or_else(|| format!("No quotes for date {}", date))
         }
 
-        fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
-            Ok(())
+        fn opens_for_date(&self, date: NaiveDate) -> Result<&HashMap<String, f64>, String> {
+            self.opens
+                .get(&date)
+                .ok_or_else(|| format!("No opens for date {}", date))
         }
     }
 }
@@ -107,5 +221,3 @@ mod implementation {
 #[cfg(test)]
 #[path = "./tests/backtest_market_data_manager_test.rs"]
 mod backtest_market_data_manager_test;
-#[path = "./tests/mock_historical_data_service.rs"]
-mod mock_historical_data_service;
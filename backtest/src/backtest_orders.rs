@@ -1,3 +1,5 @@
+use app_config::app_config::{ExecutionDelay, FillConfig};
+use chrono::NaiveDate;
 use domain::domain::*;
 use log::*;
 use services::orders::OrderService;
@@ -7,52 +9,92 @@ use std::{collections::HashMap, sync::Mutex};
 pub trait BacktestOrderService: OrderService {
     fn open_positions(&self) -> Vec<Position>;
     fn realized_pnl(&self) -> Vec<RealizedPnL>;
+    // Fills orders held back by the execution delay, at the given opening prices
+    fn fill_pending(&self, date: NaiveDate, opens: &HashMap<String, f64>);
 }
 
-pub fn new() -> Arc<impl BacktestOrderService + Send + Sync> {
+pub fn new(
+    execution_delay: ExecutionDelay,
+    fills: FillConfig,
+) -> Arc<impl BacktestOrderService + Send + Sync> {
     Arc::new(implementation::BacktestOrders {
+        execution_delay,
+        fills,
         positions: Arc::new(Mutex::new(HashMap::new())),
+        pending: Arc::new(Mutex::new(Vec::new())),
         pnl: Arc::new(Mutex::new(Vec::new())),
     })
 }
 
 mod implementation {
     use super::*;
+    use services::fills;
     use services::orders::implementation::*;
 
     pub struct BacktestOrders {
+        pub execution_delay: ExecutionDelay,
+        pub fills: FillConfig,
         pub positions: Arc<Mutex<HashMap<String, Position>>>,
+        // Orders awaiting a fill, with the strategy that generated them, in submission order
+        pub pending: Arc<Mutex<Vec<(Order, String)>>>,
         pub pnl: Arc<Mutex<Vec<RealizedPnL>>>,
     }
 
     impl BacktestOrderService for BacktestOrders {
         fn open_positions(&self) -> Vec<Position> {
-            self.positions
+            let mut positions: Vec<Position> = self
+                .positions
                 .lock()
                 .unwrap()
                 .values()
                 .filter(|p| p.quantity > 0)
                 .cloned()
-                .collect()
+                .collect();
+            // Sorted so that anything summed over positions doesn't vary with HashMap order
+            positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+            positions
         }
 
         fn realized_pnl(&self) -> Vec<RealizedPnL> {
             self.pnl.lock().unwrap().clone()
         }
+
+        fn fill_pending(&self, date: NaiveDate, opens: &HashMap<String, f64>) {
+            let fillable: Vec<(Order, String)> = {
+                let mut pending = self.pending.lock().unwrap();
+                let (fillable, waiting) = pending
+                    .drain(..)
+                    .partition(|(order, _)| order.date < date && opens.contains_key(&order.symbol));
+                *pending = waiting;
+                fillable
+            };
+
+            fillable.into_iter().for_each(|(order, strategy)| {
+                let order = Order {
+                    date,
+                    px: opens.get(&order.symbol).cloned(),
+                    ..order
+                };
+                info!("Filling delayed order: {:?}", order);
+                self.fill(&order, strategy);
+            });
+        }
     }
 
     impl OrderService for BacktestOrders {
         fn create_order(&self, order: Order, strategy: String) -> Result<Order, String> {
-            let position = position_from(&order, self.get_position(&order.symbol));
-            self.update_position(&position);
-
-            if order.side == Side::Sell {
-                let pnl = calc_pnl(position, &order, strategy);
-                self.pnl.lock().unwrap().push(pnl.clone());
-                info!("Generated P&L: {:?}", pnl);
+            match self.execution_delay {
+                ExecutionDelay::None => Ok(self.fill(&order, strategy)),
+                ExecutionDelay::NextOpen => {
+                    let mut pending = self.pending.lock().unwrap();
+                    // The position won't reflect a pending order, so a repeated signal would double up
+                    if pending.iter().any(|(o, _)| o.symbol == order.symbol) {
+                        return Err(format!("Order already pending for {}", order.symbol));
+                    }
+                    pending.push((order.clone(), strategy));
+                    Ok(order)
+                }
             }
-
-            Ok(order)
         }
 
         fn get_position(&self, symbol: &str) -> Option<Position> {
@@ -67,4 +109,34 @@ mod implementation {
                 .insert(position.symbol.clone(), position.clone());
         }
     }
+
+    impl BacktestOrders {
+        // Fills at the order's price, less slippage and commission, returning the order as filled
+        fn fill(&self, order: &Order, strategy: String) -> Order {
+            let fill = fills::fill(
+                &self.fills,
+                &order.side,
+                order.quantity,
+                order.px.unwrap_or(0.0),
+            );
+            let order = Order {
+                px: Some(fill.price),
+                ..order.clone()
+            };
+            let position =
+                position_after(&order, self.get_position(&order.symbol), fill.commission);
+            self.update_position(&position);
+
+            if order.side == Side::Sell {
+                let pnl = calc_pnl_after(position, &order, strategy, fill.commission);
+                self.pnl.lock().unwrap().push(pnl.clone());
+                info!("Generated P&L: {:?}", pnl);
+            }
+            order
+        }
+    }
 }
+
+#[cfg(test)]
+#[path = "./tests/backtest_orders_test.rs"]
+mod backtest_orders_test;
@@ -1,12 +1,15 @@
 use crate::backtest_market_data_manager::BacktestMarketDataManager;
-use app_config::app_config::Strategy;
+use crate::backtest_orders::BacktestOrderService;
+use crate::metrics::{EquityCurve, EquityPoint};
+use app_config::app_config::{Allocation, Strategy};
 use chrono::NaiveDate;
 use log::*;
-use services::{historical_data::HistoricalDataService, orders::OrderService};
+use services::allocation;
+use services::historical_data::{with_prices, HistoricalDataService};
 use std::sync::Arc;
 
 pub trait BacktestService {
-    fn run(&self) -> Result<(), String>;
+    fn run(&self) -> Result<EquityCurve, String>;
 }
 
 pub fn new(
@@ -14,8 +17,9 @@ pub fn new(
     backtest_range: i64,
     historical_data: Arc<impl HistoricalDataService + 'static + Send + Sync>,
     market_data_manager: Arc<impl BacktestMarketDataManager + 'static + Send + Sync>,
-    orders: Arc<impl OrderService + 'static + Send + Sync>,
+    orders: Arc<impl BacktestOrderService + 'static + Send + Sync>,
     strategies: Vec<Strategy>,
+    allocation: Allocation,
 ) -> Arc<impl BacktestService + Send + Sync> {
     Arc::new(implementation::Backtest {
         end,
@@ -24,19 +28,20 @@ pub fn new(
         market_data_manager,
         orders,
         strategies,
+        allocation,
     })
 }
 
 mod implementation {
-    use std::sync::atomic::AtomicBool;
-
     use super::*;
-    use services::trading::{self, TradingService};
+    use domain::domain::SymbolData;
+    use services::trading::implementation::{handle_quote, load_history};
+    use std::collections::HashMap;
 
     pub struct Backtest<
         H: HistoricalDataService + 'static + Send + Sync,
         M: BacktestMarketDataManager + 'static + Send + Sync,
-        O: OrderService + 'static + Send + Sync,
+        O: BacktestOrderService + 'static + Send + Sync,
     > {
         pub end: NaiveDate,
         pub backtest_range: i64,
@@ -44,70 +49,127 @@ mod implementation {
         pub market_data_manager: Arc<M>,
         pub orders: Arc<O>,
         pub strategies: Vec<Strategy>,
+        pub allocation: Allocation,
     }
 
     impl<
             H: HistoricalDataService + Send + Sync,
             M: BacktestMarketDataManager + Send + Sync,
-            O: OrderService + Send + Sync,
+            O: BacktestOrderService + Send + Sync,
         > BacktestService for Backtest<H, M, O>
     {
+        // The simulation runs synchronously on the calling thread, so results depend only on the data:
         // - For each date in range:
-        //   - Construct BacktestMarketDataService from MarketDataManager data
-        //   - run() strategies - will subscribe to MarketDataService and be fed quotes
-        fn run(&self) -> Result<(), String> {
+        //   - Fill any orders delayed until that day's open
+        //   - Reallocate capital among strategies, from the equity allocated plus the P&L so far
+        //   - Load each strategy's history as of that date
+        //   - Push each of the day's quotes, in time order, through every strategy and on to the order service
+        //   - Mark open positions to market to produce the day's equity
+        fn run(&self) -> Result<EquityCurve, String> {
             let start = self.end - chrono::Duration::days(self.backtest_range);
             info!("Running backtest from {} to {}", start, self.end);
+            let strategies: Vec<(domain::domain::Strategy, &Strategy)> = self
+                .strategies
+                .iter()
+                .map(|s| {
+                    let strategy = domain::domain::Strategy::with_params(
+                        &s.name,
+                        s.symbols.clone(),
+                        &s.params,
+                    );
+                    (strategy, s)
+                })
+                .collect();
+            let mut marks: HashMap<String, f64> = HashMap::new();
+            let mut curve = Vec::new();
 
             for i in 0..=self.backtest_range {
                 let date = start + chrono::Duration::days(i);
-                let shutdown = Arc::new(AtomicBool::new(false));
-
                 info!("\nRunning for {}", date);
-                match self.market_data_manager.service_for_date(date) {
-                    Ok(market_data) => {
-                        self.strategies.clone().into_iter().for_each(|strategy| {
-                            let mut trading_service = trading::new(
-                                date,
-                                strategy.name.clone(),
-                                strategy.symbols.clone(),
-                                strategy.capital.clone(),
-                                market_data.clone(),
-                                self.historical_data.clone(),
-                                self.orders.clone(),
-                                shutdown.clone(),
-                            );
 
-                            match trading_service.run() {
-                                Ok(_) => {
-                                    info!(
-                                        "Strategy '{}' ran successfully for {}",
-                                        strategy.name, date
-                                    );
-
-                                    // Because TradingService uses a non-blocking read in another thread, we need to sleep for a bit
-                                    // This is non-ideal but not too big a deal.
-                                    std::thread::sleep(std::time::Duration::from_millis(10));
-
-                                    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
-                                    trading_service
-                                        .shutdown()
-                                        .expect("Unexpected error shutting down trading_service");
-                                }
-                                Err(e) => {
-                                    info!("Error starting TradingService {}: {}", strategy.name, e)
-                                }
-                            }
-                        });
-                    }
+                let quotes = match self.market_data_manager.quotes_for_date(date) {
+                    Ok(quotes) => quotes,
                     Err(_) => {
                         info!("Skipping {} - no data (weekend or holiday)", date);
                         continue;
                     }
+                };
+
+                self.orders
+                    .fill_pending(date, self.market_data_manager.opens_for_date(date)?);
+                let capital = allocation::allocate(
+                    &self.allocation,
+                    &self.strategies,
+                    self.allocation.equity
+                        + curve.last().map(|p: &EquityPoint| p.equity).unwrap_or(0.0),
+                    &self.historical_data.fetch_as_of(date),
+                );
+
+                let symbol_data: Vec<HashMap<String, SymbolData>> = strategies
+                    .iter()
+                    .map(|(strategy, config)| {
+                        load_history(
+                            date,
+                            strategy.symbols(),
+                            with_prices(self.historical_data.clone(), config.prices),
+                        )
+                    })
+                    .collect();
+
+                for quote in quotes {
+                    for ((strategy, config), symbol_data) in strategies.iter().zip(&symbol_data) {
+                        if strategy.symbols().contains(&quote.symbol) {
+                            handle_quote(
+                                date,
+                                symbol_data,
+                                quote,
+                                capital
+                                    .get(&config.name)
+                                    .and_then(|capital| capital.get(&quote.symbol))
+                                    .copied()
+                                    .unwrap_or(0),
+                                &config.sizing,
+                                strategy,
+                                self.orders.clone(),
+                            );
+                        }
+                    }
+                    marks.insert(quote.symbol.clone(), (quote.bid + quote.ask) / 2.0);
                 }
+
+                curve.push(EquityPoint {
+                    date,
+                    equity: equity(self.orders.clone(), date, &marks),
+                });
             }
 
-            Ok(())
+            Ok(curve)
         }
     }
+
+    pub fn equity(
+        orders: Arc<impl BacktestOrderService>,
+        date: NaiveDate,
+        marks: &HashMap<String, f64>,
+    ) -> f64 {
+        let realized = orders
+            .realized_pnl()
+            .iter()
+            .filter(|pnl| pnl.date <= date)
+            .map(|pnl| pnl.pnl)
+            .sum::<f64>();
+        let unrealized = orders
+            .open_positions()
+            .iter()
+            .map(|p| match marks.get(&p.symbol) {
+                Some(px) => p.quantity as f64 * px - p.cost_basis,
+                None => 0.0,
+            })
+            .sum::<f64>();
+        realized + unrealized
+    }
 }
+
+#[cfg(test)]
+#[path = "./tests/backtest_service_test.rs"]
+mod backtest_service_test;
@@ -1,33 +1,75 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use app_config::app_config::AppConfig;
+use app_config::app_config::{AppConfig, HistoricalSource};
+use backtest_historical_data::BacktestHistoricalDataManager;
+use backtest_market_data_manager::QuoteSource;
 use backtest_orders::BacktestOrderService;
 use backtest_service::BacktestService;
 use chrono::Local;
 use core::util::time;
+use domain::domain::{Interval, PriceAdjustment};
 use itertools::Itertools;
 use log::*;
-use log4rs;
-use services::historical_data;
-use std::env;
+use services::{
+    corporate_actions, historical_data,
+    history_cache::{self, HistoryCache},
+    market_data_recorder::{self, Record},
+};
+use std::{collections::HashMap, env, sync::Arc};
+use walk_forward::WalkForwardService;
 
 mod backtest_historical_data;
 mod backtest_market_data_manager;
 mod backtest_orders;
 mod backtest_service;
+mod metrics;
+mod walk_forward;
+
+#[cfg(test)]
+#[path = "./tests/mock_historical_data_service.rs"]
+mod mock_historical_data_service;
 
 fn main() {
-    let access_token = env::var("ACCESS_TOKEN").expect("ACCESS_TOKEN not found");
+    // `cargo run --bin backtest -- walk-forward` runs walk-forward analysis instead of a single backtest
+    let walk_forward = env::args().nth(1).as_deref() == Some("walk-forward");
 
     log4rs::init_file("config/backtest-log4rs.yaml", Default::default()).unwrap();
     let config = AppConfig::new().expect("Could not load config");
     info!("Config:\n{:?}", config);
 
+    // `cargo run --bin backtest -- clear-cache [SYMBOL...]` empties the history cache, entirely or for
+    // the given symbols
+    if env::args().nth(1).as_deref() == Some("clear-cache") {
+        let cache = history_cache::new(&config.historical_data.cache, Local::now().date_naive());
+        let symbols: Vec<String> = env::args().skip(2).collect();
+        if symbols.is_empty() {
+            cache.invalidate_all().expect("Failed to clear cache");
+        } else {
+            symbols.iter().for_each(|symbol| {
+                for interval in [Interval::Daily, config.bar_interval] {
+                    cache
+                        .invalidate(symbol, interval)
+                        .expect("Failed to clear cache");
+                }
+            });
+        }
+        return;
+    }
+
+    // Daily bars from files or anything served from the cache need no token, so such backtests can
+    // run offline
+    let access_token = match (config.historical_data.source, config.bar_interval) {
+        _ if config.historical_data.cache.offline => env::var("ACCESS_TOKEN").unwrap_or_default(),
+        (HistoricalSource::File, Interval::Daily) => env::var("ACCESS_TOKEN").unwrap_or_default(),
+        _ => env::var("ACCESS_TOKEN").expect("ACCESS_TOKEN not found"),
+    };
+
     let end = Local::now().naive_local().date();
     let symbols = config.all_symbols();
 
     let historical_data = historical_data::new(
+        &config.historical_data,
         access_token.clone(),
         symbols.clone(),
         config.backtest_range + config.hist_data_range,
@@ -38,18 +80,108 @@ fn main() {
         end,
         config.backtest_range,
         config.hist_data_range,
-        historical_data,
+        historical_data.clone(),
+        config.backtest_prices,
     );
 
+    // Quotes and bars are adjusted with the same factors as the daily history
+    let adjustments = match config.backtest_prices {
+        PriceAdjustment::Raw => HashMap::new(),
+        PriceAdjustment::Adjusted => {
+            backtest_historical_data::adjustments(end, historical_data.as_ref())
+        }
+    };
+    let adjustments_for = |symbol: &str| {
+        adjustments
+            .get(symbol)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    };
+
+    let bars = match (&config.market_data.replay, config.bar_interval) {
+        (Some(replay), _) => QuoteSource::Recorded(
+            market_data_recorder::read(&replay.path)
+                .expect("Failed to read recording")
+                .into_iter()
+                .map(|record| Record {
+                    quote: record.quote.map(|quote| {
+                        let date = record.received.date_naive();
+                        corporate_actions::adjust_quote(
+                            &quote,
+                            date,
+                            adjustments_for(&quote.symbol),
+                        )
+                    }),
+                    ..record
+                })
+                .collect(),
+        ),
+        (None, Interval::Daily) => QuoteSource::Bars(backtest_market_data_manager::daily_bars(
+            &backtest_historical_data.all(),
+        )),
+        (None, interval) => QuoteSource::Bars(Arc::new(
+            historical_data::fetch_bars(
+                &config.historical_data,
+                &access_token,
+                symbols.clone(),
+                interval,
+                end - chrono::Duration::days(config.backtest_range),
+                end,
+            )
+            .report()
+            .iter()
+            .map(|(symbol, bars)| {
+                let bars = corporate_actions::adjust_bars(bars, adjustments_for(symbol));
+                (symbol.clone(), bars)
+            })
+            .collect(),
+        )),
+    };
+
     let backtest_market_data_manager = backtest_market_data_manager::new(
         access_token.clone(),
         symbols.clone(),
         config.backtest_range,
         end,
-        backtest_historical_data.clone(),
+        config.quote_synthesis.clone(),
+        config.bar_interval,
+        bars,
     );
 
-    let orders = backtest_orders::new();
+    if walk_forward {
+        let walk_forward_service = walk_forward::new(
+            end,
+            config.backtest_range,
+            config.execution_delay,
+            config.fills,
+            config.allocation,
+            config
+                .walk_forward
+                .clone()
+                .expect("No [walk_forward] section in config"),
+            backtest_historical_data.clone(),
+            backtest_market_data_manager,
+            config.strategies.clone(),
+        );
+
+        return time("walk_forward_service.run()", || {
+            match walk_forward_service.run() {
+                Ok(report) => info!(
+                    "\nWalk-forward analysis completed successfully\n\nWindows:\n{:?}\n\nOut-of-sample equity:\n{:?}\n\n\
+                    Metric: {:?}; in-sample: {}; out-of-sample: {}; efficiency: {:?}\n",
+                    report.windows.iter().format("\n"),
+                    report.equity.iter().format("\n"),
+                    report.metric,
+                    report.in_sample_score,
+                    report.out_of_sample_score,
+                    report.efficiency()
+                ),
+                Err(e) => info!("Walk-forward analysis failed: {}", e),
+            }
+        });
+    }
+
+    let orders = backtest_orders::new(config.execution_delay, config.fills);
     let backtest_service = backtest_service::new(
         end,
         config.backtest_range,
@@ -57,16 +189,21 @@ fn main() {
         backtest_market_data_manager,
         orders.clone(),
         config.strategies.clone(),
+        config.allocation,
     );
 
     time("backtest_service.run()", || match backtest_service.run() {
-        Ok(_) => {
+        Ok(equity) => {
             let pnl = orders.realized_pnl();
             info!(
-                "\nBacktest completed successfully\n\nOpen positions:\n{:?}\n\nRealized P&L:\n{:?}\n\nTotal P&L: {}\n",
+                "\nBacktest completed successfully\n\nOpen positions:\n{:?}\n\nRealized P&L:\n{:?}\n\nTotal P&L: {}\n\n\
+                Total P&L incl. open positions: {}; Sharpe: {}; max drawdown: {}\n",
                 orders.open_positions().iter().format("\n"),
                 pnl.iter().format("\n"),
-                pnl.iter().map(|pnl| pnl.pnl).sum::<f64>());
+                pnl.iter().map(|pnl| pnl.pnl).sum::<f64>(),
+                metrics::total_pnl(&equity),
+                metrics::sharpe(&equity),
+                metrics::max_drawdown(&equity));
         }
         Err(e) => info!("Backtest failed: {}", e),
     })
@@ -0,0 +1,93 @@
+use app_config::app_config::WalkForwardMetric;
+use chrono::NaiveDate;
+
+// Trading days per year, for annualizing daily figures
+const TRADING_DAYS: f64 = 252.0;
+
+// Realized plus unrealized P&L at the close of each trading day
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquityPoint {
+    pub date: NaiveDate,
+    pub equity: f64,
+}
+
+pub type EquityCurve = Vec<EquityPoint>;
+
+// The most a curve scores for its return over drawdown, which is also what a gain with no drawdown
+// scores, so that every score is a ratio
+pub const MAX_RETURN_OVER_DRAWDOWN: f64 = 100.0;
+
+// Annualized so that in-sample and out-of-sample windows of different lengths are comparable.
+// Higher is always better.
+pub fn evaluate(metric: WalkForwardMetric, curve: &[EquityPoint]) -> f64 {
+    match metric {
+        WalkForwardMetric::PnL => annualized_pnl(curve),
+        WalkForwardMetric::Sharpe => sharpe(curve),
+        WalkForwardMetric::ReturnOverDrawdown => return_over_drawdown(curve),
+    }
+}
+
+// Annualized P&L over the largest drawdown, capped. A window that never trades scores nothing.
+pub fn return_over_drawdown(curve: &[EquityPoint]) -> f64 {
+    let pnl = annualized_pnl(curve);
+    match max_drawdown(curve) {
+        drawdown if drawdown > 0.0 => (pnl / drawdown).min(MAX_RETURN_OVER_DRAWDOWN),
+        _ if pnl > 0.0 => MAX_RETURN_OVER_DRAWDOWN,
+        _ => 0.0,
+    }
+}
+
+pub fn total_pnl(curve: &[EquityPoint]) -> f64 {
+    curve.last().map(|p| p.equity).unwrap_or(0.0)
+}
+
+pub fn annualized_pnl(curve: &[EquityPoint]) -> f64 {
+    match curve.len() {
+        0 => 0.0,
+        n => total_pnl(curve) / n as f64 * TRADING_DAYS,
+    }
+}
+
+// Annualized Sharpe ratio of daily P&L changes, with a zero risk-free rate
+pub fn sharpe(curve: &[EquityPoint]) -> f64 {
+    let changes = daily_changes(curve);
+    if changes.is_empty() {
+        return 0.0;
+    }
+
+    let len = changes.len() as f64;
+    let mean = changes.iter().sum::<f64>() / len;
+    let std_dev = (changes.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / len).sqrt();
+    if std_dev > 0.0 {
+        mean / std_dev * TRADING_DAYS.sqrt()
+    } else {
+        0.0
+    }
+}
+
+// Largest peak-to-trough decline in equity, as a positive amount
+pub fn max_drawdown(curve: &[EquityPoint]) -> f64 {
+    let mut peak = 0.0_f64;
+    let mut drawdown = 0.0_f64;
+    for point in curve {
+        peak = peak.max(point.equity);
+        drawdown = drawdown.max(peak - point.equity);
+    }
+    drawdown
+}
+
+fn daily_changes(curve: &[EquityPoint]) -> Vec<f64> {
+    let mut previous = 0.0;
+    curve
+        .iter()
+        .map(|point| {
+            let change = point.equity - previous;
+            previous = point.equity;
+            change
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "./tests/metrics_test.rs"]
+mod metrics_test;
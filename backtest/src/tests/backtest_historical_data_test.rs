@@ -1,6 +1,6 @@
 use super::*;
+use crate::mock_historical_data_service::MockHistoricalDataService;
 use chrono::NaiveDate;
-use mock_historical_data_service::MockHistoricalDataService;
 
 #[test]
 fn test_windowing() {
@@ -14,15 +14,16 @@ fn test_windowing() {
         backtest_range,
         hist_data_range,
         historical_data_service,
+        PriceAdjustment::Raw,
     ));
 
     // First day of backtest range
     let start =
         NaiveDate::from_ymd_opt(2024, 6, (30 - backtest_range - hist_data_range) as u32).unwrap();
-    let end = start + chrono::Duration::days(4);
-    let map = service.fetch(end);
+    let as_of = start + chrono::Duration::days(4);
+    let map = service.fetch_as_of(as_of);
     let data = map.get("SPY").expect("No data for SPY");
-    assert_eq!(data.len(), (hist_data_range + 1) as usize);
+    assert_eq!(data.len(), hist_data_range as usize);
     assert_eq!(
         data.into_iter().map(|d| d.date).collect::<Vec<_>>(),
         vec![
@@ -30,16 +31,15 @@ fn test_windowing() {
             NaiveDate::from_ymd_opt(2024, 6, 7).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 8).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 9).unwrap(),
-            NaiveDate::from_ymd_opt(2024, 6, 10).unwrap(),
         ]
     );
 
     // Middle
     let start = NaiveDate::from_ymd_opt(2024, 6, 16).unwrap();
-    let end = start + chrono::Duration::days(4);
-    let map = service.fetch(end);
+    let as_of = start + chrono::Duration::days(4);
+    let map = service.fetch_as_of(as_of);
     let data = map.get("SPY").expect("No data for SPY");
-    assert_eq!(data.len(), (hist_data_range + 1) as usize);
+    assert_eq!(data.len(), hist_data_range as usize);
     assert_eq!(
         data.into_iter().map(|d| d.date).collect::<Vec<_>>(),
         vec![
@@ -47,16 +47,15 @@ fn test_windowing() {
             NaiveDate::from_ymd_opt(2024, 6, 17).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 18).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 19).unwrap(),
-            NaiveDate::from_ymd_opt(2024, 6, 20).unwrap(),
         ]
     );
 
     // Last
     let start = NaiveDate::from_ymd_opt(2024, 6, 26).unwrap();
-    let end = start + chrono::Duration::days(4);
-    let map = service.fetch(end);
+    let as_of = start + chrono::Duration::days(4);
+    let map = service.fetch_as_of(as_of);
     let data = map.get("SPY").expect("No data for SPY");
-    assert_eq!(data.len(), (hist_data_range + 1) as usize);
+    assert_eq!(data.len(), hist_data_range as usize);
     assert_eq!(
         data.into_iter().map(|d| d.date).collect::<Vec<_>>(),
         vec![
@@ -64,7 +63,128 @@ fn test_windowing() {
             NaiveDate::from_ymd_opt(2024, 6, 27).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 28).unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 29).unwrap(),
-            NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(),
         ]
     );
 }
+
+#[test]
+fn test_no_look_ahead() {
+    let end = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+    let historical_data_service = Arc::new(MockHistoricalDataService { end });
+    let hist_data_range = 4;
+    let service = new(
+        end,
+        20,
+        hist_data_range,
+        historical_data_service,
+        PriceAdjustment::Raw,
+    );
+
+    let mut as_of = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+    while as_of <= end {
+        let map = service.fetch_as_of(as_of);
+        let data = map.get("SPY").expect("No data for SPY");
+        assert!(data.iter().all(|d| d.date < as_of));
+        as_of += chrono::Duration::days(1);
+    }
+
+    // Closes are the day of the month. Trading on the 20th, the statistics must come from the 16th
+    // through the 19th only - including the 20th's own close would give a mean of 18.
+    let as_of = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+    let symbol_data = services::trading::implementation::load_history(
+        as_of,
+        &["SPY".to_string()],
+        service.clone(),
+    );
+    let spy = symbol_data.get("SPY").unwrap();
+    assert_eq!(spy.mean, 17.5);
+    assert_eq!(spy.history.last().unwrap().date, as_of.pred_opt().unwrap());
+}
+
+// 200 until a 2-for-1 split on the 20th, 100 from then on
+struct SplitHistoricalDataService {}
+
+impl HistoricalDataService for SplitHistoricalDataService {
+    fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+        let days = (1..=30)
+            .map(|d| {
+                let close = if d < 20 { 200.0 } else { 100.0 };
+                Day {
+                    symbol: Some("SPY".to_string()),
+                    date: NaiveDate::from_ymd_opt(2024, 6, d).unwrap(),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 100,
+                }
+            })
+            .filter(|day| day.date < as_of)
+            .collect();
+        Arc::new(HashMap::from([("SPY".to_string(), days)]))
+    }
+
+    fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+        Arc::new(HashMap::from([(
+            "SPY".to_string(),
+            vec![CorporateAction {
+                symbol: "SPY".to_string(),
+                ex_date: NaiveDate::from_ymd_opt(2024, 6, 20).unwrap(),
+                action: domain::domain::Action::Split(2.0),
+            }],
+        )]))
+    }
+}
+
+#[test]
+fn test_adjusted_prices() {
+    let end = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+    let underlying = Arc::new(SplitHistoricalDataService {});
+    let raw = new(end, 20, 4, underlying.clone(), PriceAdjustment::Raw);
+    let adjusted = new(end, 20, 4, underlying, PriceAdjustment::Adjusted);
+
+    let all = adjusted.all();
+    assert!(all.get("SPY").unwrap().iter().all(|d| d.close == 100.0));
+    assert!(raw
+        .all()
+        .get("SPY")
+        .unwrap()
+        .iter()
+        .any(|d| d.close == 200.0));
+
+    // Windows before the ex-date are adjusted too, to match the adjusted quotes being replayed
+    let as_of = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+    let window = adjusted.fetch_as_of(as_of);
+    assert!(window.get("SPY").unwrap().iter().all(|d| d.close == 100.0));
+    assert!(window.get("SPY").unwrap().iter().all(|d| d.volume == 200));
+
+    // Having been applied, the actions aren't passed on
+    assert!(adjusted.corporate_actions().is_empty());
+    assert_eq!(raw.corporate_actions().len(), 1);
+}
+
+#[test]
+fn test_adjustments_only_for_actions_by_the_end() {
+    let underlying = SplitHistoricalDataService {};
+    let before = adjustments(NaiveDate::from_ymd_opt(2024, 6, 19).unwrap(), &underlying);
+    assert!(before.get("SPY").unwrap().is_empty());
+
+    // Shared by bars and replayed quotes, which are adjusted as the daily history is
+    let after = adjustments(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(), &underlying);
+    let quote = corporate_actions::adjust_quote(
+        &domain::domain::Quote {
+            symbol: "SPY".to_string(),
+            bid: 200.0,
+            ask: 200.0,
+            biddate: chrono::Local::now(),
+            askdate: chrono::Local::now(),
+            bidsz: 100,
+            asksz: 100,
+            bidexch: String::new(),
+            askexch: String::new(),
+        },
+        NaiveDate::from_ymd_opt(2024, 6, 19).unwrap(),
+        after.get("SPY").unwrap(),
+    );
+    assert_eq!((quote.bid, quote.bidsz), (100.0, 200));
+}
@@ -1,6 +1,7 @@
 use super::*;
+use crate::backtest_historical_data::BacktestHistoricalDataManager;
+use crate::mock_historical_data_service::MockHistoricalDataService;
 use chrono::{Datelike, Duration};
-use mock_historical_data_service::MockHistoricalDataService;
 
 #[test]
 fn test_backtest_market_data_manager() {
@@ -13,7 +14,9 @@ fn test_backtest_market_data_manager() {
         symbols,
         backtest_range,
         end,
-        historical_data_service,
+        QuoteSynthesis::default(),
+        Interval::Daily,
+        QuoteSource::Bars(daily_bars(&historical_data_service.all())),
     ));
 
     let start_date = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
@@ -22,13 +25,244 @@ fn test_backtest_market_data_manager() {
     while date <= end_date {
         println!("{:?}", date);
         date += Duration::days(1);
-        let market_data = service
-            .service_for_date(date)
-            .expect(format!("Could not get market data for {}", date).as_str());
-        let receiver = market_data.subscribe().expect("Failed to subscribe");
-        let quote = receiver.recv().expect("Failed to receive quote");
+        let quotes = service
+            .quotes_for_date(date)
+            .unwrap_or_else(|_| panic!("Could not get market data for {}", date));
+        assert_eq!(quotes.len(), 1);
+        let quote = &quotes[0];
         assert_eq!(quote.symbol, "SPY");
         assert_eq!(quote.bid, date.day() as f64);
         assert_eq!(quote.ask, date.day() as f64);
     }
 }
+
+fn day(open: f64, high: f64, low: f64, close: f64) -> Bar {
+    Bar::from(Day {
+        symbol: Some("SPY".to_string()),
+        date: NaiveDate::from_ymd_opt(2024, 6, 3).unwrap(),
+        open,
+        high,
+        low,
+        close,
+        volume: 10000,
+    })
+}
+
+fn prices(quotes: &[Quote]) -> Vec<f64> {
+    quotes.iter().map(|q| (q.bid + q.ask) / 2.0).collect()
+}
+
+#[test]
+fn test_synthesize() {
+    let ohlc = |path| QuoteSynthesis {
+        mode: SynthesisMode::Ohlc,
+        path,
+        ..QuoteSynthesis::default()
+    };
+    let day = day(100.0, 104.0, 90.0, 95.0);
+
+    let quotes = synthesize(&day, Interval::Daily, &QuoteSynthesis::default());
+    assert_eq!(prices(&quotes), vec![95.0]);
+    assert_eq!(quotes[0].bid, quotes[0].ask);
+
+    let quotes = synthesize(&day, Interval::Daily, &ohlc(IntradayPath::HighFirst));
+    assert_eq!(prices(&quotes), vec![100.0, 104.0, 90.0, 95.0]);
+    assert!(quotes.windows(2).all(|w| w[0].biddate < w[1].biddate));
+    assert_eq!(
+        quotes[3].biddate.time(),
+        NaiveTime::from_hms_opt(16, 0, 0).unwrap()
+    );
+
+    assert_eq!(
+        prices(&synthesize(
+            &day,
+            Interval::Daily,
+            &ohlc(IntradayPath::LowFirst)
+        )),
+        vec![100.0, 90.0, 104.0, 95.0]
+    );
+    // The high is nearer the open
+    assert_eq!(
+        prices(&synthesize(
+            &day,
+            Interval::Daily,
+            &ohlc(IntradayPath::NearestFirst)
+        )),
+        vec![100.0, 104.0, 90.0, 95.0]
+    );
+}
+
+#[test]
+fn test_synthesize_random_path() {
+    let random = |seed| QuoteSynthesis {
+        mode: SynthesisMode::Ohlc,
+        path: IntradayPath::Random,
+        seed,
+        ..QuoteSynthesis::default()
+    };
+
+    // Repeatable for a given seed, and each order turns up across seeds
+    let day = day(100.0, 104.0, 90.0, 95.0);
+    let paths: Vec<Vec<f64>> = (0..20)
+        .map(|seed| prices(&synthesize(&day, Interval::Daily, &random(seed))))
+        .collect();
+    (0..20).for_each(|seed| {
+        assert_eq!(
+            prices(&synthesize(&day, Interval::Daily, &random(seed))),
+            paths[seed as usize]
+        )
+    });
+    assert!(paths.contains(&vec![100.0, 104.0, 90.0, 95.0]));
+    assert!(paths.contains(&vec![100.0, 90.0, 104.0, 95.0]));
+}
+
+#[test]
+fn test_synthesize_spread() {
+    let synthesis = QuoteSynthesis {
+        spread_bps: 20.0,
+        ..QuoteSynthesis::default()
+    };
+    let quotes = synthesize(&day(100.0, 104.0, 90.0, 100.0), Interval::Daily, &synthesis);
+    assert!((quotes[0].bid - 99.9).abs() < 1e-9);
+    assert!((quotes[0].ask - 100.1).abs() < 1e-9);
+}
+
+#[test]
+fn test_intraday_bars() {
+    let session = NaiveDate::from_ymd_opt(2024, 6, 3)
+        .unwrap()
+        .and_time(NaiveTime::from_hms_opt(9, 30, 0).unwrap())
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap();
+    let bar = |symbol: &str, i: i64, px: f64| Bar {
+        symbol: symbol.to_string(),
+        timestamp: session + Interval::FiveMinute.duration() * i as i32,
+        open: px,
+        high: px + 1.0,
+        low: px - 1.0,
+        close: px + 0.5,
+        volume: 100,
+    };
+    // Deliberately out of order
+    let bars = HashMap::from([
+        (
+            "MSFT".to_string(),
+            vec![bar("MSFT", 1, 400.0), bar("MSFT", 0, 401.0)],
+        ),
+        (
+            "AAPL".to_string(),
+            vec![bar("AAPL", 0, 200.0), bar("AAPL", 1, 201.0)],
+        ),
+    ]);
+
+    let service = new(
+        "".to_string(),
+        vec![],
+        1,
+        session.date_naive(),
+        QuoteSynthesis::default(),
+        Interval::FiveMinute,
+        QuoteSource::Bars(Arc::new(bars)),
+    );
+    let quotes = service.quotes_for_date(session.date_naive()).unwrap();
+    assert_eq!(
+        quotes
+            .iter()
+            .map(|q| (q.symbol.as_str(), q.bid))
+            .collect::<Vec<_>>(),
+        vec![
+            ("AAPL", 200.5),
+            ("MSFT", 401.5),
+            ("AAPL", 201.5),
+            ("MSFT", 400.5)
+        ]
+    );
+    // Each bar's close comes at the end of its span
+    assert_eq!(quotes[0].biddate, session + Interval::FiveMinute.duration());
+
+    let opens = service.opens_for_date(session.date_naive()).unwrap();
+    assert_eq!(opens["AAPL"], 200.0);
+    assert_eq!(opens["MSFT"], 401.0);
+
+    let ohlc = QuoteSynthesis {
+        mode: SynthesisMode::Ohlc,
+        path: IntradayPath::HighFirst,
+        ..QuoteSynthesis::default()
+    };
+    let quotes = synthesize(&bar("AAPL", 0, 200.0), Interval::FiveMinute, &ohlc);
+    assert_eq!(
+        quotes
+            .iter()
+            .map(|q| (q.biddate - session).num_seconds())
+            .collect::<Vec<_>>(),
+        vec![0, 100, 200, 300]
+    );
+}
+
+#[test]
+fn test_recorded_quotes() {
+    let session = NaiveDate::from_ymd_opt(2024, 6, 3)
+        .unwrap()
+        .and_time(NaiveTime::from_hms_opt(9, 30, 0).unwrap())
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap();
+    let quote = |symbol: &str, bid: f64| Quote {
+        symbol: symbol.to_string(),
+        bid,
+        ask: bid + 1.0,
+        // Stale, from the session before
+        biddate: session - Duration::days(1),
+        askdate: session - Duration::days(1),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    };
+    let record = |minute: i64, quote: Option<Quote>| Record {
+        received: session + Duration::minutes(minute),
+        raw: "{}".to_string(),
+        quote,
+    };
+    let records = vec![
+        record(0, Some(quote("MSFT", 400.0))),
+        record(1, None),
+        record(2, Some(quote("AAPL", 200.0))),
+        record(3, Some(quote("TSLA", 100.0))),
+        record(4, Some(quote("MSFT", 402.0))),
+        record(24 * 60, Some(quote("AAPL", 210.0))),
+    ];
+
+    let service = new(
+        "".to_string(),
+        vec!["AAPL".to_string(), "MSFT".to_string()],
+        1,
+        session.date_naive(),
+        QuoteSynthesis::default(),
+        Interval::Daily,
+        QuoteSource::Recorded(records),
+    );
+    let quotes = service.quotes_for_date(session.date_naive()).unwrap();
+    assert_eq!(
+        quotes
+            .iter()
+            .map(|q| (q.symbol.as_str(), q.bid))
+            .collect::<Vec<_>>(),
+        vec![("MSFT", 400.0), ("AAPL", 200.0), ("MSFT", 402.0)]
+    );
+    let opens = service.opens_for_date(session.date_naive()).unwrap();
+    assert_eq!(opens["MSFT"], 400.5);
+    assert_eq!(opens["AAPL"], 200.5);
+
+    let next = service
+        .quotes_for_date(session.date_naive().succ_opt().unwrap())
+        .unwrap();
+    assert_eq!(next.len(), 1);
+}
+
+#[test]
+fn test_path_hash_is_fixed() {
+    // Pinned, so a seed's paths can't change with the toolchain
+    assert_eq!(path_hash(7, "SPY", 1717372800), 737924065933621486);
+}
@@ -0,0 +1,92 @@
+use super::*;
+use app_config::app_config::CommissionConfig;
+
+fn order(date: NaiveDate, side: Side, quantity: i64, px: f64) -> Order {
+    Order {
+        id: None,
+        date,
+        symbol: "SPY".to_string(),
+        side,
+        quantity,
+        px: Some(px),
+        account: String::new(),
+    }
+}
+
+#[test]
+fn test_immediate_fill() {
+    let orders = new(ExecutionDelay::None, FillConfig::default());
+    let date = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+    orders
+        .create_order(order(date, Side::Buy, 10, 100.0), "test".into())
+        .unwrap();
+    let position = orders.get_position("SPY").expect("Expected a position");
+    assert_eq!(position.quantity, 10);
+    assert_eq!(position.cost_basis, 1000.0);
+}
+
+#[test]
+fn test_next_open_fill() {
+    let orders = new(ExecutionDelay::NextOpen, FillConfig::default());
+    let monday = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+    let tuesday = monday + chrono::Duration::days(1);
+    let wednesday = tuesday + chrono::Duration::days(1);
+    let opens = HashMap::from([("SPY".to_string(), 95.0)]);
+
+    orders
+        .create_order(order(monday, Side::Buy, 10, 100.0), "test".into())
+        .unwrap();
+    assert!(orders.get_position("SPY").is_none());
+    assert!(orders
+        .create_order(order(monday, Side::Buy, 10, 100.0), "test".into())
+        .is_err());
+
+    // Not until the next day's open
+    orders.fill_pending(monday, &opens);
+    assert!(orders.get_position("SPY").is_none());
+    orders.fill_pending(tuesday, &HashMap::new());
+    assert!(orders.get_position("SPY").is_none());
+
+    orders.fill_pending(wednesday, &opens);
+    let position = orders.get_position("SPY").expect("Expected a position");
+    assert_eq!(position.quantity, 10);
+    assert_eq!(position.cost_basis, 950.0);
+
+    orders
+        .create_order(order(wednesday, Side::Sell, 10, 120.0), "test".into())
+        .unwrap();
+    orders.fill_pending(
+        wednesday + chrono::Duration::days(1),
+        &HashMap::from([("SPY".to_string(), 110.0)]),
+    );
+    let pnl = orders.realized_pnl();
+    assert_eq!(pnl.len(), 1);
+    assert_eq!(pnl[0].pnl, 150.0);
+    assert!(orders.open_positions().is_empty());
+}
+
+#[test]
+fn test_fills_with_slippage_and_commission() {
+    let fills = FillConfig {
+        slippage_bps: 100.0,
+        commission: CommissionConfig {
+            per_order: 1.0,
+            ..CommissionConfig::default()
+        },
+    };
+    let orders = new(ExecutionDelay::None, fills);
+    let date = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+
+    let filled = orders
+        .create_order(order(date, Side::Buy, 10, 100.0), "test".into())
+        .unwrap();
+    assert_eq!(filled.px, Some(101.0));
+    // The commission is part of what the position cost
+    assert_eq!(orders.get_position("SPY").unwrap().cost_basis, 1011.0);
+
+    orders
+        .create_order(order(date, Side::Sell, 10, 110.0), "test".into())
+        .unwrap();
+    // Sold at 108.9 for 1089, less 1011 and the sale's commission
+    assert!((orders.realized_pnl()[0].pnl - 77.0).abs() < 1e-9);
+}
@@ -0,0 +1,130 @@
+use super::*;
+use crate::backtest_historical_data::BacktestHistoricalDataManager;
+use crate::{backtest_historical_data, backtest_market_data_manager, backtest_orders};
+use app_config::app_config::{Allocation, ExecutionDelay, FillConfig, QuoteSynthesis, Sizing};
+use domain::domain::{Day, Interval, PriceAdjustment};
+use std::collections::HashMap;
+
+// Flat prices around 100 with a dip to 80 and a spike to 120 every 12 days
+struct OscillatingHistoricalDataService {
+    start: NaiveDate,
+    days: i64,
+}
+
+impl HistoricalDataService for OscillatingHistoricalDataService {
+    fn fetch_as_of(&self, _: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+        let days = (0..self.days)
+            .map(|i| {
+                let close = match i % 12 {
+                    4 => 80.0,
+                    10 => 120.0,
+                    n => 99.0 + (n % 3) as f64,
+                };
+                Day {
+                    symbol: Some("SPY".to_string()),
+                    date: self.start + chrono::Duration::days(i),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 10000,
+                }
+            })
+            .collect();
+        Arc::new(HashMap::from([("SPY".to_string(), days)]))
+    }
+}
+
+fn run_backtest(
+    execution_delay: ExecutionDelay,
+) -> (EquityCurve, Vec<domain::domain::RealizedPnL>) {
+    let backtest_range = 90;
+    let hist_data_range = 10;
+    let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let end = start + chrono::Duration::days(backtest_range + hist_data_range);
+    let underlying = Arc::new(OscillatingHistoricalDataService {
+        start,
+        days: backtest_range + hist_data_range + 1,
+    });
+    let historical_data = backtest_historical_data::new(
+        end,
+        backtest_range,
+        hist_data_range,
+        underlying,
+        PriceAdjustment::Raw,
+    );
+    let market_data_manager = backtest_market_data_manager::new(
+        "".to_string(),
+        vec!["SPY".to_string()],
+        backtest_range,
+        end,
+        QuoteSynthesis::default(),
+        Interval::Daily,
+        backtest_market_data_manager::QuoteSource::Bars(backtest_market_data_manager::daily_bars(
+            &historical_data.all(),
+        )),
+    );
+    let orders = backtest_orders::new(execution_delay, FillConfig::default());
+    let strategies = vec![Strategy {
+        name: "mean-reversion".to_string(),
+        symbols: vec!["SPY".to_string()],
+        capital: HashMap::from([("SPY".to_string(), 10000)]),
+        budget: 0.0,
+        budget_percent: 0.0,
+        params: HashMap::new(),
+        prices: PriceAdjustment::Raw,
+        account: None,
+        sizing: Sizing::default(),
+    }];
+
+    let curve = new(
+        end,
+        backtest_range,
+        historical_data,
+        market_data_manager,
+        orders.clone(),
+        strategies,
+        Allocation::default(),
+    )
+    .run()
+    .expect("Backtest failed");
+    (curve, orders.realized_pnl())
+}
+
+#[test]
+fn test_run_is_deterministic() {
+    let (curve, pnl) = run_backtest(ExecutionDelay::None);
+    assert_eq!(curve.len(), 91);
+    assert!(!pnl.is_empty(), "Expected round trips");
+    // Buying the dips at 80 and selling the spikes at 120 is profitable
+    assert!(pnl.iter().all(|p| p.pnl > 0.0));
+
+    for _ in 0..5 {
+        let (other_curve, other_pnl) = run_backtest(ExecutionDelay::None);
+        assert_eq!(other_curve, curve);
+        assert_eq!(
+            other_pnl
+                .iter()
+                .map(|p| (p.date, p.pnl))
+                .collect::<Vec<_>>(),
+            pnl.iter().map(|p| (p.date, p.pnl)).collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_next_open_execution() {
+    let (_, same_bar) = run_backtest(ExecutionDelay::None);
+    let (curve, next_open) = run_backtest(ExecutionDelay::NextOpen);
+    assert_eq!(curve.len(), 91);
+    assert_eq!(next_open.len(), same_bar.len());
+    // The dips and spikes last a single day, so waiting for the next open gives up the edge
+    assert!(next_open.iter().all(|p| p.pnl.abs() < 10.0));
+    // Each sell fills the trading day after the signal
+    next_open
+        .iter()
+        .zip(&same_bar)
+        .for_each(|(delayed, immediate)| {
+            assert_eq!(delayed.date, immediate.date + chrono::Duration::days(1))
+        });
+}
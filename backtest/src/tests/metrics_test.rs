@@ -0,0 +1,65 @@
+use super::*;
+
+fn curve(equity: &[f64]) -> EquityCurve {
+    let start = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+    equity
+        .iter()
+        .enumerate()
+        .map(|(i, e)| EquityPoint {
+            date: start + chrono::Duration::days(i as i64),
+            equity: *e,
+        })
+        .collect()
+}
+
+#[test]
+fn test_max_drawdown() {
+    assert_eq!(
+        max_drawdown(&curve(&[10.0, 30.0, 5.0, 20.0, -10.0, 50.0])),
+        40.0
+    );
+    assert_eq!(max_drawdown(&curve(&[1.0, 2.0, 3.0])), 0.0);
+    // Drawdown is measured from the starting equity of zero
+    assert_eq!(max_drawdown(&curve(&[-5.0, -2.0])), 5.0);
+}
+
+#[test]
+fn test_sharpe() {
+    // Constant gains have no variance
+    assert_eq!(sharpe(&curve(&[1.0, 2.0, 3.0])), 0.0);
+    assert_eq!(sharpe(&curve(&[])), 0.0);
+
+    // Daily changes of 1, 3: mean 2, std dev 1
+    let s = sharpe(&curve(&[1.0, 4.0]));
+    assert!((s - 2.0 * 252.0_f64.sqrt()).abs() < 1e-9);
+}
+
+#[test]
+fn test_evaluate() {
+    let c = curve(&[10.0, 30.0, 5.0, 20.0]);
+    assert_eq!(total_pnl(&c), 20.0);
+    assert_eq!(evaluate(WalkForwardMetric::PnL, &c), 20.0 / 4.0 * 252.0);
+    assert_eq!(
+        evaluate(WalkForwardMetric::ReturnOverDrawdown, &c),
+        20.0 / 4.0 * 252.0 / 25.0
+    );
+}
+
+#[test]
+fn test_return_over_drawdown_without_drawdown() {
+    // A parameter set that never trades doesn't win the optimization
+    let flat = curve(&[0.0, 0.0, 0.0]);
+    assert_eq!(return_over_drawdown(&flat), 0.0);
+    assert_eq!(return_over_drawdown(&curve(&[])), 0.0);
+
+    // A gain without a drawdown is scored as a ratio like any other, rather than in dollars
+    assert_eq!(
+        return_over_drawdown(&curve(&[1.0, 2.0])),
+        MAX_RETURN_OVER_DRAWDOWN
+    );
+    assert_eq!(
+        return_over_drawdown(&curve(&[1000.0, 999.0, 2000.0])),
+        MAX_RETURN_OVER_DRAWDOWN
+    );
+    assert!(return_over_drawdown(&curve(&[10.0, 30.0, 5.0, 20.0])) < MAX_RETURN_OVER_DRAWDOWN);
+}
@@ -1,4 +1,4 @@
-use super::BacktestHistoricalDataManager;
+use crate::backtest_historical_data::BacktestHistoricalDataManager;
 use chrono::{Datelike, NaiveDate};
 use domain::domain::Day;
 use services::historical_data::HistoricalDataService;
@@ -9,7 +9,7 @@ pub struct MockHistoricalDataService {
 }
 
 impl HistoricalDataService for MockHistoricalDataService {
-    fn fetch(&self, end: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+    fn fetch_as_of(&self, _: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
         let backtest_range = 20;
         let hist_data_range = 4;
         let start = self.end - chrono::Duration::days(backtest_range + hist_data_range);
@@ -34,6 +34,6 @@ impl HistoricalDataService for MockHistoricalDataService {
 
 impl BacktestHistoricalDataManager for MockHistoricalDataService {
     fn all(&self) -> Arc<HashMap<String, Vec<Day>>> {
-        self.fetch(self.end)
+        self.fetch_as_of(self.end)
     }
 }
@@ -0,0 +1,88 @@
+use super::*;
+
+fn date(month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+}
+
+#[test]
+fn test_windows() {
+    let windows = windows(date(6, 1), date(6, 30), 10, 7);
+    assert_eq!(
+        windows,
+        vec![
+            Window {
+                in_sample: (date(6, 1), date(6, 10)),
+                out_of_sample: (date(6, 11), date(6, 17)),
+            },
+            Window {
+                in_sample: (date(6, 8), date(6, 17)),
+                out_of_sample: (date(6, 18), date(6, 24)),
+            },
+            // The last out-of-sample window is truncated to the end of the range
+            Window {
+                in_sample: (date(6, 15), date(6, 24)),
+                out_of_sample: (date(6, 25), date(6, 30)),
+            },
+        ]
+    );
+
+    // The out-of-sample windows tile the range after the first in-sample window without overlap
+    let days: i64 = windows
+        .iter()
+        .map(|w| (w.out_of_sample.1 - w.out_of_sample.0).num_days() + 1)
+        .sum();
+    assert_eq!(days, 20);
+
+    assert!(super::windows(date(6, 1), date(6, 10), 10, 7).is_empty());
+}
+
+#[test]
+fn test_expand_grid() {
+    let base = HashMap::from([("num_std_dev".to_string(), 2.0), ("other".to_string(), 1.0)]);
+    let grid = HashMap::from([
+        ("num_std_dev".to_string(), vec![1.5, 2.5]),
+        ("extra".to_string(), vec![1.0, 2.0, 3.0]),
+    ]);
+
+    let combinations = expand_grid(&base, &grid);
+    assert_eq!(combinations.len(), 6);
+    assert!(combinations.iter().all(|c| c["other"] == 1.0));
+    assert_eq!(
+        combinations
+            .iter()
+            .map(|c| (c["extra"], c["num_std_dev"]))
+            .collect::<Vec<_>>(),
+        vec![
+            (1.0, 1.5),
+            (1.0, 2.5),
+            (2.0, 1.5),
+            (2.0, 2.5),
+            (3.0, 1.5),
+            (3.0, 2.5)
+        ]
+    );
+
+    assert_eq!(expand_grid(&base, &HashMap::new()), vec![base]);
+}
+
+#[test]
+fn test_stitch() {
+    let point = |day, equity| EquityPoint {
+        date: date(6, day),
+        equity,
+    };
+    let stitched = stitch(&[
+        vec![point(1, 10.0), point(2, 20.0)],
+        vec![],
+        vec![point(3, -5.0), point(4, 5.0)],
+    ]);
+    assert_eq!(
+        stitched,
+        vec![
+            point(1, 10.0),
+            point(2, 20.0),
+            point(3, 15.0),
+            point(4, 25.0)
+        ]
+    );
+}
@@ -0,0 +1,295 @@
+use crate::backtest_market_data_manager::BacktestMarketDataManager;
+use crate::metrics::{self, EquityCurve, EquityPoint};
+use app_config::app_config::{
+    Allocation, ExecutionDelay, FillConfig, Strategy, WalkForward, WalkForwardMetric,
+};
+use chrono::{Duration, NaiveDate};
+use log::*;
+use services::historical_data::HistoricalDataService;
+use std::{collections::HashMap, sync::Arc};
+
+pub trait WalkForwardService {
+    fn run(&self) -> Result<WalkForwardReport, String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub in_sample: (NaiveDate, NaiveDate),
+    pub out_of_sample: (NaiveDate, NaiveDate),
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowResult {
+    pub window: Window,
+    // Chosen parameters, one entry per strategy in config order
+    pub params: Vec<HashMap<String, f64>>,
+    pub in_sample_score: f64,
+    pub out_of_sample_score: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkForwardReport {
+    pub metric: WalkForwardMetric,
+    pub windows: Vec<WindowResult>,
+    // Out-of-sample equity curves joined end to end
+    pub equity: EquityCurve,
+    pub in_sample_score: f64,
+    pub out_of_sample_score: f64,
+}
+
+impl WalkForwardReport {
+    // Out-of-sample score as a fraction of in-sample score; well below 1.0 suggests overfitting
+    pub fn efficiency(&self) -> Option<f64> {
+        if self.in_sample_score > 0.0 {
+            Some(self.out_of_sample_score / self.in_sample_score)
+        } else {
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn new(
+    end: NaiveDate,
+    backtest_range: i64,
+    execution_delay: ExecutionDelay,
+    fills: FillConfig,
+    allocation: Allocation,
+    config: WalkForward,
+    historical_data: Arc<impl HistoricalDataService + 'static + Send + Sync>,
+    market_data_manager: Arc<impl BacktestMarketDataManager + 'static + Send + Sync>,
+    strategies: Vec<Strategy>,
+) -> Arc<impl WalkForwardService> {
+    Arc::new(implementation::WalkForward {
+        end,
+        backtest_range,
+        execution_delay,
+        fills,
+        allocation,
+        config,
+        historical_data,
+        market_data_manager,
+        strategies,
+    })
+}
+
+// Rolling windows over [start, end]: each out-of-sample window immediately follows its in-sample
+// window, and successive windows advance by the out-of-sample length.
+pub fn windows(
+    start: NaiveDate,
+    end: NaiveDate,
+    in_sample_days: i64,
+    out_of_sample_days: i64,
+) -> Vec<Window> {
+    assert!(
+        in_sample_days > 0 && out_of_sample_days > 0,
+        "Walk-forward windows must be non-empty"
+    );
+
+    let mut windows = Vec::new();
+    let mut in_sample_start = start;
+    loop {
+        let in_sample_end = in_sample_start + Duration::days(in_sample_days - 1);
+        let out_of_sample_start = in_sample_end + Duration::days(1);
+        if out_of_sample_start > end {
+            break;
+        }
+        let out_of_sample_end =
+            (out_of_sample_start + Duration::days(out_of_sample_days - 1)).min(end);
+        windows.push(Window {
+            in_sample: (in_sample_start, in_sample_end),
+            out_of_sample: (out_of_sample_start, out_of_sample_end),
+        });
+        in_sample_start += Duration::days(out_of_sample_days);
+    }
+    windows
+}
+
+// Every combination of grid values, each layered over the base parameters
+pub fn expand_grid(
+    base: &HashMap<String, f64>,
+    grid: &HashMap<String, Vec<f64>>,
+) -> Vec<HashMap<String, f64>> {
+    let mut names: Vec<&String> = grid.keys().collect();
+    names.sort();
+
+    names
+        .into_iter()
+        .fold(vec![base.clone()], |combinations, name| {
+            combinations
+                .iter()
+                .flat_map(|params| {
+                    grid[name].iter().map(move |value| {
+                        let mut params = params.clone();
+                        params.insert(name.clone(), *value);
+                        params
+                    })
+                })
+                .collect()
+        })
+}
+
+// Each curve starts from zero, so offset it by the final equity of the curves before it
+pub fn stitch(curves: &[EquityCurve]) -> EquityCurve {
+    let mut offset = 0.0;
+    let mut stitched = Vec::new();
+    for curve in curves {
+        stitched.extend(curve.iter().map(|point| EquityPoint {
+            date: point.date,
+            equity: point.equity + offset,
+        }));
+        offset = stitched.last().map(|p| p.equity).unwrap_or(offset);
+    }
+    stitched
+}
+
+mod implementation {
+    use super::*;
+    use crate::backtest_orders;
+    use crate::backtest_service::{self, BacktestService};
+
+    pub struct WalkForward<
+        H: HistoricalDataService + 'static + Send + Sync,
+        M: BacktestMarketDataManager + 'static + Send + Sync,
+    > {
+        pub end: NaiveDate,
+        pub backtest_range: i64,
+        pub execution_delay: ExecutionDelay,
+        pub fills: FillConfig,
+        pub allocation: Allocation,
+        pub config: app_config::app_config::WalkForward,
+        pub historical_data: Arc<H>,
+        pub market_data_manager: Arc<M>,
+        pub strategies: Vec<Strategy>,
+    }
+
+    impl<
+            H: HistoricalDataService + 'static + Send + Sync,
+            M: BacktestMarketDataManager + 'static + Send + Sync,
+        > WalkForwardService for WalkForward<H, M>
+    {
+        fn run(&self) -> Result<WalkForwardReport, String> {
+            let start = self.end - Duration::days(self.backtest_range);
+            let windows = windows(
+                start,
+                self.end,
+                self.config.in_sample_days,
+                self.config.out_of_sample_days,
+            );
+            if windows.is_empty() {
+                return Err(format!(
+                    "Backtest range of {} days is too short for an in-sample window of {} days",
+                    self.backtest_range, self.config.in_sample_days
+                ));
+            }
+
+            let mut results = Vec::new();
+            let mut curves = Vec::new();
+            for window in windows {
+                info!("Walk-forward window: {:?}", window);
+                let (params, scores): (Vec<_>, Vec<_>) = self
+                    .strategies
+                    .iter()
+                    .map(|strategy| self.optimize(strategy, window.in_sample))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .unzip();
+                let strategies = self.with_params(&params);
+
+                let out_of_sample = self.backtest(window.out_of_sample, strategies)?;
+                let result = WindowResult {
+                    window,
+                    params,
+                    // The optimization already scored each strategy's chosen parameters in-sample
+                    in_sample_score: scores.iter().sum::<f64>() / scores.len() as f64,
+                    out_of_sample_score: metrics::evaluate(self.config.metric, &out_of_sample),
+                };
+                info!("Walk-forward window result: {:?}", result);
+
+                results.push(result);
+                curves.push(out_of_sample);
+            }
+
+            let equity = stitch(&curves);
+            let in_sample_score =
+                results.iter().map(|r| r.in_sample_score).sum::<f64>() / results.len() as f64;
+            Ok(WalkForwardReport {
+                metric: self.config.metric,
+                windows: results,
+                out_of_sample_score: metrics::evaluate(self.config.metric, &equity),
+                equity,
+                in_sample_score,
+            })
+        }
+    }
+
+    impl<
+            H: HistoricalDataService + 'static + Send + Sync,
+            M: BacktestMarketDataManager + 'static + Send + Sync,
+        > WalkForward<H, M>
+    {
+        // Strategies are optimized independently of each other. The parameters scoring best, with
+        // their score, or the strategy's own if there's nothing to choose between.
+        fn optimize(
+            &self,
+            strategy: &Strategy,
+            range: (NaiveDate, NaiveDate),
+        ) -> Result<(HashMap<String, f64>, f64), String> {
+            let mut candidates = expand_grid(&strategy.params, &self.config.grid);
+            if candidates.is_empty() {
+                candidates.push(strategy.params.clone());
+            }
+            let mut best: Option<(f64, HashMap<String, f64>)> = None;
+            for params in candidates {
+                let candidate = Strategy {
+                    params: params.clone(),
+                    ..strategy.clone()
+                };
+                let score =
+                    metrics::evaluate(self.config.metric, &self.backtest(range, vec![candidate])?);
+                info!(
+                    "In-sample {:?} for '{}' with {:?}: {}",
+                    self.config.metric, strategy.name, params, score
+                );
+                match &best {
+                    Some((best_score, _)) if *best_score >= score => {}
+                    _ => best = Some((score, params)),
+                }
+            }
+            best.map(|(score, params)| (params, score))
+                .ok_or(format!("No parameters to try for '{}'", strategy.name))
+        }
+
+        fn with_params(&self, params: &[HashMap<String, f64>]) -> Vec<Strategy> {
+            self.strategies
+                .iter()
+                .zip(params)
+                .map(|(strategy, params)| Strategy {
+                    params: params.clone(),
+                    ..strategy.clone()
+                })
+                .collect()
+        }
+
+        fn backtest(
+            &self,
+            (start, end): (NaiveDate, NaiveDate),
+            strategies: Vec<Strategy>,
+        ) -> Result<EquityCurve, String> {
+            backtest_service::new(
+                end,
+                (end - start).num_days(),
+                self.historical_data.clone(),
+                self.market_data_manager.clone(),
+                backtest_orders::new(self.execution_delay, self.fills),
+                strategies,
+                self.allocation,
+            )
+            .run()
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/walk_forward_test.rs"]
+mod walk_forward_test;
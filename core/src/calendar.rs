@@ -0,0 +1,126 @@
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday,
+};
+
+// NYSE trading days: weekdays other than exchange holidays. Doesn't know about closures before 2000.
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !is_holiday(date)
+}
+
+// Trading days in [start, end]
+pub fn trading_days(start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+    start
+        .iter_days()
+        .take_while(|date| *date <= end)
+        .filter(|date| is_trading_day(*date))
+        .collect()
+}
+
+// The regular session opens at 9:30 and closes at 16:00 exchange time
+pub fn regular_open() -> NaiveTime {
+    NaiveTime::from_hms_opt(9, 30, 0).unwrap()
+}
+
+pub fn regular_close() -> NaiveTime {
+    NaiveTime::from_hms_opt(16, 0, 0).unwrap()
+}
+
+// Within the regular session of a trading day, wherever the time was taken. Early closes aren't known.
+pub fn in_regular_session<Tz: TimeZone>(time: &DateTime<Tz>) -> bool {
+    let time = exchange_time(time);
+    is_trading_day(time.date()) && time.time() >= regular_open() && time.time() < regular_close()
+}
+
+// The time on the exchange's clock in New York: UTC-4 from 2:00 on the second Sunday in March until
+// 2:00 on the first Sunday in November, and UTC-5 otherwise. Daylight saving rules before 2007 aren't
+// known.
+pub fn exchange_time<Tz: TimeZone>(time: &DateTime<Tz>) -> NaiveDateTime {
+    let utc = time.naive_utc();
+    let two_am = |date: NaiveDate| date.and_hms_opt(2, 0, 0).unwrap();
+    let summer_starts = two_am(nth_weekday(utc.year(), 3, Weekday::Sun, 2)) + Duration::hours(5);
+    let summer_ends = two_am(nth_weekday(utc.year(), 11, Weekday::Sun, 1)) + Duration::hours(4);
+    if utc >= summer_starts && utc < summer_ends {
+        utc - Duration::hours(4)
+    } else {
+        utc - Duration::hours(5)
+    }
+}
+
+pub fn is_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    let mut holidays = vec![
+        // New Year's Day is not observed on the preceding Friday, which would close the year's last session
+        observed(ymd(year, 1, 1)).filter(|d| d.year() == year),
+        Some(nth_weekday(year, 1, Weekday::Mon, 3)), // Martin Luther King Jr. Day
+        Some(nth_weekday(year, 2, Weekday::Mon, 3)), // Washington's Birthday
+        Some(easter(year) - chrono::Duration::days(2)), // Good Friday
+        Some(last_weekday(year, 5, Weekday::Mon)),   // Memorial Day
+        observed(ymd(year, 7, 4)),
+        Some(nth_weekday(year, 9, Weekday::Mon, 1)), // Labor Day
+        Some(nth_weekday(year, 11, Weekday::Thu, 4)), // Thanksgiving
+        observed(ymd(year, 12, 25)),
+    ];
+    if year >= 2022 {
+        holidays.push(observed(ymd(year, 6, 19))); // Juneteenth
+    }
+    holidays.contains(&Some(date)) || SPECIAL_CLOSURES.contains(&(year, date.month(), date.day()))
+}
+
+// Closures outside the regular schedule
+const SPECIAL_CLOSURES: [(i32, u32, u32); 10] = [
+    (2001, 9, 11),
+    (2001, 9, 12),
+    (2001, 9, 13),
+    (2001, 9, 14),
+    (2004, 6, 11),
+    (2007, 1, 2),
+    (2012, 10, 29),
+    (2012, 10, 30),
+    (2018, 12, 5),
+    (2025, 1, 9),
+];
+
+fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+// Saturday holidays are observed on the Friday before, Sunday holidays on the Monday after
+fn observed(date: NaiveDate) -> Option<NaiveDate> {
+    match date.weekday() {
+        Weekday::Sat => date.pred_opt(),
+        Weekday::Sun => date.succ_opt(),
+        _ => Some(date),
+    }
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+// Western Easter Sunday, by the anonymous Gregorian algorithm
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    ymd(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+#[path = "./tests/calendar_test.rs"]
+mod calendar_test;
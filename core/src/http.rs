@@ -1,5 +1,4 @@
 use backoff::{retry, Error, ExponentialBackoff};
-use log::*;
 use reqwest::{
     blocking::{Client, Response},
     header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_LENGTH},
@@ -21,20 +20,22 @@ pub fn get<T: DeserializeOwned>(url: &str, token: &str) -> Result<T, String> {
 
 pub fn post<T: DeserializeOwned>(url: &str, token: &str, body: String) -> Result<T, String> {
     let op = || {
-        let response = Client::new()
+        Client::new()
             .post(url)
             .headers(headers(token))
             .body(body.clone())
             .send()
             .map_err(backoff::Error::transient)
-            .unwrap()
-            .text();
-        info!("\n\nresponse:\n{:?}", response);
+    };
+
+    call(op)
+}
 
+pub fn delete<T: DeserializeOwned>(url: &str, token: &str) -> Result<T, String> {
+    let op = || {
         Client::new()
-            .post(url)
+            .delete(url)
             .headers(headers(token))
-            .body(body.clone())
             .send()
             .map_err(backoff::Error::transient)
     };
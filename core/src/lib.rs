@@ -1,3 +1,5 @@
+pub mod calendar;
 pub mod http;
+pub mod rate_limiter;
 pub mod serde;
 pub mod util;
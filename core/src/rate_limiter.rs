@@ -0,0 +1,59 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+// Token bucket shared between threads: holds up to `capacity` tokens, refilled at `rate` per second,
+// one taken per request. A rate of zero or less means no limit.
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    // Tokens available as of the instant
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        let capacity = capacity.max(1.0);
+        TokenBucket {
+            rate,
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    // Blocks until a token is available
+    pub fn acquire(&self) {
+        while let Err(wait) = self.take() {
+            thread::sleep(wait);
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    // Takes a token, or says how long until one is available
+    fn take(&self) -> Result<(), Duration> {
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let (tokens, last) = *state;
+        let tokens =
+            (tokens + now.duration_since(last).as_secs_f64() * self.rate).min(self.capacity);
+        if tokens >= 1.0 {
+            *state = (tokens - 1.0, now);
+            Ok(())
+        } else {
+            *state = (tokens, now);
+            Err(Duration::from_secs_f64((1.0 - tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/rate_limiter_test.rs"]
+mod rate_limiter_test;
@@ -8,12 +8,23 @@ where
     serializer.serialize_i64(date.timestamp_millis())
 }
 
+// Tradier sends millis as a string; what we serialize ourselves is a number
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Millis {
+    Number(i64),
+    String(String),
+}
+
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    String::deserialize(deserializer)
-        .and_then(|d| d.parse::<i64>().map_err(serde::de::Error::custom))
+    Millis::deserialize(deserializer)
+        .and_then(|millis| match millis {
+            Millis::Number(millis) => Ok(millis),
+            Millis::String(s) => s.parse::<i64>().map_err(serde::de::Error::custom),
+        })
         .and_then(|millis| {
             DateTime::<Utc>::from_timestamp_millis(millis)
                 .ok_or_else(|| serde::de::Error::custom("Invalid timestamp."))
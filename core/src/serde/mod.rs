@@ -1,3 +1,5 @@
 pub mod millis_date_time_format;
+pub mod one_or_many;
 pub mod rfc_3339_date_time_format;
 pub mod string_date_format;
+pub mod string_number_format;
@@ -0,0 +1,95 @@
+use super::*;
+use chrono::{FixedOffset, Utc};
+
+#[test]
+fn test_holidays_2024() {
+    let holidays: Vec<NaiveDate> = ymd(2024, 1, 1)
+        .iter_days()
+        .take_while(|d| d.year() == 2024)
+        .filter(|d| is_holiday(*d))
+        .collect();
+    assert_eq!(
+        holidays,
+        vec![
+            ymd(2024, 1, 1),
+            ymd(2024, 1, 15),
+            ymd(2024, 2, 19),
+            ymd(2024, 3, 29),
+            ymd(2024, 5, 27),
+            ymd(2024, 6, 19),
+            ymd(2024, 7, 4),
+            ymd(2024, 9, 2),
+            ymd(2024, 11, 28),
+            ymd(2024, 12, 25),
+        ]
+    );
+    assert_eq!(trading_days(ymd(2024, 1, 1), ymd(2024, 12, 31)).len(), 252);
+}
+
+#[test]
+fn test_observed() {
+    // Saturday Jan 1 2022 wasn't observed on Friday Dec 31 2021
+    assert!(is_trading_day(ymd(2021, 12, 31)));
+    assert!(!is_trading_day(ymd(2022, 1, 1)));
+    // Sunday Juneteenth 2022 was observed on the Monday
+    assert!(!is_trading_day(ymd(2022, 6, 20)));
+    // Saturday July 4 2026 is observed on the Friday
+    assert!(!is_trading_day(ymd(2026, 7, 3)));
+    // Juneteenth wasn't a holiday before 2022
+    assert!(is_trading_day(ymd(2021, 6, 18)));
+    assert!(!is_trading_day(ymd(2025, 1, 9)));
+}
+
+#[test]
+fn test_easter() {
+    assert_eq!(easter(2024), ymd(2024, 3, 31));
+    assert_eq!(easter(2025), ymd(2025, 4, 20));
+    assert_eq!(easter(2019), ymd(2019, 4, 21));
+}
+
+#[test]
+fn test_in_regular_session() {
+    // New York is 4 hours behind UTC in June
+    let eastern = FixedOffset::west_opt(4 * 3600).unwrap();
+    let at = |date: NaiveDate, h, m| {
+        eastern
+            .from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap())
+            .unwrap()
+    };
+    let monday = ymd(2024, 6, 3);
+    assert!(!in_regular_session(&at(monday, 9, 29)));
+    assert!(in_regular_session(&at(monday, 9, 30)));
+    assert!(in_regular_session(&at(monday, 15, 59)));
+    assert!(!in_regular_session(&at(monday, 16, 0)));
+    assert!(!in_regular_session(&at(ymd(2024, 6, 1), 12, 0)));
+    assert!(!in_regular_session(&at(ymd(2024, 7, 4), 12, 0)));
+}
+
+#[test]
+fn test_in_regular_session_elsewhere() {
+    // The open, as seen from UTC and from Tokyo
+    let utc = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+    assert!(!in_regular_session(&utc(2024, 6, 3, 13, 29)));
+    assert!(in_regular_session(&utc(2024, 6, 3, 13, 30)));
+    let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+    assert!(in_regular_session(
+        &utc(2024, 6, 3, 19, 59).with_timezone(&tokyo)
+    ));
+    assert!(!in_regular_session(
+        &utc(2024, 6, 3, 20, 0).with_timezone(&tokyo)
+    ));
+
+    // An hour later in UTC outside daylight saving
+    assert!(!in_regular_session(&utc(2024, 1, 8, 14, 29)));
+    assert!(in_regular_session(&utc(2024, 1, 8, 14, 30)));
+    assert!(in_regular_session(&utc(2024, 1, 8, 20, 59)));
+    // Daylight saving began at 7:00 UTC on March 10, and ended at 6:00 UTC on November 3
+    assert_eq!(
+        exchange_time(&utc(2024, 3, 10, 7, 0)),
+        ymd(2024, 3, 10).and_hms_opt(3, 0, 0).unwrap()
+    );
+    assert_eq!(
+        exchange_time(&utc(2024, 11, 3, 6, 0)),
+        ymd(2024, 11, 3).and_hms_opt(1, 0, 0).unwrap()
+    );
+}
@@ -0,0 +1,37 @@
+use super::*;
+use std::sync::Arc;
+
+#[test]
+fn test_burst_then_rate() {
+    let bucket = TokenBucket::new(100.0, 5.0);
+    let start = Instant::now();
+    (0..5).for_each(|_| assert!(bucket.try_acquire()));
+    assert!(!bucket.try_acquire());
+
+    // Another 10 at 100 per second take around 100ms
+    (0..10).for_each(|_| bucket.acquire());
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(90), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+}
+
+#[test]
+fn test_shared() {
+    let bucket = Arc::new(TokenBucket::new(200.0, 1.0));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let bucket = bucket.clone();
+            thread::spawn(move || (0..10).for_each(|_| bucket.acquire()))
+        })
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    // 40 tokens with 1 up front at 200 per second, however many threads ask
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[test]
+fn test_unlimited() {
+    let bucket = TokenBucket::new(0.0, 1.0);
+    assert!((0..1000).all(|_| bucket.try_acquire()));
+}
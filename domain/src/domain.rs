@@ -1,15 +1,18 @@
-use chrono::{DateTime, Local, NaiveDate};
-use core::serde::{millis_date_time_format, rfc_3339_date_time_format, string_date_format};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use core::serde::{
+    millis_date_time_format, rfc_3339_date_time_format, string_date_format, string_number_format,
+};
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::{
     any::Any,
+    collections::HashMap,
     fmt::{self, Display, Formatter},
 };
 
 use crate::serde::side_format;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Quote {
     pub symbol: String,
     pub bid: f64,
@@ -18,9 +21,136 @@ pub struct Quote {
     pub biddate: DateTime<Local>,
     #[serde(with = "millis_date_time_format")]
     pub askdate: DateTime<Local>,
+    // Sizes are in round lots, as Tradier reports them; absent from synthesized quotes
+    #[serde(default)]
+    pub bidsz: i64,
+    #[serde(default)]
+    pub asksz: i64,
+    #[serde(default)]
+    pub bidexch: String,
+    #[serde(default)]
+    pub askexch: String,
+}
+
+// An event from Tradier's streaming API, tagged by its type
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MarketEvent {
+    Quote(Quote),
+    Trade(Trade),
+    Summary(Summary),
+    Timesale(Timesale),
+    // A trade with extended information, as sent for the tradex filter
+    Tradex(Trade),
+}
+
+impl MarketEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            MarketEvent::Quote(_) => EventType::Quote,
+            MarketEvent::Trade(_) => EventType::Trade,
+            MarketEvent::Summary(_) => EventType::Summary,
+            MarketEvent::Timesale(_) => EventType::Timesale,
+            MarketEvent::Tradex(_) => EventType::Tradex,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Quote(quote) => &quote.symbol,
+            MarketEvent::Trade(trade) | MarketEvent::Tradex(trade) => &trade.symbol,
+            MarketEvent::Summary(summary) => &summary.symbol,
+            MarketEvent::Timesale(timesale) => &timesale.symbol,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    Quote,
+    Trade,
+    Summary,
+    Timesale,
+    Tradex,
+}
+
+impl EventType {
+    pub const ALL: [EventType; 5] = [
+        EventType::Quote,
+        EventType::Trade,
+        EventType::Summary,
+        EventType::Timesale,
+        EventType::Tradex,
+    ];
+}
+
+// As Tradier names event types in stream filters
+impl Display for EventType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EventType::Quote => write!(f, "quote"),
+            EventType::Trade => write!(f, "trade"),
+            EventType::Summary => write!(f, "summary"),
+            EventType::Timesale => write!(f, "timesale"),
+            EventType::Tradex => write!(f, "tradex"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    pub symbol: String,
+    pub exch: String,
+    #[serde(with = "string_number_format")]
+    pub price: f64,
+    #[serde(with = "string_number_format")]
+    pub size: i64,
+    // Cumulative volume for the day
+    #[serde(with = "string_number_format")]
+    pub cvol: i64,
+    #[serde(with = "millis_date_time_format")]
+    pub date: DateTime<Local>,
+    #[serde(with = "string_number_format")]
+    pub last: f64,
+}
+
+// The day's session so far
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Summary {
+    pub symbol: String,
+    #[serde(with = "string_number_format")]
+    pub open: f64,
+    #[serde(with = "string_number_format")]
+    pub high: f64,
+    #[serde(with = "string_number_format")]
+    pub low: f64,
+    #[serde(rename = "prevClose", with = "string_number_format")]
+    pub prev_close: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Timesale {
+    pub symbol: String,
+    pub exch: String,
+    #[serde(with = "string_number_format")]
+    pub bid: f64,
+    #[serde(with = "string_number_format")]
+    pub ask: f64,
+    #[serde(with = "string_number_format")]
+    pub last: f64,
+    #[serde(with = "string_number_format")]
+    pub size: i64,
+    #[serde(with = "millis_date_time_format")]
+    pub date: DateTime<Local>,
+    pub seq: i64,
+    pub flag: String,
+    pub cancel: bool,
+    pub correction: bool,
+    pub session: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Day {
     pub symbol: Option<String>,
     #[serde(with = "string_date_format")]
@@ -32,6 +162,128 @@ pub struct Day {
     pub volume: i64,
 }
 
+// Generalizes Day to bars of any interval; timestamp is the start of the bar
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bar {
+    pub symbol: String,
+    pub timestamp: DateTime<Local>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: i64,
+}
+
+impl From<Day> for Bar {
+    fn from(day: Day) -> Self {
+        Bar {
+            symbol: day.symbol.expect("Missing symbol"),
+            timestamp: day
+                .date
+                .and_time(NaiveTime::MIN)
+                .and_local_timezone(Local)
+                .earliest()
+                .expect("Failed to convert date to datetime"),
+            open: day.open,
+            high: day.high,
+            low: day.low,
+            close: day.close,
+            volume: day.volume,
+        }
+    }
+}
+
+impl Bar {
+    pub fn date(&self) -> NaiveDate {
+        self.timestamp.date_naive()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Interval {
+    #[default]
+    #[serde(rename = "daily")]
+    Daily,
+    // Only built from live quotes; Tradier has no history at this interval
+    #[serde(rename = "1sec")]
+    Second,
+    #[serde(rename = "1min")]
+    Minute,
+    #[serde(rename = "5min")]
+    FiveMinute,
+    #[serde(rename = "15min")]
+    FifteenMinute,
+}
+
+impl Interval {
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            Interval::Daily => chrono::Duration::days(1),
+            Interval::Second => chrono::Duration::seconds(1),
+            Interval::Minute => chrono::Duration::minutes(1),
+            Interval::FiveMinute => chrono::Duration::minutes(5),
+            Interval::FifteenMinute => chrono::Duration::minutes(15),
+        }
+    }
+}
+
+// As Tradier names intervals
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Interval::Daily => write!(f, "daily"),
+            Interval::Second => write!(f, "1sec"),
+            Interval::Minute => write!(f, "1min"),
+            Interval::FiveMinute => write!(f, "5min"),
+            Interval::FifteenMinute => write!(f, "15min"),
+        }
+    }
+}
+
+// A bar built from live quotes, as persisted
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AggregatedBar {
+    pub interval: Interval,
+    #[serde(flatten)]
+    pub bar: Bar,
+}
+
+impl Persistable for AggregatedBar {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn id(&self) -> i64 {
+        self.bar.timestamp.timestamp_millis()
+    }
+}
+
+// A split or dividend, taking effect from the first session on its ex-date
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    pub symbol: String,
+    pub ex_date: NaiveDate,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    // New shares per old share, e.g. 4.0 for a 4-for-1 split
+    Split(f64),
+    // Cash per share
+    Dividend(f64),
+}
+
+// Whether prices before a split or dividend are left as traded or back-adjusted to be comparable with
+// later prices
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PriceAdjustment {
+    #[default]
+    Raw,
+    Adjusted,
+}
+
 #[derive(Debug)]
 pub struct SymbolData {
     pub symbol: String,
@@ -79,6 +331,9 @@ pub struct Order {
     // Integer quantity as we'll only trade equities
     pub quantity: i64,
     pub px: Option<f64>,
+    // The account it was placed in, by name; empty in backtests
+    #[serde(default)]
+    pub account: String,
 }
 
 impl Persistable for Order {
@@ -119,8 +374,12 @@ pub struct Position {
     pub cost_basis: f64,
     #[serde(with = "millis_date_time_format")]
     pub date: DateTime<Local>,
+    // The account it's held in, by name; empty in backtests
+    #[serde(default)]
+    pub account: String,
 }
 
+// The account is set by whoever read the position
 impl From<TradierPosition> for Position {
     fn from(tp: TradierPosition) -> Self {
         Position {
@@ -129,6 +388,7 @@ impl From<TradierPosition> for Position {
             quantity: tp.quantity as i64,
             cost_basis: tp.cost_basis,
             date: tp.date_acquired,
+            account: String::new(),
         }
     }
 }
@@ -167,6 +427,9 @@ pub struct RealizedPnL {
     pub date: NaiveDate,
     pub pnl: f64,
     pub strategy: String,
+    // The account the sale was made in, by name; empty in backtests
+    #[serde(default)]
+    pub account: String,
 }
 
 impl Persistable for RealizedPnL {
@@ -179,6 +442,26 @@ impl Persistable for RealizedPnL {
     }
 }
 
+// An account's cash and equity as of a time, as kept for paper accounts
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountBalance {
+    pub account: String,
+    pub cash: f64,
+    pub equity: f64,
+    #[serde(with = "millis_date_time_format")]
+    pub date: DateTime<Local>,
+}
+
+impl Persistable for AccountBalance {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn id(&self) -> i64 {
+        self.date.timestamp_millis()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Signal {
     Buy,
@@ -188,16 +471,34 @@ pub enum Signal {
 
 #[derive(Debug, Clone)]
 pub enum Strategy {
-    MeanReversion { symbols: Vec<String> },
+    MeanReversion {
+        symbols: Vec<String>,
+        // Width of the Bollinger Bands, in standard deviations from the mean
+        num_std_dev: f64,
+    },
 }
 
 impl Strategy {
     pub fn new(name: &str, symbols: Vec<String>) -> Self {
+        Strategy::with_params(name, symbols, &HashMap::new())
+    }
+
+    // Unspecified parameters take their defaults; unknown parameters are ignored
+    pub fn with_params(name: &str, symbols: Vec<String>, params: &HashMap<String, f64>) -> Self {
         match name {
-            "mean-reversion" => Strategy::MeanReversion { symbols },
+            "mean-reversion" => Strategy::MeanReversion {
+                symbols,
+                num_std_dev: *params.get("num_std_dev").unwrap_or(&2.0),
+            },
             _ => panic!("Unknown strategy: {}", name),
         }
     }
+
+    pub fn symbols(&self) -> &[String] {
+        match self {
+            Strategy::MeanReversion { symbols, .. } => symbols,
+        }
+    }
 }
 
 impl Display for Strategy {
@@ -224,7 +525,10 @@ impl StrategyHandler for Strategy {
         //     _quote.clone()
         // };
         match self {
-            Strategy::MeanReversion { symbols } => {
+            Strategy::MeanReversion {
+                symbols,
+                num_std_dev,
+            } => {
                 if symbols.contains(&quote.symbol) {
                     info!("MeanReversionStrategy handling quote: {:?}", quote);
                     info!(
@@ -232,13 +536,14 @@ impl StrategyHandler for Strategy {
                         quote.ask, data.mean, data.std_dev
                     );
                     info!(
-                        "quote.ask: {}; (mean - 2.0 * std_dev): {}",
+                        "quote.ask: {}; (mean - {} * std_dev): {}",
                         quote.ask,
-                        data.mean - 2.0 * data.std_dev
+                        num_std_dev,
+                        data.mean - num_std_dev * data.std_dev
                     );
 
-                    let buy = quote.ask < data.mean - 2.0 * data.std_dev;
-                    let sell = quote.ask > data.mean + 2.0 * data.std_dev;
+                    let buy = quote.ask < data.mean - num_std_dev * data.std_dev;
+                    let sell = quote.ask > data.mean + num_std_dev * data.std_dev;
 
                     if buy {
                         info!("***Buy signal for {}***", quote.symbol);
@@ -17,6 +17,10 @@ fn test_mean_reversion_strategy() {
         ask: 90.0,
         biddate: Local::now(),
         askdate: Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
     };
     match strategy.handle(&buy_quote, &symbol_data) {
         Ok(signal) => {
@@ -34,6 +38,10 @@ fn test_mean_reversion_strategy() {
         ask: 150.0,
         biddate: Local::now(),
         askdate: Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
     };
     match strategy.handle(&buy_quote, &symbol_data) {
         Ok(signal) => {
@@ -51,6 +59,10 @@ fn test_mean_reversion_strategy() {
         ask: 95.0,
         biddate: Local::now(),
         askdate: Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
     };
     match strategy.handle(&buy_quote, &symbol_data) {
         Ok(signal) => {
@@ -61,3 +73,89 @@ fn test_mean_reversion_strategy() {
         }
     }
 }
+
+#[test]
+fn test_mean_reversion_strategy_params() {
+    let params = HashMap::from([("num_std_dev".to_string(), 1.0)]);
+    let strategy = Strategy::with_params("mean-reversion", vec!["SPY".to_string()], &params);
+    let symbol_data = SymbolData {
+        mean: 100.0,
+        std_dev: 4.0,
+        symbol: "SPY".to_string(),
+        history: Vec::new(),
+    };
+
+    // Within 2 std devs, but outside the narrower 1 std dev band
+    let quote = Quote {
+        symbol: "SPY".to_string(),
+        bid: 95.0,
+        ask: 95.0,
+        biddate: Local::now(),
+        askdate: Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    };
+    assert_eq!(strategy.handle(&quote, &symbol_data), Ok(Signal::Buy));
+    assert_eq!(
+        Strategy::new("mean-reversion", vec!["SPY".to_string()]).handle(&quote, &symbol_data),
+        Ok(Signal::None)
+    );
+}
+
+#[test]
+fn test_parse_market_events() {
+    let quote = r#"{"type":"quote","symbol":"SPY","bid":281.84,"bidsz":60,"bidexch":"M","biddate":"1557757189000","ask":281.85,"asksz":6,"askexch":"Z","askdate":"1557757190000"}"#;
+    match serde_json::from_str::<MarketEvent>(quote).unwrap() {
+        MarketEvent::Quote(quote) => {
+            assert_eq!(
+                (quote.bid, quote.bidsz, quote.bidexch.as_str()),
+                (281.84, 60, "M")
+            );
+            assert_eq!(
+                (quote.ask, quote.asksz, quote.askexch.as_str()),
+                (281.85, 6, "Z")
+            );
+            assert_eq!(quote.askdate.timestamp_millis(), 1557757190000);
+        }
+        event => panic!("Expected a quote: {:?}", event),
+    }
+
+    let trade = r#"{"type":"trade","symbol":"SPY","exch":"J","price":"281.85","size":"100","cvol":"34652845","date":"1557757190000","last":"281.85"}"#;
+    match serde_json::from_str::<MarketEvent>(trade).unwrap() {
+        MarketEvent::Trade(trade) => {
+            assert_eq!(
+                (trade.price, trade.size, trade.cvol),
+                (281.85, 100, 34652845)
+            );
+            assert_eq!(trade.exch, "J");
+        }
+        event => panic!("Expected a trade: {:?}", event),
+    }
+
+    let summary = r#"{"type":"summary","symbol":"SPY","open":"282.42","high":"283.49","low":"281.63","prevClose":"283.09"}"#;
+    match serde_json::from_str::<MarketEvent>(summary).unwrap() {
+        MarketEvent::Summary(summary) => assert_eq!(summary.prev_close, 283.09),
+        event => panic!("Expected a summary: {:?}", event),
+    }
+
+    let timesale = r#"{"type":"timesale","symbol":"SPY","exch":"Q","bid":"282.08","ask":"282.09","last":"282.09","size":"100","date":"1557758874355","seq":352832,"flag":"","cancel":false,"correction":false,"session":"normal"}"#;
+    match serde_json::from_str::<MarketEvent>(timesale).unwrap() {
+        MarketEvent::Timesale(timesale) => {
+            assert_eq!((timesale.last, timesale.seq), (282.09, 352832));
+            assert_eq!(timesale.session, "normal");
+        }
+        event => panic!("Expected a timesale: {:?}", event),
+    }
+
+    let tradex = trade.replace("\"trade\"", "\"tradex\"");
+    let tradex = serde_json::from_str::<MarketEvent>(&tradex).unwrap();
+    assert_eq!(tradex.event_type(), EventType::Tradex);
+    assert_eq!(tradex.symbol(), "SPY");
+
+    // What we serialize reads back
+    let json = serde_json::to_string(&tradex).unwrap();
+    let event = serde_json::from_str::<MarketEvent>(&json).unwrap();
+    assert_eq!(event.event_type(), EventType::Tradex);
+}
@@ -2,20 +2,32 @@
 #![allow(unused_variables)]
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     env,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{atomic::AtomicBool, Arc, Mutex},
     thread::{self, JoinHandle},
     time::Duration,
 };
 
-use app_config::app_config::AppConfig;
+use app_config::app_config::{
+    AccountConfig, AllocationMethod, AppConfig, BrokerKind, CorporateActionSource,
+    FeedHealthConfig, HistoricalSource, MarketDataProvider, Strategy as StrategyConfig,
+};
 use chrono::{Local, NaiveDate};
+use domain::domain::{Order, Position, Strategy};
 use log::*;
-use log4rs;
+use services::bar_aggregator::BarAggregator;
+use services::broker::{Balances, PaperBook};
+use services::feed_health::FeedHealthMonitor;
+use services::historical_data::HistoricalDataService;
+use services::market_data_recorder::MarketDataRecorder;
+use services::orders::OrderService;
 use services::persistence::PersistenceService;
 use services::trading::TradingService;
-use services::{historical_data, market_data, orders, trading};
+use services::{
+    allocation, bar_aggregator, broker, feed_health, historical_data, market_data,
+    market_data_recorder, market_data_replay, market_data_websocket, orders, paper_orders, trading,
+};
 use services::{market_data::MarketDataService, persistence};
 
 fn main() {
@@ -24,34 +36,131 @@ fn main() {
     let config = AppConfig::new().expect("Failed to parse config");
     info!("Config:\n{:?}", config);
 
+    let mut config = config;
     let mut today = Local::now().naive_local().date();
-    let (mut shutdown, mut handle) = init_for_new_day(today, config.clone());
+    let paper_books = PaperBooks::default();
+    let mut day = init_for_new_day(today, config.clone(), paper_books.clone());
 
     loop {
-        thread::sleep(Duration::from_secs(300));
+        thread::sleep(Duration::from_secs(60));
         let now = Local::now().naive_local().date();
+        // Edits to the config are picked up as they're made
+        match AppConfig::new() {
+            Ok(reloaded) => config = reloaded,
+            Err(e) => warn!("Failed to reload config, keeping the previous one: {}", e),
+        }
 
         if now > today {
-            shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+            day.shutdown
+                .store(true, std::sync::atomic::Ordering::Relaxed);
 
-            handle
+            day.handle
                 .join()
                 .expect("Failed to join MarketDataService thread");
             info!("All threads exited successfully");
 
             today = now;
             info!("Trading day ended - resetting for {}", today);
-            (shutdown, handle) = init_for_new_day(today, config.clone());
+            day = init_for_new_day(today, config.clone(), paper_books.clone());
+        } else {
+            (day.reload)(config.clone());
         }
     }
 }
 
-fn init_for_new_day(today: NaiveDate, config: AppConfig) -> (Arc<AtomicBool>, JoinHandle<()>) {
-    let access_token = env::var("ACCESS_TOKEN").expect("ACCESS_TOKEN not found");
-    let sandbox_token = env::var("SANDBOX_TOKEN").expect("SANDBOX_TOKEN not found");
-    let account_id = env::var("ACCOUNT_ID").expect("ACCOUNT_ID not found");
+// What's running for a trading day
+struct Day {
+    shutdown: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    // Starts strategies added to the config since the day began
+    reload: Box<dyn FnMut(AppConfig)>,
+}
+
+// Paper accounts by name, kept from one day to the next for as long as the server runs
+type PaperBooks = Arc<Mutex<HashMap<String, PaperBook>>>;
+
+// Orders for an account, whether at a broker or on paper
+enum AccountOrders<B: OrderService, P: OrderService> {
+    Broker(Arc<B>),
+    Paper(Arc<P>),
+}
+
+impl<B: OrderService, P: OrderService> OrderService for AccountOrders<B, P> {
+    fn create_order(&self, order: Order, strategy: String) -> Result<Order, String> {
+        match self {
+            AccountOrders::Broker(orders) => orders.create_order(order, strategy),
+            AccountOrders::Paper(orders) => orders.create_order(order, strategy),
+        }
+    }
+
+    fn get_position(&self, symbol: &str) -> Option<Position> {
+        match self {
+            AccountOrders::Broker(orders) => orders.get_position(symbol),
+            AccountOrders::Paper(orders) => orders.get_position(symbol),
+        }
+    }
+
+    fn update_position(&self, position: &Position) {
+        match self {
+            AccountOrders::Broker(orders) => orders.update_position(position),
+            AccountOrders::Paper(orders) => orders.update_position(position),
+        }
+    }
+
+    fn balances(&self) -> Result<Balances, String> {
+        match self {
+            AccountOrders::Broker(orders) => orders.balances(),
+            AccountOrders::Paper(orders) => orders.balances(),
+        }
+    }
+}
+
+// Strategies are started the same way whichever provider the quotes come from
+fn init_for_new_day(today: NaiveDate, config: AppConfig, paper_books: PaperBooks) -> Day {
+    match config.market_data.provider() {
+        MarketDataProvider::Replay => {
+            let replay = config
+                .market_data
+                .replay
+                .clone()
+                .expect("The replay provider requires [market_data.replay]");
+            // Orders placed against a replay must not reach a live account
+            assert!(
+                config.sandbox,
+                "Replaying market data requires sandbox = true"
+            );
+            // Recorded quotes carry the times they were recorded, so their lag says nothing
+            let mut config = config;
+            config.market_data.health.max_lag_secs = 0.0;
+            let market_data = market_data_replay::new(&replay, config.market_data.subscriptions);
+            start(today, config, market_data, paper_books)
+        }
+        MarketDataProvider::Websocket => {
+            let token = env::var("MARKET_DATA_TOKEN").unwrap_or_default();
+            let market_data = market_data_websocket::new(token, &config.market_data)
+                .expect("Failed to create MarketDataService");
+            start(today, config, market_data, paper_books)
+        }
+        MarketDataProvider::Tradier => {
+            let access_token = env::var("ACCESS_TOKEN").expect("ACCESS_TOKEN not found");
+            let market_data = market_data::new(access_token, &config.market_data);
+            start(today, config, market_data, paper_books)
+        }
+    }
+}
+
+fn start(
+    today: NaiveDate,
+    config: AppConfig,
+    market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
+    paper_books: PaperBooks,
+) -> Day {
+    let access_token = if needs_access_token(&config) {
+        env::var("ACCESS_TOKEN").expect("ACCESS_TOKEN not found")
+    } else {
+        env::var("ACCESS_TOKEN").unwrap_or_default()
+    };
     let mongo_url = env::var("MONGO_URL").expect("MONGO_URL not found");
-    let market_data = market_data::new(access_token.clone());
     let symbols = config.all_symbols();
     let shutdown = Arc::new(AtomicBool::new(false));
     let persistence = persistence::new(mongo_url.clone());
@@ -61,54 +170,259 @@ fn init_for_new_day(today: NaiveDate, config: AppConfig) -> (Arc<AtomicBool>, Jo
         .expect("Failed to initialize persistence");
 
     let historical_data = historical_data::new(
+        &config.historical_data,
         access_token.clone(),
         symbols.clone(),
         config.hist_data_range,
         today,
     );
 
-    let orders = if config.sandbox {
-        orders::new(
-            sandbox_token.clone(),
-            account_id.clone(),
-            "sandbox.tradier.com".into(),
-            persistence.clone(),
-        )
-        .expect("Failed to create OrdersService")
-    } else {
-        orders::new(
-            access_token.clone(),
-            account_id.clone(),
-            "api.tradier.com".into(),
-            persistence.clone(),
-        )
-        .expect("Failed to create OrdersService")
+    let open_account = {
+        let persistence = persistence.clone();
+        let market_data = market_data.clone();
+        let fills = config.fills;
+        move |name: &str, account: &AccountConfig| match account.broker {
+            // Paper accounts fill against the day's quotes, carrying their cash and positions over
+            BrokerKind::Paper => {
+                let mut books = paper_books.lock().map_err(|e| e.to_string())?;
+                let book = match books.get(name) {
+                    Some(book) => book.clone(),
+                    // Picked up where it was left before the server restarted
+                    None => {
+                        let book = paper_orders::restore(name, account.cash, persistence.as_ref())?;
+                        books.insert(name.to_string(), book.clone());
+                        book
+                    }
+                };
+                let orders = paper_orders::new(
+                    name.to_string(),
+                    book,
+                    fills,
+                    market_data.clone(),
+                    persistence.clone(),
+                )?;
+                Ok(Arc::new(AccountOrders::Paper(orders)))
+            }
+            BrokerKind::Tradier => {
+                // The broker's positions replace those stored
+                persistence.drop_positions(name)?;
+                let orders = orders::with_broker(
+                    name.to_string(),
+                    broker::new(account)?,
+                    persistence.clone(),
+                )?;
+                Ok(Arc::new(AccountOrders::Broker(orders)))
+            }
+        }
+    };
+    let mut accounts = HashMap::new();
+    // Capital is reallocated each day, from the history as of the day
+    let mut history = match config.allocation.method {
+        AllocationMethod::Capital => HashMap::new(),
+        _ => (*historical_data.fetch_as_of(today)).clone(),
     };
 
     let mut symbols: HashSet<String> = HashSet::new();
+    let mut started: HashSet<String> = HashSet::new();
     let date = Local::now().naive_local().date();
+    let health = config.market_data.health;
 
-    config.strategies.into_iter().for_each(|strategy| {
+    for strategy in config.strategies.clone() {
+        let orders = match orders_for(&mut accounts, &config, &strategy, &open_account) {
+            Ok(orders) => orders,
+            Err(e) => {
+                error!("Not starting strategy {}: {}", strategy.name, e);
+                continue;
+            }
+        };
         symbols.extend(strategy.symbols.clone());
-        let mut trading_service = trading::new(
+        started.insert(strategy.name.clone());
+        let strategy = allocated(&config, strategy, orders.as_ref(), &history);
+        start_strategy(
             date,
-            strategy.name.clone(),
-            strategy.symbols.clone(),
-            strategy.capital.clone(),
+            strategy,
             market_data.clone(),
             historical_data.clone(),
-            orders.clone(),
+            orders,
+            health,
             shutdown.clone(),
         );
-        match trading_service.run() {
-            Ok(_) => (),
-            Err(e) => info!("Error starting TradingService {}: {}", strategy.name, e),
-        }
-    });
+    }
+
+    // Subscribed before the feed starts, so nothing is missed
+    let monitor = feed_health::new(&health, market_data.clone());
+    monitor
+        .init(shutdown.clone(), symbols.iter().cloned().collect())
+        .expect("Failed to start FeedHealthMonitor");
+    if !config.market_data.bars.intervals.is_empty() {
+        bar_aggregator::new(
+            &config.market_data.bars,
+            market_data.clone(),
+            persistence.clone(),
+        )
+        .init(shutdown.clone())
+        .expect("Failed to start BarAggregator");
+    }
+    if config.market_data.recording.enabled {
+        market_data_recorder::new(&config.market_data.recording, market_data.clone())
+            .start(shutdown.clone())
+            .expect("Failed to start MarketDataRecorder");
+    }
 
     let handle = market_data
         .init(shutdown.clone(), symbols.into_iter().collect())
         .expect("Failed to start MarketDataService");
 
-    (shutdown, handle)
+    // Running strategies can't be stopped mid-day, so only additions take effect before the next day
+    let running = shutdown.clone();
+    let reload = move |config: AppConfig| {
+        for strategy in config.strategies.clone() {
+            if started.contains(&strategy.name) {
+                continue;
+            }
+            info!("Starting strategy {} added to config", strategy.name);
+            let orders = match orders_for(&mut accounts, &config, &strategy, &open_account) {
+                Ok(orders) => orders,
+                Err(e) => {
+                    error!("Not starting strategy {}: {}", strategy.name, e);
+                    continue;
+                }
+            };
+            started.insert(strategy.name.clone());
+            if let Err(e) = market_data.add_symbols(&strategy.symbols) {
+                error!("Failed to subscribe to {:?}: {}", strategy.symbols, e);
+                continue;
+            }
+            if let Err(e) = monitor.add_symbols(&strategy.symbols) {
+                error!("Failed to monitor {:?}: {}", strategy.symbols, e);
+            }
+            let historical_data = historical_data::new(
+                &config.historical_data,
+                access_token.clone(),
+                strategy.symbols.clone(),
+                config.hist_data_range,
+                today,
+            );
+            if config.allocation.method != AllocationMethod::Capital {
+                history.extend(
+                    historical_data
+                        .fetch_as_of(today)
+                        .iter()
+                        .map(|(symbol, days)| (symbol.clone(), days.clone())),
+                );
+            }
+            let strategy = allocated(&config, strategy, orders.as_ref(), &history);
+            start_strategy(
+                date,
+                strategy,
+                market_data.clone(),
+                historical_data,
+                orders,
+                health,
+                running.clone(),
+            );
+        }
+    };
+
+    Day {
+        shutdown,
+        handle,
+        reload: Box::new(reload),
+    }
+}
+
+// The orders for the account a strategy trades in, opening the account if no strategy has yet
+// Tradier's token is only needed when something comes from Tradier, so that a paper account can be
+// forward-tested against another feed, with history read from files, and no Tradier account
+fn needs_access_token(config: &AppConfig) -> bool {
+    let tradier_account = config.strategies.iter().any(|strategy| {
+        matches!(config.account(strategy), Ok((_, account)) if account.broker == BrokerKind::Tradier)
+    });
+    let history = &config.historical_data;
+    let tradier_history = (history.source == HistoricalSource::Tradier && !history.cache.offline)
+        || history.corporate_actions.source == CorporateActionSource::Tradier;
+    config.market_data.provider() == MarketDataProvider::Tradier
+        || tradier_account
+        || tradier_history
+}
+
+fn orders_for<O: Clone>(
+    accounts: &mut HashMap<String, O>,
+    config: &AppConfig,
+    strategy: &StrategyConfig,
+    open: &impl Fn(&str, &AccountConfig) -> Result<O, String>,
+) -> Result<O, String> {
+    let (name, account) = config.account(strategy)?;
+    if let Some(orders) = accounts.get(&name) {
+        return Ok(orders.clone());
+    }
+    info!("Opening account {}: {:?}", name, account.broker);
+    let orders =
+        open(&name, &account).map_err(|e| format!("Failed to open account {}: {}", name, e))?;
+    accounts.insert(name, orders.clone());
+    Ok(orders)
+}
+
+// The strategy with its capital for the day: its share of the equity of the account it trades in,
+// alongside the other strategies trading there
+fn allocated(
+    config: &AppConfig,
+    strategy: StrategyConfig,
+    orders: &impl OrderService,
+    history: &HashMap<String, Vec<domain::domain::Day>>,
+) -> StrategyConfig {
+    if config.allocation.method == AllocationMethod::Capital {
+        return strategy;
+    }
+    let account = |s: &StrategyConfig| config.account(s).map(|(name, _)| name).ok();
+    let peers: Vec<StrategyConfig> = config
+        .strategies
+        .iter()
+        .filter(|s| account(s) == account(&strategy))
+        .cloned()
+        .collect();
+    let equity = match orders.balances() {
+        Ok(balances) => balances.equity,
+        Err(e) => {
+            warn!(
+                "Allocating to {} from the configured equity, as the account's can't be read: {}",
+                strategy.name, e
+            );
+            config.allocation.equity
+        }
+    };
+    let capital = allocation::allocate(&config.allocation, &peers, equity, history)
+        .remove(&strategy.name)
+        .unwrap_or_default();
+    info!("Capital for {}: {:?}", strategy.name, capital);
+    StrategyConfig {
+        capital,
+        ..strategy
+    }
+}
+
+fn start_strategy(
+    date: NaiveDate,
+    strategy: StrategyConfig,
+    market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
+    historical_data: Arc<impl HistoricalDataService + 'static + Send + Sync>,
+    orders: Arc<impl OrderService + 'static + Send + Sync>,
+    health: FeedHealthConfig,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut trading_service = trading::new(
+        date,
+        Strategy::with_params(&strategy.name, strategy.symbols.clone(), &strategy.params),
+        strategy.capital.clone(),
+        strategy.sizing,
+        market_data,
+        historical_data::with_prices(historical_data, strategy.prices),
+        orders,
+        health,
+        shutdown,
+    );
+    match trading_service.run() {
+        Ok(_) => (),
+        Err(e) => info!("Error starting TradingService {}: {}", strategy.name, e),
+    }
 }
@@ -0,0 +1,89 @@
+use app_config::app_config::{Allocation, AllocationMethod, Strategy};
+use domain::domain::Day;
+use log::*;
+use std::collections::HashMap;
+
+// Each strategy's limit on each of its symbols, by strategy name, given the equity of the account
+// they trade in and the symbols' daily history
+pub fn allocate(
+    allocation: &Allocation,
+    strategies: &[Strategy],
+    equity: f64,
+    history: &HashMap<String, Vec<Day>>,
+) -> HashMap<String, HashMap<String, i64>> {
+    let inverse_volatility = |symbol: &String| {
+        history
+            .get(symbol)
+            .map(|days| volatility(days, allocation.volatility_days))
+            .filter(|volatility| *volatility > 0.0)
+            .map(|volatility| 1.0 / volatility)
+    };
+    // Weighted by inverse volatility, every symbol of every strategy carries the same risk, so each
+    // strategy's weight is the sum of its symbols'
+    let weights: Vec<HashMap<String, f64>> = strategies
+        .iter()
+        .map(|strategy| match allocation.method {
+            AllocationMethod::InverseVolatility => strategy
+                .symbols
+                .iter()
+                .filter_map(|symbol| {
+                    let weight = inverse_volatility(symbol);
+                    if weight.is_none() {
+                        warn!(
+                            "No volatility for {}, so {} is allocated nothing for it",
+                            symbol, strategy.name
+                        );
+                    }
+                    weight.map(|weight| (symbol.clone(), weight))
+                })
+                .collect(),
+            _ => strategy.symbols.iter().map(|s| (s.clone(), 1.0)).collect(),
+        })
+        .collect();
+    let total: f64 = weights.iter().flat_map(|symbols| symbols.values()).sum();
+
+    strategies
+        .iter()
+        .zip(&weights)
+        .map(|(strategy, symbols)| {
+            let weight = symbols.values().sum::<f64>();
+            let budget = match allocation.method {
+                AllocationMethod::Capital => {
+                    return (strategy.name.clone(), strategy.capital.clone())
+                }
+                AllocationMethod::Fixed => strategy.budget,
+                AllocationMethod::PercentOfEquity => equity * strategy.budget_percent / 100.0,
+                AllocationMethod::InverseVolatility if total > 0.0 => {
+                    equity * allocation.percent / 100.0 * weight / total
+                }
+                AllocationMethod::InverseVolatility => 0.0,
+            };
+            info!("Allocated {:.2} to {}", budget, strategy.name);
+            let capital = symbols
+                .iter()
+                .map(|(symbol, share)| (symbol.clone(), (budget * share / weight) as i64))
+                .collect();
+            (strategy.name.clone(), capital)
+        })
+        .collect()
+}
+
+// The standard deviation of the last `days` daily returns
+pub fn volatility(history: &[Day], days: usize) -> f64 {
+    let returns: Vec<f64> = history
+        .windows(2)
+        .filter(|pair| pair[0].close > 0.0)
+        .map(|pair| pair[1].close / pair[0].close - 1.0)
+        .collect();
+    let recent = &returns[returns.len().saturating_sub(days)..];
+    if recent.len() < 2 {
+        return 0.0;
+    }
+    let mean = recent.iter().sum::<f64>() / recent.len() as f64;
+    let variance = recent.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / recent.len() as f64;
+    variance.sqrt()
+}
+
+#[cfg(test)]
+#[path = "./tests/allocation_test.rs"]
+mod allocation_test;
@@ -0,0 +1,286 @@
+use crate::market_data::MarketDataService;
+use crate::persistence::PersistenceService;
+use app_config::app_config::BarConfig;
+use chrono::{DateTime, Local, NaiveTime};
+use crossbeam_channel::{Receiver, Sender};
+use domain::domain::{AggregatedBar, Bar, EventType, Interval, MarketEvent, Quote};
+use log::*;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+};
+
+pub trait BarAggregator {
+    // Subscribes to market data and builds bars until shutdown
+    fn init(&self, shutdown: Arc<AtomicBool>) -> Result<JoinHandle<()>, String>;
+
+    // Bars of the given interval, each published once its period has ended
+    fn subscribe(&self, interval: Interval) -> Result<Receiver<Bar>, String>;
+
+    fn unsubscribe(&self, subscriber: &Receiver<Bar>) -> Result<(), String>;
+}
+
+// Bars are built from trades, or from quote mids with no volume for symbols that haven't traded, timed
+// by the events' own timestamps so that replayed data gives the same bars as it did live. A bar closes
+// once an event for any symbol arrives after its period, or once that much time has passed since the
+// last event.
+pub fn new(
+    config: &BarConfig,
+    market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
+    persistence: Arc<impl PersistenceService + 'static + Send + Sync>,
+) -> Arc<impl BarAggregator> {
+    Arc::new(implementation::Aggregator {
+        intervals: config.intervals.clone(),
+        persist: config.persist,
+        market_data,
+        persistence,
+        subscribers: Arc::new(Mutex::new(Vec::new())),
+    })
+}
+
+// The start of the period of the given interval that `time` falls in, counting from local midnight
+pub fn period_start(time: DateTime<Local>, interval: Interval) -> DateTime<Local> {
+    let midnight = time
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .expect("Failed to convert date to datetime");
+    let step = interval.duration().num_milliseconds();
+    let elapsed = (time - midnight).num_milliseconds();
+    midnight + chrono::Duration::milliseconds(elapsed / step * step)
+}
+
+// Each symbol's open bar of one interval
+pub struct Builder {
+    pub interval: Interval,
+    pub open: HashMap<String, Bar>,
+}
+
+impl Builder {
+    pub fn new(interval: Interval) -> Builder {
+        Builder {
+            interval,
+            open: HashMap::new(),
+        }
+    }
+
+    // Prices from before the open bar's period, e.g. from a stale quote, are folded into it rather
+    // than reopening a closed bar
+    pub fn add(&mut self, symbol: &str, time: DateTime<Local>, price: f64, volume: i64) {
+        match self.open.get_mut(symbol) {
+            Some(bar) => {
+                bar.high = bar.high.max(price);
+                bar.low = bar.low.min(price);
+                bar.close = price;
+                bar.volume += volume;
+            }
+            None => {
+                let bar = Bar {
+                    symbol: symbol.to_string(),
+                    timestamp: period_start(time, self.interval),
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume,
+                };
+                self.open.insert(symbol.to_string(), bar);
+            }
+        }
+    }
+
+    // Removes and returns the bars whose periods have ended by `now`, by symbol
+    pub fn close(&mut self, now: DateTime<Local>) -> Vec<Bar> {
+        let duration = self.interval.duration();
+        let mut ended: Vec<String> = self
+            .open
+            .iter()
+            .filter(|(_, bar)| bar.timestamp + duration <= now)
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        ended.sort();
+        ended
+            .iter()
+            .filter_map(|symbol| self.open.remove(symbol))
+            .collect()
+    }
+}
+
+mod implementation {
+    use super::*;
+    use crossbeam_channel::RecvTimeoutError;
+    use std::{
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    type Subscribers = Arc<Mutex<Vec<(Interval, Sender<Bar>, Receiver<Bar>)>>>;
+
+    pub struct Aggregator<
+        M: MarketDataService + 'static + Send + Sync,
+        P: PersistenceService + 'static + Send + Sync,
+    > {
+        pub intervals: Vec<Interval>,
+        pub persist: bool,
+        pub market_data: Arc<M>,
+        pub persistence: Arc<P>,
+        pub subscribers: Subscribers,
+    }
+
+    impl<M: MarketDataService + Send + Sync, P: PersistenceService + Send + Sync> BarAggregator
+        for Aggregator<M, P>
+    {
+        fn init(&self, shutdown: Arc<AtomicBool>) -> Result<JoinHandle<()>, String> {
+            let rx = self
+                .market_data
+                .subscribe_events(&[EventType::Quote, EventType::Trade])?;
+            let mut builders: Vec<Builder> = self
+                .intervals
+                .iter()
+                .map(|interval| Builder::new(*interval))
+                .collect();
+            let publisher = Publisher {
+                persist: self.persist,
+                persistence: self.persistence.clone(),
+                subscribers: self.subscribers.clone(),
+            };
+
+            Ok(thread::spawn(move || {
+                let mut clock = Clock::default();
+                let mut traded: HashSet<String> = HashSet::new();
+                while !shutdown.load(Ordering::Relaxed) {
+                    match rx.recv_timeout(Duration::from_millis(100)) {
+                        Ok(event) => {
+                            let (time, price, volume) = match &event {
+                                MarketEvent::Trade(trade) => {
+                                    traded.insert(trade.symbol.clone());
+                                    (trade.date, trade.price, trade.size)
+                                }
+                                MarketEvent::Quote(quote) if !traded.contains(&quote.symbol) => {
+                                    (quote.biddate.max(quote.askdate), mid(quote), 0)
+                                }
+                                _ => continue,
+                            };
+                            clock.observe(time);
+                            for builder in builders.iter_mut() {
+                                publisher.publish(builder.interval, builder.close(clock.now()));
+                                builder.add(event.symbol(), time, price, volume);
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            if clock.started() {
+                                for builder in builders.iter_mut() {
+                                    publisher.publish(builder.interval, builder.close(clock.now()));
+                                }
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            info!("MarketData channel disconnected");
+                            break;
+                        }
+                    }
+                }
+
+                info!("BarAggregator shutting down");
+            }))
+        }
+
+        fn subscribe(&self, interval: Interval) -> Result<Receiver<Bar>, String> {
+            if !self.intervals.contains(&interval) {
+                return Err(format!("No {} bars are built", interval));
+            }
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let subscriber = receiver.clone();
+            self.subscribers
+                .lock()
+                .map(|mut s| s.push((interval, sender, receiver)))
+                .map_err(|e| e.to_string())
+                .map(|_| subscriber)
+        }
+
+        fn unsubscribe(&self, subscriber: &Receiver<Bar>) -> Result<(), String> {
+            let mut subscribers = self.subscribers.lock().map_err(|e| e.to_string())?;
+            match subscribers
+                .iter()
+                .position(|(_, _, r)| r.same_channel(subscriber))
+            {
+                Some(index) => {
+                    subscribers.remove(index);
+                    Ok(())
+                }
+                None => Err("No such subscriber found".to_string()),
+            }
+        }
+    }
+
+    struct Publisher<P: PersistenceService> {
+        persist: bool,
+        persistence: Arc<P>,
+        subscribers: Subscribers,
+    }
+
+    impl<P: PersistenceService> Publisher<P> {
+        fn publish(&self, interval: Interval, bars: Vec<Bar>) {
+            for bar in bars {
+                for (_, sender, _) in self
+                    .subscribers
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|(i, _, _)| *i == interval)
+                {
+                    if let Err(e) = sender.send(bar.clone()) {
+                        info!("Error sending bar to subscriber: {}", e);
+                    }
+                }
+                if self.persist {
+                    if let Err(e) = self
+                        .persistence
+                        .write(Box::new(AggregatedBar { interval, bar }))
+                    {
+                        warn!("Failed to persist bar: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    // Market time: the latest quote time seen, advanced by the wall-clock time since it was seen
+    #[derive(Default)]
+    struct Clock {
+        latest: Option<(DateTime<Local>, Instant)>,
+    }
+
+    impl Clock {
+        fn observe(&mut self, time: DateTime<Local>) {
+            match self.latest {
+                Some((latest, _)) if latest >= time => (),
+                _ => self.latest = Some((time, Instant::now())),
+            }
+        }
+
+        fn started(&self) -> bool {
+            self.latest.is_some()
+        }
+
+        fn now(&self) -> DateTime<Local> {
+            match self.latest {
+                Some((latest, seen)) => {
+                    latest + chrono::Duration::from_std(seen.elapsed()).unwrap_or_default()
+                }
+                None => Local::now(),
+            }
+        }
+    }
+
+    fn mid(quote: &Quote) -> f64 {
+        (quote.bid + quote.ask) / 2.0
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/bar_aggregator_test.rs"]
+mod bar_aggregator_test;
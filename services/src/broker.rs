@@ -0,0 +1,517 @@
+use crate::fills;
+use app_config::app_config::{AccountConfig, BrokerKind, FillConfig};
+use chrono::Local;
+use crossbeam_channel::Receiver;
+use domain::domain::{Order, Position, Quote, Side};
+use log::*;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+};
+
+// What every broker offers, so that orders can be routed to any of them
+pub trait BrokerAdapter {
+    // Places a market order, returning its state once the broker has taken it
+    fn submit(&self, order: &Order) -> Result<OrderState, String>;
+    fn cancel(&self, order_id: i64) -> Result<(), String>;
+    fn status(&self, order_id: i64) -> Result<OrderState, String>;
+    // Open positions by symbol
+    fn positions(&self) -> Result<HashMap<String, Position>, String>;
+    fn balances(&self) -> Result<Balances, String>;
+    fn account(&self) -> Result<AccountInfo, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    // Accepted, but not yet filled
+    Open,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderState {
+    pub id: i64,
+    pub status: OrderStatus,
+    // Shares filled so far, at this average price
+    pub filled: i64,
+    pub avg_fill_price: Option<f64>,
+    // Charged for the shares filled, where the broker reports it
+    pub commission: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Balances {
+    pub cash: f64,
+    // Cash plus the market value of positions
+    pub equity: f64,
+    // What may be spent on stock now
+    pub buying_power: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountInfo {
+    pub account_id: String,
+    // E.g. "cash", "margin" or "paper"
+    pub account_type: String,
+    pub status: String,
+}
+
+// The broker for an account, with its credentials read from the environment
+pub fn new(config: &AccountConfig) -> Result<Arc<dyn BrokerAdapter + Send + Sync>, String> {
+    match config.broker {
+        BrokerKind::Tradier => {
+            let var = |name: &str| env::var(name).map_err(|_| format!("{} not found", name));
+            Ok(tradier(
+                var(&config.token_env)?,
+                var(&config.account_id_env)?,
+                config.host.clone(),
+            ))
+        }
+        BrokerKind::Paper => Ok(paper(config.cash)),
+    }
+}
+
+// Tradier's brokerage API at `base_url`, a host such as "api.tradier.com" or a full URL
+pub fn tradier(
+    access_token: String,
+    account_id: String,
+    base_url: String,
+) -> Arc<dyn BrokerAdapter + Send + Sync> {
+    Arc::new(implementation::Tradier {
+        access_token,
+        account_id,
+        base_url,
+    })
+}
+
+// An account held in memory that fills every order in full, at once, at the order's price
+pub fn paper(cash: f64) -> Arc<dyn BrokerAdapter + Send + Sync> {
+    paper_with(paper_book(cash), FillConfig::default(), None)
+}
+
+// The cash, positions and orders of a paper account, which outlive the brokers trading it
+pub type PaperBook = Arc<Mutex<implementation::PaperAccount>>;
+
+pub fn paper_book(cash: f64) -> PaperBook {
+    Arc::new(Mutex::new(implementation::PaperAccount {
+        cash,
+        ..Default::default()
+    }))
+}
+
+// A paper account's book carried on from its cash and open positions. Each position is valued at its
+// cost until its symbol is quoted. Order and position ids carry on from the highest of those stored,
+// so they aren't reused.
+pub fn restored_paper_book(cash: f64, positions: Vec<Position>, orders: &[Order]) -> PaperBook {
+    let last_order_id = orders
+        .iter()
+        .filter_map(|order| order.id)
+        .max()
+        .unwrap_or(0);
+    let last_position_id = positions
+        .iter()
+        .filter_map(|position| position.broker_id)
+        .max()
+        .unwrap_or(0);
+    let positions: HashMap<String, Position> = positions
+        .into_iter()
+        .filter(|position| position.quantity > 0)
+        .map(|position| (position.symbol.clone(), position))
+        .collect();
+    let marks = positions
+        .values()
+        .map(|p| (p.symbol.clone(), p.cost_basis / p.quantity as f64))
+        .collect();
+    Arc::new(Mutex::new(implementation::PaperAccount {
+        cash,
+        positions,
+        marks,
+        last_order_id,
+        last_position_id,
+        ..Default::default()
+    }))
+}
+
+// A paper account filling orders by the fill model, at the latest of the quotes given for their
+// symbol, or at the order's price until one arrives
+pub fn paper_with(
+    book: PaperBook,
+    fills: FillConfig,
+    quotes: Option<Receiver<Quote>>,
+) -> Arc<dyn BrokerAdapter + Send + Sync> {
+    Arc::new(implementation::Paper {
+        fills,
+        quotes,
+        account: book,
+    })
+}
+
+pub mod implementation {
+    use super::*;
+    use core::http::*;
+    use core::serde::one_or_many::OneOrMany;
+    use domain::domain::TradierPosition;
+    use serde::Deserialize;
+
+    pub struct Tradier {
+        pub access_token: String,
+        pub account_id: String,
+        pub base_url: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct OrderResponse {
+        order: OrderData,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct OrderData {
+        id: i64,
+        status: String,
+        #[serde(default)]
+        exec_quantity: f64,
+        avg_fill_price: Option<f64>,
+    }
+
+    // Tradier sends null positions for an empty account, and a lone object rather than an array for a
+    // single position
+    #[derive(Deserialize)]
+    struct PositionResponse {
+        positions: Option<Positions>,
+    }
+
+    #[derive(Deserialize)]
+    struct Positions {
+        position: OneOrMany<TradierPosition>,
+    }
+
+    #[derive(Deserialize)]
+    struct BalanceResponse {
+        balances: TradierBalances,
+    }
+
+    // Buying power is found under the section for the account's type
+    #[derive(Deserialize)]
+    struct TradierBalances {
+        total_cash: f64,
+        total_equity: f64,
+        margin: Option<BuyingPower>,
+        pdt: Option<BuyingPower>,
+        cash: Option<CashAvailable>,
+    }
+
+    #[derive(Deserialize)]
+    struct BuyingPower {
+        stock_buying_power: f64,
+    }
+
+    #[derive(Deserialize)]
+    struct CashAvailable {
+        cash_available: f64,
+    }
+
+    #[derive(Deserialize)]
+    struct ProfileResponse {
+        profile: Profile,
+    }
+
+    #[derive(Deserialize)]
+    struct Profile {
+        account: OneOrMany<TradierAccount>,
+    }
+
+    #[derive(Deserialize)]
+    struct TradierAccount {
+        account_number: String,
+        #[serde(rename = "type")]
+        account_type: String,
+        status: String,
+    }
+
+    impl Tradier {
+        fn url(&self, path: &str) -> String {
+            url(
+                &self.base_url,
+                &format!("/v1/accounts/{}{}", self.account_id, path),
+            )
+        }
+    }
+
+    impl BrokerAdapter for Tradier {
+        fn submit(&self, order: &Order) -> Result<OrderState, String> {
+            let body = format!(
+                "account_id={}&class=equity&symbol={}&side={}&quantity={}&type=market&duration=day",
+                self.account_id, order.symbol, order.side, order.quantity
+            );
+            let response = post::<OrderResponse>(&self.url("/orders"), &self.access_token, body)?;
+            info!("Response: {:?}", response);
+            match response.order.status.as_str() {
+                "ok" => Ok(OrderState {
+                    id: response.order.id,
+                    status: OrderStatus::Open,
+                    filled: 0,
+                    avg_fill_price: None,
+                    commission: 0.0,
+                }),
+                _ => Err(response.order.status),
+            }
+        }
+
+        fn cancel(&self, order_id: i64) -> Result<(), String> {
+            let url = self.url(&format!("/orders/{}", order_id));
+            let response = delete::<OrderResponse>(&url, &self.access_token)?;
+            match response.order.status.as_str() {
+                "ok" => Ok(()),
+                _ => Err(response.order.status),
+            }
+        }
+
+        fn status(&self, order_id: i64) -> Result<OrderState, String> {
+            let url = self.url(&format!("/orders/{}", order_id));
+            let order = get::<OrderResponse>(&url, &self.access_token)?.order;
+            let status = match order.status.as_str() {
+                "open" | "pending" => OrderStatus::Open,
+                "partially_filled" => OrderStatus::PartiallyFilled,
+                "filled" => OrderStatus::Filled,
+                "canceled" => OrderStatus::Canceled,
+                "expired" => OrderStatus::Expired,
+                "rejected" | "error" => OrderStatus::Rejected,
+                other => return Err(format!("Unknown order status {}", other)),
+            };
+            Ok(OrderState {
+                id: order.id,
+                status,
+                filled: order.exec_quantity as i64,
+                avg_fill_price: order.avg_fill_price.filter(|_| order.exec_quantity > 0.0),
+                commission: 0.0,
+            })
+        }
+
+        fn positions(&self) -> Result<HashMap<String, Position>, String> {
+            let url = self.url("/positions");
+            info!("url: {}", url);
+            let response = get::<PositionResponse>(&url, &self.access_token)?;
+            Ok(response
+                .positions
+                .map(|positions| Vec::from(positions.position))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|position| (position.symbol.clone(), position.into()))
+                .collect())
+        }
+
+        fn balances(&self) -> Result<Balances, String> {
+            let balances =
+                get::<BalanceResponse>(&self.url("/balances"), &self.access_token)?.balances;
+            let buying_power = match (&balances.margin, &balances.pdt, &balances.cash) {
+                (Some(margin), _, _) => margin.stock_buying_power,
+                (_, Some(pdt), _) => pdt.stock_buying_power,
+                (_, _, Some(cash)) => cash.cash_available,
+                _ => balances.total_cash,
+            };
+            Ok(Balances {
+                cash: balances.total_cash,
+                equity: balances.total_equity,
+                buying_power,
+            })
+        }
+
+        fn account(&self) -> Result<AccountInfo, String> {
+            let url = url(&self.base_url, "/v1/user/profile");
+            let response = get::<ProfileResponse>(&url, &self.access_token)?;
+            Vec::from(response.profile.account)
+                .into_iter()
+                .find(|account| account.account_number == self.account_id)
+                .map(|account| AccountInfo {
+                    account_id: account.account_number,
+                    account_type: account.account_type,
+                    status: account.status,
+                })
+                .ok_or(format!("Account {} not found in profile", self.account_id))
+        }
+    }
+
+    // Tradier's hosts are given without a scheme, but a full URL, e.g. of a local stand-in, is used as is
+    pub fn url(base_url: &str, path: &str) -> String {
+        if base_url.contains("://") {
+            format!("{}{}", base_url.trim_end_matches('/'), path)
+        } else {
+            format!("https://{}{}", base_url, path)
+        }
+    }
+
+    pub struct Paper {
+        pub fills: FillConfig,
+        pub quotes: Option<Receiver<Quote>>,
+        pub account: PaperBook,
+    }
+
+    #[derive(Default)]
+    pub struct PaperAccount {
+        pub cash: f64,
+        pub positions: HashMap<String, Position>,
+        pub orders: Vec<OrderState>,
+        // The price of each symbol at which its position is valued: the last quote's midpoint, or the
+        // last fill price before any quote
+        pub marks: HashMap<String, f64>,
+        pub quotes: HashMap<String, Quote>,
+        // The ids last given out, which may be higher than the counts held if the book was restored
+        pub last_order_id: i64,
+        pub last_position_id: i64,
+    }
+
+    impl Paper {
+        fn lock(&self) -> Result<std::sync::MutexGuard<'_, PaperAccount>, String> {
+            let mut account = self.account.lock().map_err(|e| e.to_string())?;
+            if let Some(quotes) = &self.quotes {
+                for quote in quotes.try_iter() {
+                    account
+                        .marks
+                        .insert(quote.symbol.clone(), (quote.bid + quote.ask) / 2.0);
+                    account.quotes.insert(quote.symbol.clone(), quote);
+                }
+            }
+            Ok(account)
+        }
+    }
+
+    impl BrokerAdapter for Paper {
+        fn submit(&self, order: &Order) -> Result<OrderState, String> {
+            if order.quantity <= 0 {
+                return Err(format!("Invalid quantity {}", order.quantity));
+            }
+            let mut account = self.lock()?;
+            // Buys take the ask and sells the bid
+            let quoted = account
+                .quotes
+                .get(&order.symbol)
+                .map(|quote| match order.side {
+                    Side::Buy => quote.ask,
+                    Side::Sell => quote.bid,
+                })
+                .or(order.px)
+                .filter(|px| *px > 0.0)
+                .ok_or(format!("No price to fill paper order: {:?}", order))?;
+            let fill = fills::fill(&self.fills, &order.side, order.quantity, quoted);
+            let value = fill.price * order.quantity as f64;
+            let held = account.positions.get(&order.symbol).cloned();
+            let position = match (&order.side, held) {
+                (Side::Buy, _) if value + fill.commission > account.cash => {
+                    return Err(format!(
+                        "Insufficient cash for {} {}: {:.2} available",
+                        order.quantity, order.symbol, account.cash
+                    ))
+                }
+                (Side::Buy, Some(position)) => Position {
+                    quantity: position.quantity + order.quantity,
+                    cost_basis: position.cost_basis + value + fill.commission,
+                    ..position
+                },
+                (Side::Buy, None) => Position {
+                    broker_id: Some(account.last_position_id + 1),
+                    symbol: order.symbol.clone(),
+                    quantity: order.quantity,
+                    cost_basis: value + fill.commission,
+                    date: Local::now(),
+                    account: String::new(),
+                },
+                (Side::Sell, Some(position)) if order.quantity <= position.quantity => {
+                    let remaining = position.quantity - order.quantity;
+                    Position {
+                        quantity: remaining,
+                        cost_basis: position.cost_basis * remaining as f64
+                            / position.quantity as f64,
+                        ..position
+                    }
+                }
+                (Side::Sell, held) => {
+                    return Err(format!(
+                        "Cannot sell {} {}, holding {}",
+                        order.quantity,
+                        order.symbol,
+                        held.map(|p| p.quantity).unwrap_or(0)
+                    ))
+                }
+            };
+
+            account.cash += match order.side {
+                Side::Buy => -value,
+                Side::Sell => value,
+            } - fill.commission;
+            if !account.quotes.contains_key(&order.symbol) {
+                account.marks.insert(order.symbol.clone(), fill.price);
+            }
+            account.last_position_id = account
+                .last_position_id
+                .max(position.broker_id.unwrap_or(0));
+            account.positions.insert(order.symbol.clone(), position);
+            account.last_order_id += 1;
+            let state = OrderState {
+                id: account.last_order_id,
+                status: OrderStatus::Filled,
+                filled: order.quantity,
+                avg_fill_price: Some(fill.price),
+                commission: fill.commission,
+            };
+            account.orders.push(state.clone());
+            Ok(state)
+        }
+
+        // Paper orders fill as they're placed, so there's never one left to cancel
+        fn cancel(&self, order_id: i64) -> Result<(), String> {
+            self.status(order_id)
+                .and_then(|order| Err(format!("Order {} is already filled", order.id)))
+        }
+
+        fn status(&self, order_id: i64) -> Result<OrderState, String> {
+            let account = self.lock()?;
+            account
+                .orders
+                .iter()
+                .find(|order| order.id == order_id)
+                .cloned()
+                .ok_or(format!("No order {}", order_id))
+        }
+
+        fn positions(&self) -> Result<HashMap<String, Position>, String> {
+            let account = self.lock()?;
+            Ok(account
+                .positions
+                .iter()
+                .filter(|(_, position)| position.quantity != 0)
+                .map(|(symbol, position)| (symbol.clone(), position.clone()))
+                .collect())
+        }
+
+        fn balances(&self) -> Result<Balances, String> {
+            let account = self.lock()?;
+            let value: f64 = account
+                .positions
+                .values()
+                .map(|p| p.quantity as f64 * account.marks.get(&p.symbol).unwrap_or(&0.0))
+                .sum();
+            Ok(Balances {
+                cash: account.cash,
+                equity: account.cash + value,
+                buying_power: account.cash,
+            })
+        }
+
+        fn account(&self) -> Result<AccountInfo, String> {
+            Ok(AccountInfo {
+                account_id: "paper".to_string(),
+                account_type: "paper".to_string(),
+                status: "active".to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/broker_test.rs"]
+mod broker_test;
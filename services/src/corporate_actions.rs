@@ -0,0 +1,273 @@
+use crate::historical_data::{
+    implementation::{in_parallel, with_retries},
+    Fetched,
+};
+use app_config::app_config::{CorporateActionSource, CorporateActionsConfig, FetchConfig};
+use chrono::NaiveDate;
+use core::rate_limiter::TokenBucket;
+use domain::domain::{Action, Bar, CorporateAction, Day, Quote};
+use log::*;
+use std::collections::HashMap;
+
+// Multipliers for prices and volumes before an ex-date
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustment {
+    pub ex_date: NaiveDate,
+    pub price: f64,
+    pub volume: f64,
+}
+
+// Each symbol's splits and dividends, in ex-date order, with the reason for each symbol whose actions
+// couldn't be loaded
+pub fn load(
+    config: &CorporateActionsConfig,
+    fetch: &FetchConfig,
+    access_token: &str,
+    symbols: &[String],
+) -> Fetched<Vec<CorporateAction>> {
+    let mut actions = match config.source {
+        CorporateActionSource::None => Fetched {
+            data: HashMap::new(),
+            errors: HashMap::new(),
+        },
+        CorporateActionSource::File => match implementation::read_file(&config.path, symbols) {
+            Ok(data) => Fetched {
+                data,
+                errors: HashMap::new(),
+            },
+            Err(e) => Fetched {
+                data: HashMap::new(),
+                errors: symbols.iter().map(|s| (s.clone(), e.clone())).collect(),
+            },
+        },
+        CorporateActionSource::Tradier => implementation::fetch_all(fetch, symbols, |url| {
+            implementation::fetch_tables(url, access_token)
+        }),
+    };
+    actions
+        .data
+        .values_mut()
+        .for_each(|actions| actions.sort_by_key(|a| a.ex_date));
+    actions
+}
+
+// The back-adjustment for each action, computed from raw daily history. A split scales earlier prices
+// by the inverse of its ratio and earlier volumes by the ratio; a dividend scales earlier prices by
+// (1 - dividend / previous close), so returns across the ex-date are total returns.
+pub fn adjustments(days: &[Day], actions: &[CorporateAction]) -> Vec<Adjustment> {
+    let mut days: Vec<&Day> = days.iter().collect();
+    days.sort_by_key(|day| day.date);
+    actions
+        .iter()
+        .filter_map(|action| {
+            let (price, volume) = match action.action {
+                Action::Split(ratio) if ratio > 0.0 => (1.0 / ratio, ratio),
+                Action::Dividend(amount) => {
+                    let previous = days
+                        .iter()
+                        .take_while(|day| day.date < action.ex_date)
+                        .last()?;
+                    if amount <= 0.0 || amount >= previous.close {
+                        warn!("Ignoring implausible dividend: {:?}", action);
+                        return None;
+                    }
+                    (1.0 - amount / previous.close, 1.0)
+                }
+                _ => {
+                    warn!("Ignoring invalid split: {:?}", action);
+                    return None;
+                }
+            };
+            Some(Adjustment {
+                ex_date: action.ex_date,
+                price,
+                volume,
+            })
+        })
+        .collect()
+}
+
+pub fn adjust(days: &[Day], adjustments: &[Adjustment]) -> Vec<Day> {
+    days.iter()
+        .map(|day| {
+            let (price, volume) = implementation::factors(day.date, adjustments);
+            Day {
+                open: day.open * price,
+                high: day.high * price,
+                low: day.low * price,
+                close: day.close * price,
+                volume: (day.volume as f64 * volume).round() as i64,
+                ..day.clone()
+            }
+        })
+        .collect()
+}
+
+pub fn adjust_bars(bars: &[Bar], adjustments: &[Adjustment]) -> Vec<Bar> {
+    bars.iter()
+        .map(|bar| {
+            let (price, volume) = implementation::factors(bar.date(), adjustments);
+            Bar {
+                open: bar.open * price,
+                high: bar.high * price,
+                low: bar.low * price,
+                close: bar.close * price,
+                volume: (bar.volume as f64 * volume).round() as i64,
+                ..bar.clone()
+            }
+        })
+        .collect()
+}
+
+// A quote received on `date`, adjusted as that day's bar would be
+pub fn adjust_quote(quote: &Quote, date: NaiveDate, adjustments: &[Adjustment]) -> Quote {
+    let (price, volume) = implementation::factors(date, adjustments);
+    Quote {
+        bid: quote.bid * price,
+        ask: quote.ask * price,
+        bidsz: (quote.bidsz as f64 * volume).round() as i64,
+        asksz: (quote.asksz as f64 * volume).round() as i64,
+        ..quote.clone()
+    }
+}
+
+mod implementation {
+    use super::*;
+    use core::serde::{one_or_many::OneOrMany, string_date_format};
+    use serde::Deserialize;
+
+    // Combined multipliers for a date, from every adjustment with a later ex-date
+    pub fn factors(date: NaiveDate, adjustments: &[Adjustment]) -> (f64, f64) {
+        adjustments
+            .iter()
+            .filter(|a| a.ex_date > date)
+            .fold((1.0, 1.0), |(price, volume), a| {
+                (price * a.price, volume * a.volume)
+            })
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Record {
+        pub symbol: String,
+        #[serde(with = "string_date_format")]
+        pub ex_date: NaiveDate,
+        pub action: String,
+        pub value: f64,
+    }
+
+    pub fn read_file(
+        path: &str,
+        symbols: &[String],
+    ) -> Result<HashMap<String, Vec<CorporateAction>>, String> {
+        let mut reader = csv::Reader::from_path(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut actions: HashMap<String, Vec<CorporateAction>> = HashMap::new();
+        for record in reader.deserialize::<Record>() {
+            let record = record.map_err(|e| format!("{}: {}", path, e))?;
+            if !symbols.contains(&record.symbol) {
+                continue;
+            }
+            let action = match record.action.as_str() {
+                "split" => Action::Split(record.value),
+                "dividend" => Action::Dividend(record.value),
+                other => return Err(format!("{}: Unknown action '{}'", path, other)),
+            };
+            actions
+                .entry(record.symbol.clone())
+                .or_default()
+                .push(CorporateAction {
+                    symbol: record.symbol,
+                    ex_date: record.ex_date,
+                    action,
+                });
+        }
+        Ok(actions)
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct FundamentalsResponse {
+        pub results: Option<Vec<FundamentalsResult>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct FundamentalsResult {
+        pub tables: Option<Tables>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct Tables {
+        #[serde(default)]
+        pub cash_dividends: Option<OneOrMany<CashDividend>>,
+        #[serde(default)]
+        pub stock_splits: Option<OneOrMany<StockSplit>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct CashDividend {
+        #[serde(with = "string_date_format")]
+        pub ex_date: NaiveDate,
+        pub cash_amount: f64,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StockSplit {
+        #[serde(with = "string_date_format")]
+        pub ex_date: NaiveDate,
+        pub split_from: f64,
+        pub split_to: f64,
+    }
+
+    // Fetches every symbol's actions through `get`, rate limited and retried as history is
+    pub fn fetch_all(
+        fetch: &FetchConfig,
+        symbols: &[String],
+        get: impl Fn(&str) -> Result<Vec<Tables>, String> + Sync,
+    ) -> Fetched<Vec<CorporateAction>> {
+        let limiter = TokenBucket::new(fetch.requests_per_second, fetch.burst);
+        in_parallel(fetch.concurrency, symbols, |symbol| {
+            fetch_one(symbol, |url| with_retries(fetch, &limiter, || get(url)))
+        })
+    }
+
+    pub fn fetch_one(
+        symbol: &str,
+        get: impl Fn(&str) -> Result<Vec<Tables>, String>,
+    ) -> Result<Vec<CorporateAction>, String> {
+        let base = "https://api.tradier.com/beta/markets/fundamentals";
+        let dividends = get(&format!("{}/dividends?symbols={}", base, symbol))?;
+        let splits = get(&format!("{}/corporate_actions?symbols={}", base, symbol))?;
+
+        let dividends = dividends
+            .into_iter()
+            .filter_map(|tables| tables.cash_dividends)
+            .flat_map(Vec::from)
+            .map(|dividend| CorporateAction {
+                symbol: symbol.to_string(),
+                ex_date: dividend.ex_date,
+                action: Action::Dividend(dividend.cash_amount),
+            });
+        let splits = splits
+            .into_iter()
+            .filter_map(|tables| tables.stock_splits)
+            .flat_map(Vec::from)
+            .map(|split| CorporateAction {
+                symbol: symbol.to_string(),
+                ex_date: split.ex_date,
+                action: Action::Split(split.split_to / split.split_from),
+            });
+        Ok(dividends.chain(splits).collect())
+    }
+
+    pub fn fetch_tables(url: &str, access_token: &str) -> Result<Vec<Tables>, String> {
+        core::http::get::<Vec<FundamentalsResponse>>(url, access_token).map(|responses| {
+            responses
+                .into_iter()
+                .flat_map(|response| response.results.unwrap_or_default())
+                .filter_map(|result| result.tables)
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/corporate_actions_test.rs"]
+mod corporate_actions_test;
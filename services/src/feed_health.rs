@@ -0,0 +1,279 @@
+use crate::market_data::MarketDataService;
+use app_config::app_config::FeedHealthConfig;
+use chrono::{DateTime, Local};
+use core::calendar;
+use crossbeam_channel::{Receiver, Sender};
+use domain::domain::Quote;
+use log::*;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+};
+
+// Why a quote isn't fit to trade on
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteProblem {
+    // A bid or ask of zero or less
+    Invalid,
+    // The bid is above the ask
+    Crossed,
+    // The bid equals the ask
+    Locked,
+    // The newer of the bid and ask is this old
+    Lagging(chrono::Duration),
+}
+
+impl QuoteProblem {
+    fn same_kind(&self, other: &QuoteProblem) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl Display for QuoteProblem {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            QuoteProblem::Invalid => write!(f, "invalid prices"),
+            QuoteProblem::Crossed => write!(f, "crossed market"),
+            QuoteProblem::Locked => write!(f, "locked market"),
+            QuoteProblem::Lagging(lag) => write!(f, "lagging by {}ms", lag.num_milliseconds()),
+        }
+    }
+}
+
+// What's wrong with a quote as of `now`, if anything
+pub fn assess(
+    quote: &Quote,
+    now: DateTime<Local>,
+    config: &FeedHealthConfig,
+) -> Option<QuoteProblem> {
+    let lag = now - quote.biddate.max(quote.askdate);
+    if quote.bid <= 0.0 || quote.ask <= 0.0 {
+        Some(QuoteProblem::Invalid)
+    } else if quote.bid > quote.ask {
+        Some(QuoteProblem::Crossed)
+    } else if quote.bid == quote.ask && !config.allow_locked {
+        Some(QuoteProblem::Locked)
+    } else if config.max_lag_secs > 0.0
+        && lag.num_milliseconds() as f64 > config.max_lag_secs * 1000.0
+    {
+        Some(QuoteProblem::Lagging(lag))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    // No quote since `since`, or since monitoring began
+    Silent {
+        symbol: String,
+        since: DateTime<Local>,
+    },
+    Unhealthy {
+        symbol: String,
+        problem: QuoteProblem,
+    },
+    // Quotes are arriving, and fit to trade on, again
+    Recovered {
+        symbol: String,
+    },
+}
+
+pub trait FeedHealthMonitor {
+    // Watches the quotes for the symbols until shutdown
+    fn init(
+        &self,
+        shutdown: Arc<AtomicBool>,
+        symbols: Vec<String>,
+    ) -> Result<JoinHandle<()>, String>;
+
+    fn add_symbols(&self, symbols: &[String]) -> Result<(), String>;
+
+    // Alerts as they're raised: once when a symbol's quotes go wrong, and once when they recover
+    fn subscribe(&self) -> Result<Receiver<Alert>, String>;
+}
+
+// Alerts are logged as well as sent to subscribers
+pub fn new(
+    config: &FeedHealthConfig,
+    market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
+) -> Arc<impl FeedHealthMonitor> {
+    Arc::new(implementation::Monitor {
+        tracker: Arc::new(Mutex::new(Tracker::new(*config, Local::now()))),
+        market_data,
+        subscribers: Arc::new(Mutex::new(vec![])),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    Healthy,
+    Silent,
+    Unhealthy(QuoteProblem),
+}
+
+// Each symbol's last quote and state, from which alerts are raised as the state changes
+pub struct Tracker {
+    config: FeedHealthConfig,
+    started: DateTime<Local>,
+    symbols: HashMap<String, (Option<DateTime<Local>>, Status)>,
+}
+
+impl Tracker {
+    pub fn new(config: FeedHealthConfig, started: DateTime<Local>) -> Tracker {
+        Tracker {
+            config,
+            started,
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn add_symbol(&mut self, symbol: &str) {
+        self.symbols
+            .entry(symbol.to_string())
+            .or_insert((None, Status::Healthy));
+    }
+
+    pub fn last_update(&self, symbol: &str) -> Option<DateTime<Local>> {
+        self.symbols.get(symbol).and_then(|(last, _)| *last)
+    }
+
+    pub fn observe(&mut self, quote: &Quote, now: DateTime<Local>) -> Option<Alert> {
+        let config = self.config;
+        let (last, status) = self
+            .symbols
+            .entry(quote.symbol.clone())
+            .or_insert((None, Status::Healthy));
+        *last = Some(now);
+        let symbol = quote.symbol.clone();
+        match (assess(quote, now, &config), &status) {
+            (None, Status::Healthy) => None,
+            (None, _) => {
+                *status = Status::Healthy;
+                Some(Alert::Recovered { symbol })
+            }
+            (Some(problem), Status::Unhealthy(previous)) if problem.same_kind(previous) => None,
+            (Some(problem), _) => {
+                *status = Status::Unhealthy(problem.clone());
+                Some(Alert::Unhealthy { symbol, problem })
+            }
+        }
+    }
+
+    // Symbols that have gone quiet during the regular session, by symbol
+    pub fn check(&mut self, now: DateTime<Local>) -> Vec<Alert> {
+        if !calendar::in_regular_session(&now) {
+            return vec![];
+        }
+        // Quiet since before the session opened counts from the open, on the exchange's clock
+        let open = now - (calendar::exchange_time(&now).time() - calendar::regular_open());
+        let max_silence = self.config.max_silence_secs * 1000.0;
+        let mut symbols: Vec<String> = self.symbols.keys().cloned().collect();
+        symbols.sort();
+        let mut alerts = vec![];
+        for symbol in symbols {
+            let (last, status) = self.symbols.get_mut(&symbol).unwrap();
+            let since = last.unwrap_or(self.started);
+            let quiet = now - since.max(open);
+            if *status != Status::Silent && quiet.num_milliseconds() as f64 > max_silence {
+                *status = Status::Silent;
+                alerts.push(Alert::Silent { symbol, since });
+            }
+        }
+        alerts
+    }
+}
+
+mod implementation {
+    use super::*;
+    use crossbeam_channel::RecvTimeoutError;
+    use std::{
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    pub struct Monitor<M: MarketDataService + 'static + Send + Sync> {
+        pub tracker: Arc<Mutex<Tracker>>,
+        pub market_data: Arc<M>,
+        pub subscribers: Arc<Mutex<Vec<Sender<Alert>>>>,
+    }
+
+    impl<M: MarketDataService + 'static + Send + Sync> FeedHealthMonitor for Monitor<M> {
+        fn init(
+            &self,
+            shutdown: Arc<AtomicBool>,
+            symbols: Vec<String>,
+        ) -> Result<JoinHandle<()>, String> {
+            self.add_symbols(&symbols)?;
+            let rx = self.market_data.subscribe()?;
+            let market_data = self.market_data.clone();
+            let tracker = self.tracker.clone();
+            let subscribers = self.subscribers.clone();
+
+            Ok(thread::spawn(move || {
+                let mut checked = Instant::now();
+                while !shutdown.load(Ordering::Relaxed) {
+                    match rx.recv_timeout(Duration::from_millis(100)) {
+                        Ok(quote) => {
+                            let alert = tracker.lock().unwrap().observe(&quote, Local::now());
+                            alert.into_iter().for_each(|a| raise(a, &subscribers));
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => {
+                            info!("MarketData channel disconnected");
+                            break;
+                        }
+                    }
+                    if checked.elapsed() >= Duration::from_secs(1) {
+                        let alerts = tracker.lock().unwrap().check(Local::now());
+                        alerts.into_iter().for_each(|a| raise(a, &subscribers));
+                        checked = Instant::now();
+                    }
+                }
+
+                // The feed mustn't wait on a queue no longer read
+                if let Err(e) = market_data.unsubscribe(&rx) {
+                    info!("Error unsubscribing: {}", e);
+                }
+                info!("FeedHealthMonitor shutting down");
+            }))
+        }
+
+        fn add_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            let mut tracker = self.tracker.lock().map_err(|e| e.to_string())?;
+            symbols.iter().for_each(|symbol| tracker.add_symbol(symbol));
+            Ok(())
+        }
+
+        fn subscribe(&self) -> Result<Receiver<Alert>, String> {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            self.subscribers
+                .lock()
+                .map(|mut s| s.push(sender))
+                .map_err(|e| e.to_string())
+                .map(|_| receiver)
+        }
+    }
+
+    // Subscribers that have gone away are dropped
+    fn raise(alert: Alert, subscribers: &Mutex<Vec<Sender<Alert>>>) {
+        match &alert {
+            Alert::Recovered { symbol } => info!("Quotes for {} have recovered", symbol),
+            Alert::Silent { symbol, since } => warn!("No quotes for {} since {}", symbol, since),
+            Alert::Unhealthy { symbol, problem } => {
+                warn!("Quotes for {} unfit to trade on: {}", symbol, problem)
+            }
+        }
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(alert.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/feed_health_test.rs"]
+mod feed_health_test;
@@ -0,0 +1,143 @@
+use app_config::app_config::{FileFormat, HistoricalDataConfig};
+use chrono::NaiveDate;
+use domain::domain::Day;
+use log::*;
+use std::{collections::HashMap, path::Path};
+
+// Daily history from a directory of per-symbol files, for backtesting offline or on vendor data
+pub fn load(
+    config: &HistoricalDataConfig,
+    symbols: Vec<String>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> HashMap<String, Vec<Day>> {
+    info!("Loading from {} to {} from {}", start, end, config.path);
+    symbols
+        .iter()
+        .filter_map(|symbol| {
+            load_one(config, symbol, start, end)
+                .map_err(|e| error!("Failed to load historical data: {}", e))
+                .ok()
+                .map(|data| (symbol.clone(), data))
+        })
+        .collect::<HashMap<String, Vec<Day>>>()
+}
+
+pub fn load_one(
+    config: &HistoricalDataConfig,
+    symbol: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<Day>, String> {
+    let path = Path::new(&config.path).join(format!("{}.{}", symbol, config.format.extension()));
+    let rows = match config.format {
+        FileFormat::Csv => implementation::read_csv(&path),
+        FileFormat::Parquet => implementation::read_parquet(&path, &config.date_format),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut days = rows
+        .iter()
+        .map(|row| implementation::to_day(config, symbol, row))
+        .collect::<Result<Vec<Day>, String>>()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    days.retain(|day| day.date >= start && day.date <= end);
+    days.sort_by_key(|day| day.date);
+    Ok(days)
+}
+
+mod implementation {
+    use super::*;
+    use chrono::{DateTime, NaiveDateTime};
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+    use std::fs::File;
+
+    // A file row as column name -> value, before mapping onto a Day
+    pub type Row = HashMap<String, String>;
+
+    pub fn read_csv(path: &Path) -> Result<Vec<Row>, String> {
+        let mut reader = csv::Reader::from_path(path).map_err(|e| e.to_string())?;
+        let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+        reader
+            .records()
+            .map(|record| {
+                record.map_err(|e| e.to_string()).map(|record| {
+                    headers
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    pub fn read_parquet(path: &Path, date_format: &str) -> Result<Vec<Row>, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let reader = SerializedFileReader::new(file).map_err(|e| e.to_string())?;
+        reader
+            .get_row_iter(None)
+            .map_err(|e| e.to_string())?
+            .map(|row| {
+                row.map_err(|e| e.to_string()).map(|row| {
+                    row.get_column_iter()
+                        .map(|(name, field)| (name.clone(), field_to_string(field, date_format)))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    // Dates and timestamps are rendered in date_format, keeping any time of day it includes, so a date
+    // column is read the same way whatever the Parquet type
+    pub fn field_to_string(field: &Field, date_format: &str) -> String {
+        let time = match field {
+            Field::Str(s) => return s.clone(),
+            Field::Date(days) => DateTime::from_timestamp(*days as i64 * 86400, 0),
+            Field::TimestampMillis(millis) => DateTime::from_timestamp_millis(*millis),
+            Field::TimestampMicros(micros) => DateTime::from_timestamp_micros(*micros),
+            other => return other.to_string(),
+        };
+        time.map(|t| t.format(date_format).to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn to_day(config: &HistoricalDataConfig, symbol: &str, row: &Row) -> Result<Day, String> {
+        let columns = &config.columns;
+        let get = |column: &str| {
+            row.get(column)
+                .ok_or_else(|| format!("No column '{}'", column))
+        };
+        let number = |column: &str| {
+            get(column).and_then(|value| {
+                value
+                    .parse::<f64>()
+                    .map_err(|e| format!("Invalid {} '{}': {}", column, value, e))
+            })
+        };
+
+        Ok(Day {
+            symbol: Some(symbol.to_string()),
+            date: parse_date(get(&columns.date)?, &config.date_format)?,
+            open: number(&columns.open)?,
+            high: number(&columns.high)?,
+            low: number(&columns.low)?,
+            close: number(&columns.close)?,
+            volume: number(&columns.volume)? as i64,
+        })
+    }
+
+    // Accepts formats that include a time of day, e.g. "%Y-%m-%d %H:%M:%S"
+    pub fn parse_date(value: &str, format: &str) -> Result<NaiveDate, String> {
+        NaiveDate::parse_from_str(value, format)
+            .or_else(|_| NaiveDateTime::parse_from_str(value, format).map(|t| t.date()))
+            .map_err(|e| format!("Invalid date '{}' for format '{}': {}", value, format, e))
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/file_data_test.rs"]
+mod file_data_test;
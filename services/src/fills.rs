@@ -0,0 +1,36 @@
+use app_config::app_config::{CommissionConfig, FillConfig};
+use domain::domain::Side;
+
+// What an order gets for the price quoted on its side: the ask for buys, the bid for sells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    pub price: f64,
+    pub commission: f64,
+}
+
+// The same model fills backtest and paper orders, so that their results can be compared
+pub fn fill(config: &FillConfig, side: &Side, quantity: i64, quoted: f64) -> Fill {
+    let slippage = quoted * config.slippage_bps / 10_000.0;
+    let price = match side {
+        Side::Buy => quoted + slippage,
+        Side::Sell => quoted - slippage,
+    };
+    Fill {
+        price,
+        commission: commission(&config.commission, quantity),
+    }
+}
+
+pub fn commission(config: &CommissionConfig, quantity: i64) -> f64 {
+    let charged = config.per_order + config.per_share * quantity.abs() as f64;
+    // A free trade stays free, whatever the minimum
+    if charged > 0.0 {
+        charged.max(config.minimum)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/fills_test.rs"]
+mod fills_test;
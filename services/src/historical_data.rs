@@ -1,59 +1,279 @@
-use chrono::NaiveDate;
-use domain::domain::Day;
+use crate::{
+    corporate_actions, file_data,
+    history_cache::{self, HistoryCache},
+    validation,
+};
+use app_config::app_config::{HistoricalDataConfig, HistoricalSource};
+use chrono::{DateTime, Local, NaiveDate};
+use core::rate_limiter::TokenBucket;
+use domain::domain::{Bar, CorporateAction, Day, Interval, PriceAdjustment};
 use log::*;
 use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_LENGTH};
 use serde::Deserialize;
 use std::{collections::HashMap, sync::Arc};
 
 pub trait HistoricalDataService {
-    fn fetch(&self, end: NaiveDate) -> Arc<HashMap<String, Vec<Day>>>;
+    // Bars completed before `as_of`: a strategy trading on `as_of` must never see that day's own bar, as
+    // its close is not known until the session ends.
+    fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>>;
+
+    // Splits and dividends for the symbols served, by symbol, in ex-date order
+    fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+        Arc::new(HashMap::new())
+    }
 }
 
 pub fn new(
+    config: &HistoricalDataConfig,
     access_token: String,
     symbols: Vec<String>,
     range: i64,
     end: NaiveDate,
 ) -> Arc<impl HistoricalDataService> {
-    let history = fetch(access_token.as_str(), symbols, range, end);
+    // Without corporate actions, adjusted prices are simply raw prices, so carry on regardless
+    let actions = corporate_actions::load(
+        &config.corporate_actions,
+        &config.fetch,
+        &access_token,
+        &symbols,
+    );
+    actions.errors.iter().for_each(|(symbol, e)| {
+        warn!(
+            "Failed to load corporate actions for {}, its prices won't be adjusted: {}",
+            symbol, e
+        )
+    });
+    let actions = actions.data;
+    let start = end - chrono::Duration::days(range);
+    let history = match config.source {
+        HistoricalSource::Tradier => {
+            fetch(config, access_token.as_str(), symbols, range, end).report()
+        }
+        HistoricalSource::File => file_data::load(config, symbols, start, end),
+    };
+    let history = validation::validate_all(&config.validation, history, start, end, &actions);
     Arc::new(implementation::HistoricalData {
         history: Arc::new(history),
+        actions: Arc::new(actions),
     })
 }
 
+// The underlying history as a strategy wants it. Adjusted history is back-adjusted for the actions up
+// to and including `as_of`, so it lines up with the raw prices being traded on that date.
+pub fn with_prices(
+    underlying: Arc<impl HistoricalDataService + 'static + Send + Sync>,
+    prices: PriceAdjustment,
+) -> Arc<impl HistoricalDataService + 'static + Send + Sync> {
+    Arc::new(implementation::PricedHistoricalData { underlying, prices })
+}
+
+// Whatever could be fetched, with the reason for each symbol that couldn't be
+#[derive(Debug)]
+pub struct Fetched<T> {
+    pub data: HashMap<String, T>,
+    pub errors: HashMap<String, String>,
+}
+
+impl<T> Fetched<T> {
+    // Logs the errors and returns the data
+    pub fn report(self) -> HashMap<String, T> {
+        info!(
+            "Fetched {} of {} symbols",
+            self.data.len(),
+            self.data.len() + self.errors.len()
+        );
+        self.errors.iter().for_each(|(symbol, e)| {
+            error!("Failed to fetch historical data for {}: {}", symbol, e)
+        });
+        self.data
+    }
+}
+
 pub fn fetch(
+    config: &HistoricalDataConfig,
     access_token: &str,
     symbols: Vec<String>,
     range: i64,
     end: NaiveDate,
-) -> HashMap<String, Vec<Day>> {
+) -> Fetched<Vec<Day>> {
     let start = end - chrono::Duration::days(range);
     info!("Fetching from {} to {}", start, end);
-    symbols
-        .iter()
-        .map(|symbol| {
-            let data = implementation::fetch_one(access_token, symbol, start, end)
-                .expect("Failed to fetch historical data");
-            (symbol.clone(), data)
+    let cache = history_cache::new(&config.cache, Local::now().date_naive());
+    let limiter = TokenBucket::new(config.fetch.requests_per_second, config.fetch.burst);
+    implementation::in_parallel(config.fetch.concurrency, &symbols, |symbol| {
+        cache.get(symbol, Interval::Daily, start, end, |start, end| {
+            implementation::with_retries(&config.fetch, &limiter, || {
+                implementation::fetch_one(access_token, symbol, start, end)
+                    .map_err(|e| e.to_string())
+            })
         })
-        .collect::<HashMap<String, Vec<Day>>>()
+    })
 }
 
-mod implementation {
+// Bars of any interval over [start, end]. Note that Tradier keeps limited intraday history: roughly 20
+// trading days of 1-minute bars and 40 of 5- and 15-minute bars.
+pub fn fetch_bars(
+    config: &HistoricalDataConfig,
+    access_token: &str,
+    symbols: Vec<String>,
+    interval: Interval,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Fetched<Vec<Bar>> {
+    info!("Fetching {} bars from {} to {}", interval, start, end);
+    let cache = history_cache::new(&config.cache, Local::now().date_naive());
+    let limiter = TokenBucket::new(config.fetch.requests_per_second, config.fetch.burst);
+    implementation::in_parallel(
+        config.fetch.concurrency,
+        &symbols,
+        |symbol| match interval {
+            Interval::Daily => cache
+                .get(symbol, interval, start, end, |start, end| {
+                    implementation::with_retries(&config.fetch, &limiter, || {
+                        implementation::fetch_one(access_token, symbol, start, end)
+                            .map_err(|e| e.to_string())
+                    })
+                })
+                .map(|days| days.into_iter().map(Bar::from).collect()),
+            _ => cache.get(symbol, interval, start, end, |start, end| {
+                implementation::with_retries(&config.fetch, &limiter, || {
+                    implementation::fetch_timesales(access_token, symbol, interval, start, end)
+                        .map_err(|e| e.to_string())
+                })
+            }),
+        },
+    )
+}
+
+pub mod implementation {
     use super::*;
+    use app_config::app_config::FetchConfig;
+    use core::serde::one_or_many::OneOrMany;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    // Runs `fetch` for every symbol on up to `concurrency` threads
+    pub fn in_parallel<T: Send>(
+        concurrency: usize,
+        symbols: &[String],
+        fetch: impl Fn(&str) -> Result<T, String> + Sync,
+    ) -> Fetched<T> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(symbols.len()));
+        thread::scope(|scope| {
+            for _ in 0..concurrency.clamp(1, symbols.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(symbol) = symbols.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        let result = fetch(symbol);
+                        results.lock().unwrap().push((symbol.clone(), result));
+                    }
+                });
+            }
+        });
+
+        let mut fetched = Fetched {
+            data: HashMap::new(),
+            errors: HashMap::new(),
+        };
+        for (symbol, result) in results.into_inner().unwrap() {
+            match result {
+                Ok(data) => {
+                    fetched.data.insert(symbol, data);
+                }
+                Err(e) => {
+                    fetched.errors.insert(symbol, e);
+                }
+            }
+        }
+        fetched
+    }
+
+    // Makes a rate-limited request, retrying failures with exponential backoff
+    pub fn with_retries<T>(
+        config: &FetchConfig,
+        limiter: &TokenBucket,
+        request: impl Fn() -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut delay = Duration::from_millis(config.retry_delay_ms);
+        let mut attempts = 0;
+        loop {
+            limiter.acquire();
+            attempts += 1;
+            match request() {
+                Ok(response) => return Ok(response),
+                Err(e) if attempts <= config.retries => {
+                    warn!("Request failed, retrying in {:?}: {}", delay, e);
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) => return Err(format!("Failed after {} attempts: {}", attempts, e)),
+            }
+        }
+    }
 
     pub struct HistoricalData {
         pub history: Arc<HashMap<String, Vec<Day>>>,
+        pub actions: Arc<HashMap<String, Vec<CorporateAction>>>,
+    }
+
+    pub struct PricedHistoricalData<H: HistoricalDataService + 'static + Send + Sync> {
+        pub underlying: Arc<H>,
+        pub prices: PriceAdjustment,
+    }
+
+    impl<H: HistoricalDataService + 'static + Send + Sync> HistoricalDataService
+        for PricedHistoricalData<H>
+    {
+        fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+            let history = self.underlying.fetch_as_of(as_of);
+            if self.prices == PriceAdjustment::Raw {
+                return history;
+            }
+            let actions = self.underlying.corporate_actions();
+            let history = history
+                .iter()
+                .map(|(symbol, days)| {
+                    let known: Vec<CorporateAction> = actions
+                        .get(symbol)
+                        .map(|actions| {
+                            actions
+                                .iter()
+                                .filter(|a| a.ex_date <= as_of)
+                                .cloned()
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let adjustments = corporate_actions::adjustments(days, &known);
+                    (
+                        symbol.clone(),
+                        corporate_actions::adjust(days, &adjustments),
+                    )
+                })
+                .collect();
+            Arc::new(history)
+        }
+
+        fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+            self.underlying.corporate_actions()
+        }
     }
 
+    // Tradier sends a null history for a range without trading days, and a lone object rather than an
+    // array for a single day, both of which are routine when fetching incrementally
     #[derive(Deserialize, Debug)]
     struct HistoryResponse {
-        pub history: History,
+        pub history: Option<History>,
     }
 
     #[derive(Deserialize, Debug)]
     struct History {
-        pub day: Vec<Day>,
+        pub day: OneOrMany<Day>,
     }
 
     pub fn fetch_one(
@@ -79,11 +299,12 @@ mod implementation {
                 Ok(history) => {
                     let with_symbols = history
                         .history
-                        .day
-                        .iter()
+                        .map(|history| Vec::from(history.day))
+                        .unwrap_or_default()
+                        .into_iter()
                         .map(|day| Day {
                             symbol: Some(symbol.to_string()),
-                            ..day.clone()
+                            ..day
                         })
                         .collect();
                     Ok(with_symbols)
@@ -100,14 +321,98 @@ mod implementation {
         }
     }
 
+    #[derive(Deserialize, Debug)]
+    struct TimeSalesResponse {
+        pub series: Option<Series>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Series {
+        pub data: OneOrMany<TimeSale>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct TimeSale {
+        // Start of the bar, in seconds since the epoch
+        pub timestamp: i64,
+        pub open: f64,
+        pub high: f64,
+        pub low: f64,
+        pub close: f64,
+        pub volume: i64,
+    }
+
+    pub fn fetch_timesales(
+        access_token: &str,
+        symbol: &str,
+        interval: Interval,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> reqwest::Result<Vec<Bar>> {
+        let base = "https://api.tradier.com/v1/markets/timesales";
+        let params = format!(
+            "symbol={}&interval={}&start={} 00:00&end={} 23:59&session_filter=open",
+            symbol, interval, start, end
+        );
+        let url = format!("{}?{}", base, params);
+        match reqwest::blocking::Client::new()
+            .get(url.as_str())
+            .header(AUTHORIZATION, format!("Bearer {}", access_token))
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_LENGTH, "0")
+            .send()
+            .and_then(|response| response.json::<TimeSalesResponse>())
+        {
+            Ok(response) => Ok(response
+                .series
+                .map(|series| Vec::from(series.data))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|sale| {
+                    DateTime::from_timestamp(sale.timestamp, 0).map(|timestamp| Bar {
+                        symbol: symbol.to_string(),
+                        timestamp: timestamp.with_timezone(&Local),
+                        open: sale.open,
+                        high: sale.high,
+                        low: sale.low,
+                        close: sale.close,
+                        volume: sale.volume,
+                    })
+                })
+                .collect()),
+            Err(e) => {
+                info!("Request failed: {}", e);
+                Err(e)
+            }
+        }
+    }
+
     impl HistoricalDataService for HistoricalData {
-        fn fetch(&self, _: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
-            // We can ignore 'end' for the live-trading case because it's always the current date
-            self.history.clone()
+        fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+            // For live trading 'as_of' is always the current date, but Tradier will include today's
+            // incomplete bar during the session
+            let history = self
+                .history
+                .iter()
+                .map(|(symbol, days)| {
+                    let days = days
+                        .iter()
+                        .filter(|day| day.date < as_of)
+                        .cloned()
+                        .collect();
+                    (symbol.clone(), days)
+                })
+                .collect();
+            Arc::new(history)
+        }
+
+        fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+            self.actions.clone()
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::assertions_on_constants)]
 #[path = "./tests/historical_data_test.rs"]
 mod historical_data_test;
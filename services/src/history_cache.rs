@@ -0,0 +1,201 @@
+use app_config::app_config::CacheConfig;
+use chrono::NaiveDate;
+use core::serde::string_date_format;
+use domain::domain::{Bar, Day, Interval};
+use log::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub trait HistoryCache {
+    // The series for [start, end], fetching only the dates not already cached. If fetching fails, whatever
+    // is cached is returned instead.
+    fn get<T: Cacheable>(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        start: NaiveDate,
+        end: NaiveDate,
+        fetch: impl Fn(NaiveDate, NaiveDate) -> Result<Vec<T>, String>,
+    ) -> Result<Vec<T>, String>;
+
+    fn invalidate(&self, symbol: &str, interval: Interval) -> Result<(), String>;
+
+    fn invalidate_all(&self) -> Result<(), String>;
+}
+
+pub trait Cacheable: Serialize + DeserializeOwned + Clone {
+    fn date(&self) -> NaiveDate;
+}
+
+impl Cacheable for Day {
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+}
+
+impl Cacheable for Bar {
+    fn date(&self) -> NaiveDate {
+        Bar::date(self)
+    }
+}
+
+// Series are stored as {path}/{interval}/{SYMBOL}.json. Nothing from `today` onwards is stored, as the
+// day's bars aren't final until the session ends.
+pub fn new(config: &CacheConfig, today: NaiveDate) -> Arc<impl HistoryCache> {
+    Arc::new(implementation::FileHistoryCache {
+        enabled: config.enabled,
+        offline: config.offline,
+        path: PathBuf::from(&config.path),
+        today,
+    })
+}
+
+mod implementation {
+    use super::*;
+
+    pub struct FileHistoryCache {
+        pub enabled: bool,
+        pub offline: bool,
+        pub path: PathBuf,
+        pub today: NaiveDate,
+    }
+
+    // A series covering every date in [start, end]
+    #[derive(Serialize, Deserialize)]
+    #[serde(bound = "T: Cacheable")]
+    pub struct Entry<T> {
+        #[serde(with = "string_date_format")]
+        pub start: NaiveDate,
+        #[serde(with = "string_date_format")]
+        pub end: NaiveDate,
+        pub data: Vec<T>,
+    }
+
+    impl HistoryCache for FileHistoryCache {
+        fn get<T: Cacheable>(
+            &self,
+            symbol: &str,
+            interval: Interval,
+            start: NaiveDate,
+            end: NaiveDate,
+            fetch: impl Fn(NaiveDate, NaiveDate) -> Result<Vec<T>, String>,
+        ) -> Result<Vec<T>, String> {
+            if !self.enabled {
+                return fetch(start, end);
+            }
+
+            let file = self.file(symbol, interval);
+            let cached = read::<T>(&file);
+            if self.offline {
+                return cached
+                    .map(|entry| within(entry.data, start, end))
+                    .ok_or_else(|| format!("Offline and nothing cached for {}", symbol));
+            }
+
+            let result = match &cached {
+                Some(entry) => extend(entry, start, end, &fetch),
+                None => fetch(start, end).map(|data| Entry { start, end, data }),
+            };
+            let entry = match (result, cached) {
+                (Ok(entry), _) => entry,
+                (Err(e), Some(cached)) => {
+                    warn!("Fetch failed for {}, using cache: {}", symbol, e);
+                    return Ok(within(cached.data, start, end));
+                }
+                (Err(e), None) => return Err(e),
+            };
+
+            let stored_end = entry.end.min(self.today.pred_opt().unwrap());
+            if entry.start <= stored_end {
+                let stored = Entry {
+                    start: entry.start,
+                    end: stored_end,
+                    data: within(entry.data.clone(), entry.start, stored_end),
+                };
+                if let Err(e) = write(&file, &stored) {
+                    warn!("Failed to cache {}: {}", symbol, e);
+                }
+            }
+            Ok(within(entry.data, start, end))
+        }
+
+        fn invalidate(&self, symbol: &str, interval: Interval) -> Result<(), String> {
+            let file = self.file(symbol, interval);
+            info!("Invalidating {}", file.display());
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            }
+        }
+
+        fn invalidate_all(&self) -> Result<(), String> {
+            info!("Invalidating {}", self.path.display());
+            match fs::remove_dir_all(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    impl FileHistoryCache {
+        fn file(&self, symbol: &str, interval: Interval) -> PathBuf {
+            self.path
+                .join(interval.to_string())
+                .join(format!("{}.json", symbol))
+        }
+    }
+
+    // The cached entry grown to cover [start, end]. Any gap between the two is fetched too, so the
+    // coverage stays contiguous.
+    pub fn extend<T: Cacheable>(
+        entry: &Entry<T>,
+        start: NaiveDate,
+        end: NaiveDate,
+        fetch: &impl Fn(NaiveDate, NaiveDate) -> Result<Vec<T>, String>,
+    ) -> Result<Entry<T>, String> {
+        let before = if start < entry.start {
+            fetch(start, entry.start.pred_opt().unwrap())?
+        } else {
+            vec![]
+        };
+        let after = if end > entry.end {
+            fetch(entry.end.succ_opt().unwrap(), end)?
+        } else {
+            vec![]
+        };
+        Ok(Entry {
+            start: start.min(entry.start),
+            end: end.max(entry.end),
+            data: [before, entry.data.clone(), after].concat(),
+        })
+    }
+
+    pub fn within<T: Cacheable>(data: Vec<T>, start: NaiveDate, end: NaiveDate) -> Vec<T> {
+        data.into_iter()
+            .filter(|t| t.date() >= start && t.date() <= end)
+            .collect()
+    }
+
+    fn read<T: Cacheable>(file: &Path) -> Option<Entry<T>> {
+        let contents = fs::read_to_string(file).ok()?;
+        serde_json::from_str(&contents)
+            .map_err(|e| warn!("Ignoring unreadable cache {}: {}", file.display(), e))
+            .ok()
+    }
+
+    fn write<T: Cacheable>(file: &Path, entry: &Entry<T>) -> Result<(), String> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let contents = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        fs::write(file, contents).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/history_cache_test.rs"]
+mod history_cache_test;
@@ -1,5 +1,27 @@
+pub mod allocation;
+pub mod bar_aggregator;
+pub mod broker;
+pub mod corporate_actions;
+pub mod feed_health;
+pub mod file_data;
+pub mod fills;
 pub mod historical_data;
+pub mod history_cache;
 pub mod market_data;
+pub mod market_data_recorder;
+pub mod market_data_replay;
+pub mod market_data_websocket;
 pub mod orders;
+pub mod paper_orders;
 pub mod persistence;
+pub mod sizing;
 pub mod trading;
+pub mod validation;
+
+#[cfg(test)]
+#[path = "./tests/tradier_stub.rs"]
+mod tradier_stub;
+
+#[cfg(test)]
+#[path = "./tests/brokerage_stub.rs"]
+mod brokerage_stub;
@@ -1,5 +1,6 @@
-use crossbeam_channel::{Receiver, Sender};
-use domain::domain::Quote;
+use app_config::app_config::{Backpressure, MarketDataConfig, SubscriptionConfig};
+use crossbeam_channel::{Receiver, SendTimeoutError, Sender, TrySendError};
+use domain::domain::{EventType, MarketEvent, Quote};
 use log::*;
 use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_LENGTH};
 use serde::Deserialize;
@@ -15,19 +16,118 @@ pub trait MarketDataService {
         symbols: Vec<String>,
     ) -> Result<JoinHandle<()>, String>;
     fn subscribe(&self) -> Result<Receiver<Quote>, String>;
+    // Quotes queued as given, rather than as configured for the service
+    fn subscribe_with(&self, config: SubscriptionConfig) -> Result<Receiver<Quote>, String>;
     fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String>;
+
+    // Change the symbols streamed, before or after init, without reconnecting
+    fn add_symbols(&self, symbols: &[String]) -> Result<(), String>;
+    fn remove_symbols(&self, symbols: &[String]) -> Result<(), String>;
+
+    // Events of the given types only
+    fn subscribe_events(&self, types: &[EventType]) -> Result<Receiver<MarketEvent>, String>;
+    fn unsubscribe_events(&self, subscriber: &Receiver<MarketEvent>) -> Result<(), String>;
+
+    // Every message as received, before parsing. Dropping the receiver unsubscribes.
+    fn subscribe_raw(&self) -> Result<Receiver<String>, String> {
+        Err("Raw messages are not available".to_string())
+    }
+
+    // What the stream has sent so far
+    fn message_counts(&self) -> MessageCounts {
+        MessageCounts::default()
+    }
+
+    // Each quote subscriber's queue
+    fn subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberMetrics {
+    pub config: SubscriptionConfig,
+    // Quotes waiting to be received
+    pub depth: usize,
+    // Quotes discarded, or replaced by a later quote for the same symbol
+    pub dropped: u64,
 }
 
-pub fn new(access_token: String) -> Arc<impl MarketDataService> {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub events: u64,
+    pub heartbeats: u64,
+    // Well-formed, but of a type we don't handle
+    pub unknown: u64,
+    pub malformed: u64,
+}
+
+// What a message from the stream turned out to be
+#[derive(Debug)]
+pub enum Incoming {
+    Event(MarketEvent),
+    Heartbeat,
+    Ping,
+    Pong,
+    Close(String),
+    Unknown(String),
+    Malformed(String),
+}
+
+pub fn classify(msg: Message) -> Vec<Incoming> {
+    match msg {
+        Message::Text(text) => classify_text(&text),
+        Message::Binary(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => classify_text(&text),
+            Err(e) => vec![Incoming::Malformed(format!("Binary message: {}", e))],
+        },
+        Message::Ping(_) => vec![Incoming::Ping],
+        Message::Pong(_) => vec![Incoming::Pong],
+        Message::Close(frame) => vec![Incoming::Close(
+            frame
+                .map(|frame| format!("{} {}", frame.code, frame.reason))
+                .unwrap_or_default(),
+        )],
+        Message::Frame(_) => vec![],
+    }
+}
+
+// With linebreak set, a message may hold several events, one per line
+pub fn classify_text(text: &str) -> Vec<Incoming> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(implementation::classify_line)
+        .collect()
+}
+
+// Only the configured event types are requested from Tradier
+pub fn new(access_token: String, config: &MarketDataConfig) -> Arc<impl MarketDataService> {
     Arc::new(implementation::MarketData {
         access_token,
-        subscribers: Arc::new(Mutex::new(Vec::new())),
+        events: config.events.clone(),
+        session_url: config.session_url.clone(),
+        stream_url: config.stream_url.clone(),
+        subscribers: implementation::Subscribers {
+            quote_config: config.subscriptions,
+            ..Default::default()
+        },
+        counts: Arc::new(Mutex::new(MessageCounts::default())),
+        symbols: Arc::new(Mutex::new(vec![])),
+        resubscribe: Arc::new(AtomicBool::new(false)),
     })
 }
 
-mod implementation {
+pub mod implementation {
     use super::*;
-    use std::{net::TcpStream, thread, time::Duration};
+    use std::{
+        io::ErrorKind,
+        iter,
+        net::TcpStream,
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
+        time::{Duration, Instant},
+    };
     use tungstenite::{stream::MaybeTlsStream, WebSocket};
 
     #[derive(Deserialize)]
@@ -40,9 +140,24 @@ mod implementation {
         sessionid: String,
     }
 
+    // How long a read waits before checking for shutdown and symbol changes
+    pub const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+    pub const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
     pub struct MarketData {
         pub access_token: String,
-        pub subscribers: Arc<Mutex<Vec<(Sender<Quote>, Receiver<Quote>)>>>,
+        pub events: Vec<EventType>,
+        pub session_url: String,
+        pub stream_url: String,
+        pub subscribers: Subscribers,
+        pub counts: Arc<Mutex<MessageCounts>>,
+        // Those subscribed to, kept across reconnections
+        pub symbols: Arc<Mutex<Vec<String>>>,
+        // Set when the symbols change, so the subscription is sent again
+        pub resubscribe: Arc<AtomicBool>,
     }
 
     impl MarketDataService for MarketData {
@@ -52,113 +167,551 @@ mod implementation {
             symbols: Vec<String>,
         ) -> Result<JoinHandle<()>, String> {
             let token = self.access_token.clone();
-            let subscribers = self.subscribers.clone();
+            let events = self.events.clone();
+            let (session_url, stream_url) = (self.session_url.clone(), self.stream_url.clone());
+            let subscribers = Subscribers {
+                shutdown: shutdown.clone(),
+                ..self.subscribers.clone()
+            };
+            let counts = self.counts.clone();
+            change_symbols(&self.symbols, &symbols, &[])?;
+            let current = self.symbols.clone();
+            let resubscribe = self.resubscribe.clone();
 
             let handle = thread::spawn(move || {
-                while !shutdown.load(std::sync::atomic::Ordering::Relaxed) {
-                    match authenticate_and_connect(&token, symbols.clone()) {
-                        Ok(mut socket) => {
-                            while !shutdown.load(std::sync::atomic::Ordering::Relaxed) {
-                                // Note: The tungstenite API is rather non-ideal in that there is no non-blockng read.
-                                // Tradier will drop the connection at EOD or after a period of inactivity (15m), but
-                                // we might have to wait for that to occur before the service can shutdown.
-                                match socket.read() {
-                                    Ok(msg) => {
-                                        handle_quote(msg, subscribers.clone());
-                                    }
-                                    Err(e) => {
-                                        info!("Error reading message - possible EOD/inactivity connection close: {}", e);
-                                        info!("Reconnecting unless shutdown flag set");
-                                        thread::sleep(Duration::from_secs(1));
-                                        break;
-                                    }
-                                }
-                            }
+                stream(
+                    &shutdown,
+                    &current,
+                    &resubscribe,
+                    &subscribers,
+                    &counts,
+                    |symbols| {
+                        authenticate_and_connect(
+                            &session_url,
+                            &stream_url,
+                            &token,
+                            symbols.to_vec(),
+                            &events,
+                        )
+                    },
+                    |socket, session_id, _, symbols| {
+                        if symbols.is_empty() {
+                            warn!("No symbols left, keeping the previous subscription");
+                            return Ok(());
                         }
-                        Err(e) => {
-                            info!("Error connecting: {}", e);
-                            thread::sleep(Duration::from_secs(5));
+                        let message = subscription(symbols, session_id, &events);
+                        socket
+                            .send(Message::Text(message))
+                            .map_err(|e| e.to_string())
+                    },
+                    |msg| handle_message(msg, &subscribers, &counts),
+                );
+            });
+
+            Ok(handle)
+        }
+
+        fn subscribe(&self) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe()
+        }
+
+        fn subscribe_with(&self, config: SubscriptionConfig) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe_with(config)
+        }
+
+        fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
+            self.subscribers.unsubscribe(subscriber)
+        }
+
+        fn add_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            if change_symbols(&self.symbols, symbols, &[])? {
+                self.resubscribe.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+
+        fn remove_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            if change_symbols(&self.symbols, &[], symbols)? {
+                self.resubscribe.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+
+        fn subscribe_events(&self, types: &[EventType]) -> Result<Receiver<MarketEvent>, String> {
+            self.subscribers.subscribe_events(types)
+        }
+
+        fn unsubscribe_events(&self, subscriber: &Receiver<MarketEvent>) -> Result<(), String> {
+            self.subscribers.unsubscribe_events(subscriber)
+        }
+
+        fn subscribe_raw(&self) -> Result<Receiver<String>, String> {
+            self.subscribers.subscribe_raw()
+        }
+
+        fn message_counts(&self) -> MessageCounts {
+            *self.counts.lock().unwrap()
+        }
+
+        fn subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+            self.subscribers.metrics()
+        }
+    }
+
+    // Everyone listening to a service, by what they listen to
+    #[derive(Clone, Default)]
+    pub struct Subscribers {
+        pub quotes: Arc<Mutex<Vec<QuoteSubscriber>>>,
+        // For quote subscribers that don't give their own
+        pub quote_config: SubscriptionConfig,
+        pub events: Arc<Mutex<Vec<EventSubscriber>>>,
+        pub raw: Arc<Mutex<Vec<Sender<String>>>>,
+        // Set when the feed publishing to them shuts down, so it needn't wait for room in a full
+        // queue whose subscriber has stopped receiving
+        pub shutdown: Arc<AtomicBool>,
+    }
+
+    // The receiver is kept to make room in a full queue, and to tell subscribers apart
+    #[derive(Clone)]
+    pub struct QuoteSubscriber {
+        pub config: SubscriptionConfig,
+        pub sender: Sender<Quote>,
+        pub receiver: Receiver<Quote>,
+        pub dropped: Arc<AtomicU64>,
+    }
+
+    impl QuoteSubscriber {
+        pub fn new(config: SubscriptionConfig) -> QuoteSubscriber {
+            let (sender, receiver) = match config.capacity {
+                Some(capacity) => crossbeam_channel::bounded(capacity),
+                None => crossbeam_channel::unbounded(),
+            };
+            QuoteSubscriber {
+                config,
+                sender,
+                receiver,
+                dropped: Arc::new(AtomicU64::new(0)),
+            }
+        }
+
+        pub fn send(&self, quote: Quote, shutdown: &AtomicBool) -> Result<(), String> {
+            match self.config.policy {
+                Backpressure::Block => self.send_blocking(quote, shutdown),
+                Backpressure::DropOldest => self.send_dropping_oldest(quote),
+                Backpressure::Conflate => match self.sender.try_send(quote) {
+                    Err(TrySendError::Full(quote)) => self.conflate(quote),
+                    result => result.map_err(|e| e.to_string()),
+                },
+            }
+        }
+
+        // Waits for room, but only for as long as the feed is running
+        fn send_blocking(&self, mut quote: Quote, shutdown: &AtomicBool) -> Result<(), String> {
+            loop {
+                match self.sender.send_timeout(quote, READ_TIMEOUT) {
+                    Err(SendTimeoutError::Timeout(returned)) => {
+                        if shutdown.load(Ordering::Relaxed) {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                            return Err("Shut down with the subscriber's queue full".to_string());
                         }
+                        quote = returned;
                     }
+                    result => return result.map_err(|e| e.to_string()),
                 }
+            }
+        }
 
-                info!("Shutting down");
-            });
+        fn send_dropping_oldest(&self, mut quote: Quote) -> Result<(), String> {
+            loop {
+                match self.sender.try_send(quote) {
+                    Err(TrySendError::Full(returned)) => {
+                        if self.receiver.try_recv().is_ok() {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        quote = returned;
+                    }
+                    result => return result.map_err(|e| e.to_string()),
+                }
+            }
+        }
 
-            Ok(handle)
+        // Reduces the queue to the latest quote for each symbol, in the order the symbols were first
+        // queued. Should there be more symbols than room, the oldest are dropped.
+        fn conflate(&self, quote: Quote) -> Result<(), String> {
+            let mut latest: Vec<Quote> = vec![];
+            for queued in self.receiver.try_iter().chain(iter::once(quote)) {
+                match latest.iter_mut().find(|q| q.symbol == queued.symbol) {
+                    Some(previous) => {
+                        *previous = queued;
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    None => latest.push(queued),
+                }
+            }
+            latest
+                .into_iter()
+                .try_for_each(|quote| self.send_dropping_oldest(quote))
         }
 
-        fn subscribe(&self) -> Result<Receiver<Quote>, String> {
+        pub fn metrics(&self) -> SubscriberMetrics {
+            SubscriberMetrics {
+                config: self.config,
+                depth: self.receiver.len(),
+                dropped: self.dropped.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    pub struct EventSubscriber {
+        pub types: Vec<EventType>,
+        pub sender: Sender<MarketEvent>,
+        pub receiver: Receiver<MarketEvent>,
+    }
+
+    impl Subscribers {
+        pub fn subscribe(&self) -> Result<Receiver<Quote>, String> {
+            self.subscribe_with(self.quote_config)
+        }
+
+        pub fn subscribe_with(
+            &self,
+            config: SubscriptionConfig,
+        ) -> Result<Receiver<Quote>, String> {
+            if config.capacity == Some(0) {
+                return Err("A subscription's capacity must be at least 1".to_string());
+            }
+            let subscriber = QuoteSubscriber::new(config);
+            let receiver = subscriber.receiver.clone();
+            self.quotes
+                .lock()
+                .map(|mut s| s.push(subscriber))
+                .map_err(|e| e.to_string())
+                .map(|_| receiver)
+        }
+
+        pub fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
+            let mut subscribers = self.quotes.lock().map_err(|e| e.to_string())?;
+            match subscribers
+                .iter()
+                .position(|s| s.receiver.same_channel(subscriber))
+            {
+                Some(index) => {
+                    subscribers.remove(index);
+                    Ok(())
+                }
+                None => Err("No such subscriber found".to_string()),
+            }
+        }
+
+        pub fn metrics(&self) -> Vec<SubscriberMetrics> {
+            self.quotes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(QuoteSubscriber::metrics)
+                .collect()
+        }
+
+        pub fn subscribe_events(
+            &self,
+            types: &[EventType],
+        ) -> Result<Receiver<MarketEvent>, String> {
             let (sender, receiver) = crossbeam_channel::unbounded();
             let subscriber = receiver.clone();
-            self.subscribers
+            self.events
                 .lock()
-                .map(|mut s| s.push((sender, receiver)))
+                .map(|mut s| {
+                    s.push(EventSubscriber {
+                        types: types.to_vec(),
+                        sender,
+                        receiver,
+                    })
+                })
                 .map_err(|e| e.to_string())
                 .map(|_| subscriber)
         }
 
-        fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
-            match self.subscribers.lock() {
-                Ok(mut guard) => {
-                    let subscribers: &mut Vec<(Sender<Quote>, Receiver<Quote>)> = &mut guard;
-                    if let Some(index) = subscribers
-                        .iter()
-                        .position(|(_, r)| std::ptr::eq(r, subscriber))
-                    {
-                        subscribers.remove(index);
-                        Ok(())
-                    } else {
-                        Err("No such subscriber found".to_string())
+        pub fn unsubscribe_events(&self, subscriber: &Receiver<MarketEvent>) -> Result<(), String> {
+            let mut subscribers = self.events.lock().map_err(|e| e.to_string())?;
+            match subscribers
+                .iter()
+                .position(|s| s.receiver.same_channel(subscriber))
+            {
+                Some(index) => {
+                    subscribers.remove(index);
+                    Ok(())
+                }
+                None => Err("No such subscriber found".to_string()),
+            }
+        }
+
+        pub fn subscribe_raw(&self) -> Result<Receiver<String>, String> {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            self.raw
+                .lock()
+                .map(|mut s| s.push(sender))
+                .map_err(|e| e.to_string())
+                .map(|_| receiver)
+        }
+
+        // Quotes go to quote subscribers as well as to those subscribed to quote events
+        pub fn publish(&self, event: &MarketEvent) {
+            if let MarketEvent::Quote(quote) = event {
+                // Sent without holding the lock, as a blocking subscriber may take a while to make room
+                let subscribers = self.quotes.lock().unwrap().clone();
+                for subscriber in subscribers.iter() {
+                    match subscriber.send(quote.clone(), &self.shutdown) {
+                        Ok(_) => (),
+                        Err(e) => info!("Error sending quote to subscriber: {}", e),
                     }
                 }
-                Err(e) => Err(e.to_string()),
+            }
+
+            let event_type = event.event_type();
+            for subscriber in self
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|s| s.types.contains(&event_type))
+            {
+                if let Err(e) = subscriber.sender.send(event.clone()) {
+                    info!("Error sending event to subscriber: {}", e);
+                }
             }
         }
+
+        // Raw subscribers that have gone away are dropped
+        pub fn publish_raw(&self, msg: &str) {
+            self.raw
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.send(msg.to_string()).is_ok());
+        }
     }
 
-    fn handle_quote(msg: Message, subscribers: Arc<Mutex<Vec<(Sender<Quote>, Receiver<Quote>)>>>) {
-        let msg = msg.into_text().expect("Error converting message to text");
-        let quote = serde_json::from_str::<Quote>(msg.as_str()).expect("Error parsing JSON");
-        info!("Received quote: {:?}", quote);
+    // False once the server has closed the stream
+    pub fn handle_message(
+        msg: Message,
+        subscribers: &Subscribers,
+        counts: &Mutex<MessageCounts>,
+    ) -> bool {
+        if let Message::Text(text) = &msg {
+            subscribers.publish_raw(text);
+        }
+        let mut open = true;
+        for incoming in classify(msg) {
+            open &= handle_incoming(incoming, subscribers, counts);
+        }
+        open
+    }
+
+    // Nothing from the stream is fatal: whatever can't be used is counted, logged and skipped. Pings
+    // need no reply here, as tungstenite answers them itself. False for a close.
+    pub fn handle_incoming(
+        incoming: Incoming,
+        subscribers: &Subscribers,
+        counts: &Mutex<MessageCounts>,
+    ) -> bool {
+        let mut counts = counts.lock().unwrap();
+        match incoming {
+            Incoming::Event(event) => {
+                counts.events += 1;
+                info!("Received event: {:?}", event);
+                subscribers.publish(&event);
+            }
+            Incoming::Heartbeat => counts.heartbeats += 1,
+            Incoming::Ping | Incoming::Pong => debug!("Received {:?}", incoming),
+            Incoming::Close(reason) => {
+                info!("Stream closed by server: {}", reason);
+                return false;
+            }
+            Incoming::Unknown(msg) => {
+                counts.unknown += 1;
+                info!("Ignoring unknown message: {}", msg);
+            }
+            Incoming::Malformed(e) => {
+                counts.malformed += 1;
+                warn!("Malformed message ({} so far): {}", counts.malformed, e);
+            }
+        }
+        true
+    }
+
+    #[derive(Deserialize)]
+    struct Tagged {
+        #[serde(rename = "type")]
+        event_type: Option<String>,
+    }
+
+    pub fn classify_line(line: &str) -> Incoming {
+        let error = match serde_json::from_str::<MarketEvent>(line) {
+            Ok(event) => return Incoming::Event(event),
+            Err(e) => format!("{}: {}", e, line),
+        };
+        match serde_json::from_str::<Tagged>(line) {
+            Ok(Tagged {
+                event_type: Some(event_type),
+            }) => {
+                if event_type == "heartbeat" {
+                    Incoming::Heartbeat
+                } else if EventType::ALL
+                    .iter()
+                    .any(|known| known.to_string() == event_type)
+                {
+                    // One of ours, but not as we know it
+                    Incoming::Malformed(error)
+                } else {
+                    Incoming::Unknown(line.to_string())
+                }
+            }
+            Ok(Tagged { event_type: None }) => Incoming::Unknown(line.to_string()),
+            Err(_) => Incoming::Malformed(error),
+        }
+    }
 
-        for subscriber in subscribers.lock().unwrap().iter() {
-            match subscriber.0.send(quote.clone()) {
-                Ok(_) => (),
-                Err(e) => info!("Error sending quote to subscriber: {}", e),
+    // Adds and removes symbols, keeping their order, and says whether anything changed
+    pub fn change_symbols(
+        symbols: &Mutex<Vec<String>>,
+        added: &[String],
+        removed: &[String],
+    ) -> Result<bool, String> {
+        let mut symbols = symbols.lock().map_err(|e| e.to_string())?;
+        let before = symbols.clone();
+        symbols.retain(|symbol| !removed.contains(symbol));
+        for symbol in added {
+            if !symbols.contains(symbol) {
+                symbols.push(symbol.clone());
             }
         }
+        Ok(*symbols != before)
+    }
+
+    // Sent again on the same session to change the symbols streamed
+    fn subscription(symbols: &[String], session_id: &str, events: &[EventType]) -> String {
+        let symbols_json = serde_json::to_string(symbols).expect("Error serializing symbols");
+        let events_json = serde_json::to_string(events).expect("Error serializing event types");
+        format!(
+            "{{\"symbols\": {}, \"sessionid\": \"{}\", \"filter\": {}, \"linebreak\": true}}",
+            symbols_json, session_id, events_json
+        )
     }
 
+    // Streams until shutdown, whichever the provider: connecting, reconnecting when the connection
+    // drops, changing the subscription when the symbols change, and logging metrics periodically.
+    // `connect` opens a socket subscribed to the symbols, with whatever the provider needs to change
+    // its subscription later. `change` moves a socket's subscription from one set of symbols to
+    // another. `handle` publishes a message, returning false once the server has closed the stream.
+    #[allow(clippy::too_many_arguments)]
+    pub fn stream<C>(
+        shutdown: &AtomicBool,
+        symbols: &Mutex<Vec<String>>,
+        resubscribe: &AtomicBool,
+        subscribers: &Subscribers,
+        counts: &Mutex<MessageCounts>,
+        connect: impl Fn(&[String]) -> Result<(Socket, C), String>,
+        change: impl Fn(&mut Socket, &C, &[String], &[String]) -> Result<(), String>,
+        handle: impl Fn(Message) -> bool,
+    ) {
+        let mut reported = Instant::now();
+        while !shutdown.load(Ordering::Relaxed) {
+            // Whatever changes are pending are included in the new subscription
+            resubscribe.store(false, Ordering::Relaxed);
+            let mut subscribed = symbols.lock().unwrap().clone();
+            match connect(&subscribed) {
+                Ok((mut socket, context)) => {
+                    while !shutdown.load(Ordering::Relaxed) {
+                        if reported.elapsed() >= REPORT_INTERVAL {
+                            info!(
+                                "Messages: {:?}; quote subscribers: {:?}",
+                                *counts.lock().unwrap(),
+                                subscribers.metrics()
+                            );
+                            reported = Instant::now();
+                        }
+
+                        if resubscribe.swap(false, Ordering::Relaxed) {
+                            let symbols = symbols.lock().unwrap().clone();
+                            info!("Resubscribing to {:?}", symbols);
+                            if let Err(e) = change(&mut socket, &context, &subscribed, &symbols) {
+                                info!("Error resubscribing: {}", e);
+                                break;
+                            }
+                            subscribed = symbols;
+                        }
+
+                        // Servers drop the connection at EOD or after a period of inactivity (15m for
+                        // Tradier). Reads time out so that shutdown and symbol changes needn't wait for
+                        // a message.
+                        match socket.read() {
+                            Ok(msg) => {
+                                if !handle(msg) {
+                                    info!("Reconnecting unless shutdown flag set");
+                                    thread::sleep(Duration::from_secs(1));
+                                    break;
+                                }
+                            }
+                            Err(tungstenite::Error::Io(e))
+                                if matches!(
+                                    e.kind(),
+                                    ErrorKind::WouldBlock | ErrorKind::TimedOut
+                                ) => {}
+                            Err(e) => {
+                                info!("Error reading message - possible EOD/inactivity connection close: {}", e);
+                                info!("Reconnecting unless shutdown flag set");
+                                thread::sleep(Duration::from_secs(1));
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    info!("Error connecting: {}", e);
+                    thread::sleep(Duration::from_secs(5));
+                }
+            }
+        }
+
+        info!("Shutting down");
+    }
+
+    // The socket and the session it was opened for
     fn authenticate_and_connect(
+        session_url: &str,
+        stream_url: &str,
         access_token: &str,
         symbols: Vec<String>,
-    ) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
-        let response = authenticate(access_token).map_err(|e| e.to_string())?;
-        let session_id = &response.stream.sessionid;
-        let symbols_json = serde_json::to_string(&symbols).expect("Error serializing symbols");
+        events: &[EventType],
+    ) -> Result<(Socket, String), String> {
+        let response = authenticate(session_url, access_token).map_err(|e| e.to_string())?;
+        let session_id = response.stream.sessionid;
 
-        match connect("wss://ws.tradier.com/v1/markets/events") {
+        match connect(stream_url) {
             Ok((mut socket, _)) => {
-                let message = format!(
-                    "{{\"symbols\": {}, \"sessionid\": \"{}\", \"filter\": [\"quote\"], \"linebreak\": true}}",
-                    symbols_json,
-                    session_id
-                );
+                let message = subscription(&symbols, &session_id, events);
                 socket
                     .send(Message::Text(message))
-                    .expect("Error sending message");
+                    .map_err(|e| e.to_string())?;
+                set_read_timeout(&socket, READ_TIMEOUT).map_err(|e| e.to_string())?;
 
-                Ok(socket)
+                Ok((socket, session_id))
             }
 
             Err(e) => Err(e.to_string()),
         }
     }
 
-    fn authenticate(access_token: &str) -> reqwest::Result<AuthResponse> {
+    pub fn set_read_timeout(socket: &Socket, timeout: Duration) -> std::io::Result<()> {
+        match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+            MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    fn authenticate(session_url: &str, access_token: &str) -> reqwest::Result<AuthResponse> {
         reqwest::blocking::Client::new()
-            .post("https://api.tradier.com/v1/markets/events/session")
+            .post(session_url)
             .header(AUTHORIZATION, format!("Bearer {}", access_token))
             .header(ACCEPT, "application/json")
             .header(CONTENT_LENGTH, "0")
@@ -0,0 +1,211 @@
+use crate::market_data::MarketDataService;
+use app_config::app_config::RecordingConfig;
+use chrono::{DateTime, Local, NaiveDate};
+use core::serde::millis_date_time_format;
+use domain::domain::{MarketEvent, Quote};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    thread::JoinHandle,
+};
+
+// A message from the feed as it arrived, with the quote parsed from it if it was one. Other events are
+// parsed from the raw message on replay.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    #[serde(with = "millis_date_time_format")]
+    pub received: DateTime<Local>,
+    pub raw: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<Quote>,
+}
+
+impl Record {
+    pub fn new(received: DateTime<Local>, raw: String) -> Record {
+        let quote = match serde_json::from_str::<MarketEvent>(&raw) {
+            Ok(MarketEvent::Quote(quote)) => Some(quote),
+            _ => None,
+        };
+        Record {
+            received,
+            raw,
+            quote,
+        }
+    }
+}
+
+pub trait MarketDataRecorder {
+    // Records every raw message from the service until shutdown, when the current file is finished
+    fn start(&self, shutdown: Arc<AtomicBool>) -> Result<JoinHandle<()>, String>;
+}
+
+// Records are written as gzipped JSON lines to {path}/{timestamp}-{sequence}.jsonl.gz
+pub fn new(
+    config: &RecordingConfig,
+    market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
+) -> Arc<impl MarketDataRecorder> {
+    Arc::new(implementation::Recorder {
+        path: PathBuf::from(&config.path),
+        max_bytes: config.max_file_mb * 1024 * 1024,
+        market_data,
+    })
+}
+
+// The records in a recording file, or in every recording file in a directory in name order, which is
+// the order they were written in. A file cut short, e.g. by a crash, yields what was written before
+// the cut.
+pub fn read(path: &str) -> Result<Vec<Record>, String> {
+    let path = Path::new(path);
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.to_string_lossy().ends_with(".jsonl.gz"))
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut records = vec![];
+    for file in files {
+        records.extend(implementation::read_file(&file)?);
+    }
+    Ok(records)
+}
+
+// Writes records to files rotated by size and by date
+pub struct RotatingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    current: Option<(GzEncoder<BufWriter<File>>, NaiveDate, u64)>,
+    sequence: u32,
+}
+
+impl RotatingWriter {
+    pub fn new(path: PathBuf, max_bytes: u64) -> RotatingWriter {
+        RotatingWriter {
+            path,
+            max_bytes,
+            current: None,
+            sequence: 0,
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())? + "\n";
+        let date = record.received.date_naive();
+        let rotate = match &self.current {
+            Some((_, file_date, written)) => *file_date != date || *written >= self.max_bytes,
+            None => true,
+        };
+        if rotate {
+            self.finish()?;
+            self.current = Some((self.create(record.received)?, date, 0));
+        }
+
+        let (encoder, _, written) = self.current.as_mut().unwrap();
+        encoder
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())?;
+        *written += line.len() as u64;
+        Ok(())
+    }
+
+    // Completes the current file, so it can be read
+    pub fn finish(&mut self) -> Result<(), String> {
+        match self.current.take() {
+            Some((encoder, _, _)) => encoder
+                .finish()
+                .and_then(|mut writer| writer.flush())
+                .map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn create(&mut self, received: DateTime<Local>) -> Result<GzEncoder<BufWriter<File>>, String> {
+        fs::create_dir_all(&self.path).map_err(|e| e.to_string())?;
+        self.sequence += 1;
+        let file = self.path.join(format!(
+            "{}-{:04}.jsonl.gz",
+            received.format("%Y%m%d-%H%M%S"),
+            self.sequence
+        ));
+        info!("Recording market data to {}", file.display());
+        File::create(&file)
+            .map(|file| GzEncoder::new(BufWriter::new(file), Compression::default()))
+            .map_err(|e| format!("{}: {}", file.display(), e))
+    }
+}
+
+mod implementation {
+    use super::*;
+    use crossbeam_channel::RecvTimeoutError;
+    use std::{sync::atomic::Ordering, thread, time::Duration};
+
+    pub struct Recorder<M: MarketDataService + 'static + Send + Sync> {
+        pub path: PathBuf,
+        pub max_bytes: u64,
+        pub market_data: Arc<M>,
+    }
+
+    impl<M: MarketDataService + 'static + Send + Sync> MarketDataRecorder for Recorder<M> {
+        fn start(&self, shutdown: Arc<AtomicBool>) -> Result<JoinHandle<()>, String> {
+            let rx = self.market_data.subscribe_raw()?;
+            let mut writer = RotatingWriter::new(self.path.clone(), self.max_bytes);
+
+            Ok(thread::spawn(move || {
+                while !shutdown.load(Ordering::Relaxed) {
+                    match rx.recv_timeout(Duration::from_millis(100)) {
+                        Ok(raw) => {
+                            if let Err(e) = writer.write(&Record::new(Local::now(), raw)) {
+                                error!("Failed to record market data: {}", e);
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => {
+                            info!("MarketData channel disconnected");
+                            break;
+                        }
+                    }
+                }
+
+                if let Err(e) = writer.finish() {
+                    error!("Failed to finish recording: {}", e);
+                }
+                info!("Recorder shutting down");
+            }))
+        }
+    }
+
+    pub fn read_file(file: &Path) -> Result<Vec<Record>, String> {
+        let reader = File::open(file)
+            .map(|f| BufReader::new(MultiGzDecoder::new(f)))
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+
+        let mut records = vec![];
+        for line in reader.lines() {
+            match line {
+                Ok(line) => match serde_json::from_str::<Record>(&line) {
+                    Ok(record) => records.push(record),
+                    Err(e) => warn!("{}: Skipping unreadable record: {}", file.display(), e),
+                },
+                Err(e) => {
+                    warn!("{}: Recording ends early: {}", file.display(), e);
+                    break;
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/market_data_recorder_test.rs"]
+mod market_data_recorder_test;
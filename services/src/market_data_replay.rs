@@ -0,0 +1,177 @@
+use crate::market_data::{
+    classify_text,
+    implementation::{change_symbols, handle_incoming, Subscribers},
+    Incoming, MarketDataService, MessageCounts, SubscriberMetrics,
+};
+use crate::market_data_recorder::{self, Record};
+use app_config::app_config::{ReplayConfig, ReplaySpeed, SubscriptionConfig};
+use crossbeam_channel::Receiver;
+use domain::domain::{EventType, MarketEvent, Quote};
+use log::*;
+use std::{
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+};
+
+// Replays a recording in place of the live feed, publishing each message when the pace set by the
+// speed says it's due. Only events for the symbols subscribed to are published, or for all symbols if
+// there are none; every raw message is. Quotes are queued for subscribers as the live providers queue
+// them.
+pub fn new(
+    config: &ReplayConfig,
+    subscriptions: SubscriptionConfig,
+) -> Arc<impl MarketDataService> {
+    Arc::new(implementation::Replay {
+        path: config.path.clone(),
+        speed: config.speed,
+        factor: config.factor,
+        subscribers: Subscribers {
+            quote_config: subscriptions,
+            ..Default::default()
+        },
+        counts: Arc::new(Mutex::new(MessageCounts::default())),
+        symbols: Arc::new(Mutex::new(vec![])),
+    })
+}
+
+mod implementation {
+    use super::*;
+    use std::{
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    pub struct Replay {
+        pub path: String,
+        pub speed: ReplaySpeed,
+        pub factor: f64,
+        pub subscribers: Subscribers,
+        pub counts: Arc<Mutex<MessageCounts>>,
+        pub symbols: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MarketDataService for Replay {
+        fn init(
+            &self,
+            shutdown: Arc<AtomicBool>,
+            symbols: Vec<String>,
+        ) -> Result<JoinHandle<()>, String> {
+            let records = market_data_recorder::read(&self.path)?;
+            info!("Replaying {} records from {}", records.len(), self.path);
+            let factor = match self.speed {
+                ReplaySpeed::RealTime => Some(1.0),
+                ReplaySpeed::Accelerated if self.factor > 0.0 => Some(self.factor),
+                ReplaySpeed::Accelerated => {
+                    return Err(format!("Invalid replay factor {}", self.factor))
+                }
+                ReplaySpeed::AsFastAsPossible => None,
+            };
+            let subscribers = Subscribers {
+                shutdown: shutdown.clone(),
+                ..self.subscribers.clone()
+            };
+            let counts = self.counts.clone();
+            change_symbols(&self.symbols, &symbols, &[])?;
+            let symbols = self.symbols.clone();
+
+            Ok(thread::spawn(move || {
+                let started = Instant::now();
+                let first = records.first().map(|record| record.received);
+                for record in records {
+                    if let (Some(factor), Some(first)) = (factor, first) {
+                        let elapsed = (record.received - first).to_std().unwrap_or_default();
+                        if !wait_until(started + elapsed.div_f64(factor), &shutdown) {
+                            break;
+                        }
+                    } else if shutdown.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    replay(&record, &symbols, &subscribers, &counts);
+                }
+
+                info!("Replay finished");
+            }))
+        }
+
+        fn subscribe(&self) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe()
+        }
+
+        fn subscribe_with(&self, config: SubscriptionConfig) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe_with(config)
+        }
+
+        fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
+            self.subscribers.unsubscribe(subscriber)
+        }
+
+        fn add_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            change_symbols(&self.symbols, symbols, &[]).map(|_| ())
+        }
+
+        fn remove_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            change_symbols(&self.symbols, &[], symbols).map(|_| ())
+        }
+
+        fn subscribe_events(&self, types: &[EventType]) -> Result<Receiver<MarketEvent>, String> {
+            self.subscribers.subscribe_events(types)
+        }
+
+        fn unsubscribe_events(&self, subscriber: &Receiver<MarketEvent>) -> Result<(), String> {
+            self.subscribers.unsubscribe_events(subscriber)
+        }
+
+        fn subscribe_raw(&self) -> Result<Receiver<String>, String> {
+            self.subscribers.subscribe_raw()
+        }
+
+        fn message_counts(&self) -> MessageCounts {
+            *self.counts.lock().unwrap()
+        }
+
+        fn subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+            self.subscribers.metrics()
+        }
+    }
+
+    // Recorded messages go through the same handling as live ones
+    fn replay(
+        record: &Record,
+        symbols: &Mutex<Vec<String>>,
+        subscribers: &Subscribers,
+        counts: &Mutex<MessageCounts>,
+    ) {
+        subscribers.publish_raw(&record.raw);
+        // The symbols aren't held while publishing, which may wait on a blocking subscriber
+        let symbols = symbols.lock().unwrap().clone();
+        for incoming in classify_text(&record.raw) {
+            match &incoming {
+                Incoming::Event(event)
+                    if !symbols.is_empty() && !symbols.iter().any(|s| s == event.symbol()) => {}
+                _ => {
+                    handle_incoming(incoming, subscribers, counts);
+                }
+            }
+        }
+    }
+
+    // Sleeps until the deadline in short steps, so shutdown isn't held up by long gaps in the
+    // recording. False if shut down first.
+    fn wait_until(deadline: Instant, shutdown: &AtomicBool) -> bool {
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/market_data_replay_test.rs"]
+mod market_data_replay_test;
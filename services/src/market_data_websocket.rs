@@ -0,0 +1,362 @@
+use crate::market_data::{
+    classify,
+    implementation::{
+        change_symbols, handle_incoming, set_read_timeout, stream, Socket, Subscribers,
+        READ_TIMEOUT,
+    },
+    Incoming, MarketDataService, MessageCounts, SubscriberMetrics,
+};
+use app_config::app_config::{
+    MarketDataConfig, QuoteMapping, SubscriptionConfig, TimeFormat, WebsocketConfig,
+};
+use chrono::{DateTime, Local, TimeZone};
+use crossbeam_channel::Receiver;
+use domain::domain::{EventType, MarketEvent, Quote};
+use log::*;
+use serde_json::Value;
+use std::{
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+};
+use tungstenite::{connect, Message};
+
+// Streams quotes from any vendor sending JSON over a websocket, mapped into our quotes as configured,
+// so strategies see the same quotes whichever provider they come from. Only quotes are mapped.
+pub fn new(
+    access_token: String,
+    config: &MarketDataConfig,
+) -> Result<Arc<impl MarketDataService>, String> {
+    let websocket = config
+        .websocket
+        .clone()
+        .ok_or("The websocket provider requires [market_data.websocket]".to_string())?;
+    if websocket.url.is_empty() || websocket.subscribe.is_empty() {
+        return Err("[market_data.websocket] requires a url and a subscribe message".to_string());
+    }
+    Ok(Arc::new(implementation::WebsocketMarketData {
+        access_token,
+        config: websocket,
+        subscribers: Subscribers {
+            quote_config: config.subscriptions,
+            ..Default::default()
+        },
+        counts: Arc::new(Mutex::new(MessageCounts::default())),
+        symbols: Arc::new(Mutex::new(vec![])),
+        resubscribe: Arc::new(AtomicBool::new(false)),
+    }))
+}
+
+// What a vendor's message holds: a quote for each object mapped, whether the message is a single
+// object or an array of them
+pub fn map_text(text: &str, mapping: &QuoteMapping, received: DateTime<Local>) -> Vec<Incoming> {
+    match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(values)) => values
+            .iter()
+            .map(|value| implementation::map_value(value, mapping, received))
+            .collect(),
+        Ok(value) => vec![implementation::map_value(&value, mapping, received)],
+        Err(e) => vec![Incoming::Malformed(format!("{}: {}", e, text))],
+    }
+}
+
+// The template with "{symbols}" and "{token}" filled in
+pub fn fill(template: &str, symbols: &[String], access_token: &str) -> String {
+    let symbols_json = serde_json::to_string(symbols).expect("Error serializing symbols");
+    template
+        .replace("{symbols}", &symbols_json)
+        .replace("{token}", access_token)
+}
+
+pub mod implementation {
+    use super::*;
+    use std::{sync::atomic::Ordering, thread};
+
+    pub struct WebsocketMarketData {
+        pub access_token: String,
+        pub config: WebsocketConfig,
+        pub subscribers: Subscribers,
+        pub counts: Arc<Mutex<MessageCounts>>,
+        // Those subscribed to, kept across reconnections
+        pub symbols: Arc<Mutex<Vec<String>>>,
+        // Set when the symbols change, so the subscription is changed to match
+        pub resubscribe: Arc<AtomicBool>,
+    }
+
+    impl MarketDataService for WebsocketMarketData {
+        fn init(
+            &self,
+            shutdown: Arc<AtomicBool>,
+            symbols: Vec<String>,
+        ) -> Result<JoinHandle<()>, String> {
+            let token = self.access_token.clone();
+            let config = self.config.clone();
+            let subscribers = Subscribers {
+                shutdown: shutdown.clone(),
+                ..self.subscribers.clone()
+            };
+            let counts = self.counts.clone();
+            change_symbols(&self.symbols, &symbols, &[])?;
+            let current = self.symbols.clone();
+            let resubscribe = self.resubscribe.clone();
+
+            Ok(thread::spawn(move || {
+                stream(
+                    &shutdown,
+                    &current,
+                    &resubscribe,
+                    &subscribers,
+                    &counts,
+                    |symbols| {
+                        connect_and_subscribe(&config, &token, symbols)
+                            .map(|socket| (socket, ()))
+                            .map_err(|e| format!("{}: {}", config.url, e))
+                    },
+                    |socket, _, before, after| {
+                        change_subscription(socket, &config, &token, before, after)
+                    },
+                    |msg| handle_message(msg, &config.mapping, &subscribers, &counts),
+                );
+            }))
+        }
+
+        fn subscribe(&self) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe()
+        }
+
+        fn subscribe_with(&self, config: SubscriptionConfig) -> Result<Receiver<Quote>, String> {
+            self.subscribers.subscribe_with(config)
+        }
+
+        fn unsubscribe(&self, subscriber: &Receiver<Quote>) -> Result<(), String> {
+            self.subscribers.unsubscribe(subscriber)
+        }
+
+        fn add_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            if change_symbols(&self.symbols, symbols, &[])? {
+                self.resubscribe.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+
+        fn remove_symbols(&self, symbols: &[String]) -> Result<(), String> {
+            if change_symbols(&self.symbols, &[], symbols)? {
+                self.resubscribe.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+
+        fn subscribe_events(&self, types: &[EventType]) -> Result<Receiver<MarketEvent>, String> {
+            self.subscribers.subscribe_events(types)
+        }
+
+        fn unsubscribe_events(&self, subscriber: &Receiver<MarketEvent>) -> Result<(), String> {
+            self.subscribers.unsubscribe_events(subscriber)
+        }
+
+        // Quotes as mapped, in Tradier's form, so that recordings replay whichever provider made them
+        fn subscribe_raw(&self) -> Result<Receiver<String>, String> {
+            self.subscribers.subscribe_raw()
+        }
+
+        fn message_counts(&self) -> MessageCounts {
+            *self.counts.lock().unwrap()
+        }
+
+        fn subscriber_metrics(&self) -> Vec<SubscriberMetrics> {
+            self.subscribers.metrics()
+        }
+    }
+
+    fn connect_and_subscribe(
+        config: &WebsocketConfig,
+        access_token: &str,
+        symbols: &[String],
+    ) -> Result<Socket, String> {
+        let url = fill(&config.url, symbols, access_token);
+        let (mut socket, _) = connect(url.as_str()).map_err(|e| e.to_string())?;
+        let mut messages: Vec<String> = config
+            .login
+            .iter()
+            .map(|login| fill(login, symbols, access_token))
+            .collect();
+        if !symbols.is_empty() {
+            messages.push(fill(&config.subscribe, symbols, access_token));
+        }
+        for message in messages {
+            socket
+                .send(Message::Text(message))
+                .map_err(|e| e.to_string())?;
+        }
+        set_read_timeout(&socket, READ_TIMEOUT).map_err(|e| e.to_string())?;
+        Ok(socket)
+    }
+
+    // With an unsubscribe message, only the symbols removed and added are sent. Without one, the whole
+    // subscription is sent again.
+    fn change_subscription(
+        socket: &mut Socket,
+        config: &WebsocketConfig,
+        access_token: &str,
+        before: &[String],
+        after: &[String],
+    ) -> Result<(), String> {
+        let messages = match &config.unsubscribe {
+            Some(unsubscribe) => {
+                let removed: Vec<String> = before
+                    .iter()
+                    .filter(|symbol| !after.contains(symbol))
+                    .cloned()
+                    .collect();
+                let added: Vec<String> = after
+                    .iter()
+                    .filter(|symbol| !before.contains(symbol))
+                    .cloned()
+                    .collect();
+                [(unsubscribe, removed), (&config.subscribe, added)]
+                    .into_iter()
+                    .filter(|(_, symbols)| !symbols.is_empty())
+                    .map(|(template, symbols)| fill(template, &symbols, access_token))
+                    .collect()
+            }
+            None if after.is_empty() => {
+                warn!("No symbols left, keeping the previous subscription");
+                vec![]
+            }
+            None => vec![fill(&config.subscribe, after, access_token)],
+        };
+        messages.into_iter().try_for_each(|message| {
+            socket
+                .send(Message::Text(message))
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    // False once the server has closed the stream
+    fn handle_message(
+        msg: Message,
+        mapping: &QuoteMapping,
+        subscribers: &Subscribers,
+        counts: &Mutex<MessageCounts>,
+    ) -> bool {
+        let incoming = match msg {
+            Message::Text(text) => map_text(&text, mapping, Local::now()),
+            Message::Binary(bytes) => {
+                map_text(&String::from_utf8_lossy(&bytes), mapping, Local::now())
+            }
+            msg => classify(msg),
+        };
+        let mut open = true;
+        for incoming in incoming {
+            if let Incoming::Event(event) = &incoming {
+                match serde_json::to_string(event) {
+                    Ok(raw) => subscribers.publish_raw(&raw),
+                    Err(e) => warn!("Error serializing {:?}: {}", event, e),
+                }
+            }
+            open &= handle_incoming(incoming, subscribers, counts);
+        }
+        open
+    }
+
+    // Without a type field, objects without a symbol are taken to be something other than a quote
+    pub fn map_value(value: &Value, mapping: &QuoteMapping, received: DateTime<Local>) -> Incoming {
+        let is_quote = match &mapping.type_field {
+            Some(type_field) => match field(value, type_field) {
+                Some(Value::String(t)) => *t == mapping.quote_type,
+                // Such as a numeric type
+                Some(t) => {
+                    serde_json::from_str::<Value>(&mapping.quote_type).ok() == Some(t.clone())
+                }
+                None => false,
+            },
+            None => field(value, &mapping.symbol).is_some(),
+        };
+        if !is_quote {
+            return Incoming::Unknown(value.to_string());
+        }
+        match quote(value, mapping, received) {
+            Ok(quote) => Incoming::Event(MarketEvent::Quote(quote)),
+            Err(e) => Incoming::Malformed(format!("{}: {}", e, value)),
+        }
+    }
+
+    fn quote(
+        value: &Value,
+        mapping: &QuoteMapping,
+        received: DateTime<Local>,
+    ) -> Result<Quote, String> {
+        let size = |path: &Option<String>| match path {
+            Some(path) => number(value, path).map(|size| size as i64),
+            None => Ok(0),
+        };
+        let exchange = |path: &Option<String>| {
+            path.as_ref()
+                .and_then(|path| field(value, path))
+                .map(|exchange| match exchange {
+                    Value::String(exchange) => exchange.clone(),
+                    exchange => exchange.to_string(),
+                })
+                .unwrap_or_default()
+        };
+        let time = |path: &Option<String>| match path {
+            Some(path) => time(value, path, mapping.time_format),
+            None => Ok(received),
+        };
+
+        Ok(Quote {
+            symbol: field(value, &mapping.symbol)
+                .and_then(Value::as_str)
+                .ok_or(format!("No symbol at {}", mapping.symbol))?
+                .to_string(),
+            bid: number(value, &mapping.bid)?,
+            ask: number(value, &mapping.ask)?,
+            biddate: time(&mapping.bid_time)?,
+            askdate: time(&mapping.ask_time)?,
+            bidsz: size(&mapping.bid_size)?,
+            asksz: size(&mapping.ask_size)?,
+            bidexch: exchange(&mapping.bid_exchange),
+            askexch: exchange(&mapping.ask_exchange),
+        })
+    }
+
+    // The value at a dotted path, e.g. "data.quote.bid"
+    fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+        path.split('.')
+            .try_fold(value, |value, key| value.get(key))
+            .filter(|value| !value.is_null())
+    }
+
+    // Vendors differ over whether numbers are sent as numbers or strings
+    fn number(value: &Value, path: &str) -> Result<f64, String> {
+        match field(value, path) {
+            Some(Value::Number(n)) => n.as_f64().ok_or(format!("Invalid number at {}", path)),
+            Some(Value::String(s)) => s
+                .parse()
+                .map_err(|_| format!("Invalid number at {}: {}", path, s)),
+            _ => Err(format!("No number at {}", path)),
+        }
+    }
+
+    fn time(value: &Value, path: &str, format: TimeFormat) -> Result<DateTime<Local>, String> {
+        let invalid = || format!("Invalid time at {}", path);
+        match format {
+            TimeFormat::EpochMillis => Local
+                .timestamp_millis_opt(number(value, path)? as i64)
+                .single()
+                .ok_or_else(invalid),
+            TimeFormat::EpochSeconds => Local
+                .timestamp_millis_opt((number(value, path)? * 1000.0) as i64)
+                .single()
+                .ok_or_else(invalid),
+            TimeFormat::Rfc3339 => field(value, path)
+                .and_then(Value::as_str)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Local))
+                .ok_or_else(invalid),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/market_data_websocket_test.rs"]
+mod market_data_websocket_test;
@@ -1,101 +1,245 @@
+use crate::broker::{self, Balances, BrokerAdapter, OrderState, OrderStatus};
 use crate::persistence::PersistenceService;
-use core::http::*;
 use domain::domain::*;
 use log::*;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Mutex};
 
+// Balances are reread from the broker once this old, or once an order in flight is done
+pub const BALANCE_TTL: Duration = Duration::from_secs(30);
+
 pub trait OrderService {
+    // Buys are sized down to the buying power available, or refused if none is
+    // Orders are placed in the service's account, whatever account they name
     fn create_order(&self, order: Order, strategy: String) -> Result<Order, String>;
     fn get_position(&self, symbol: &str) -> Option<Position>;
     fn update_position(&self, position: &Position);
+
+    // The account's balances, less the buying power reserved for buys in flight
+    fn balances(&self) -> Result<Balances, String> {
+        Err("Balances are not available".to_string())
+    }
 }
 
+// Orders for the default Tradier account, whose positions replace any stored locally
 pub fn new(
     access_token: String,
     account_id: String,
     base_url: String,
     persistence: Arc<impl PersistenceService + Send + Sync>,
 ) -> Result<Arc<impl OrderService>, String> {
-    let positions = implementation::read_positions(&base_url, &access_token, &account_id)?;
+    persistence.drop_positions("default")?;
+    with_broker(
+        "default".to_string(),
+        broker::tradier(access_token, account_id, base_url),
+        persistence,
+    )
+}
+
+// Orders for the named account at any broker. Its orders and positions are stored under its name,
+// alongside those of other accounts, so dropping stale positions is left to the caller.
+pub fn with_broker(
+    account: String,
+    broker: Arc<dyn BrokerAdapter + Send + Sync>,
+    persistence: Arc<impl PersistenceService + Send + Sync>,
+) -> Result<Arc<impl OrderService>, String> {
+    let positions = implementation::read_positions(&account, broker.as_ref());
     info!("Read positions from broker:\n{:?}", positions);
     implementation::update_local_positions(persistence.clone(), &positions)?;
 
     Ok(Arc::new(implementation::Orders {
-        access_token,
-        account_id,
-        base_url,
+        account,
+        broker,
         persistence,
         positions: Arc::new(Mutex::new(positions)),
+        balances: Mutex::new(implementation::BalanceCache::default()),
     }))
 }
 
 pub mod implementation {
     use super::*;
     use chrono::Local;
-    use serde::Deserialize;
+
+    pub use crate::broker::implementation::url;
 
     pub struct Orders<P: PersistenceService + Send + Sync> {
-        pub access_token: String,
-        pub account_id: String,
-        pub base_url: String,
+        pub account: String,
+        pub broker: Arc<dyn BrokerAdapter + Send + Sync>,
         pub persistence: Arc<P>,
         pub positions: Arc<Mutex<HashMap<String, Position>>>,
+        pub balances: Mutex<BalanceCache>,
+    }
+
+    #[derive(Default)]
+    pub struct BalanceCache {
+        // The broker's balances, and when they were read
+        pub read: Option<(Instant, Balances)>,
+        // Buying power set aside for buys the broker hasn't finished with, by order id
+        pub in_flight: HashMap<i64, f64>,
+        // Buying power set aside for buys being submitted, which have no order id yet
+        pub submitting: HashMap<u64, f64>,
+        pub next_submission: u64,
+    }
+
+    impl BalanceCache {
+        pub fn available(&self) -> Option<Balances> {
+            let reserved: f64 =
+                self.in_flight.values().sum::<f64>() + self.submitting.values().sum::<f64>();
+            self.read.map(|(_, balances)| Balances {
+                buying_power: balances.buying_power - reserved,
+                ..balances
+            })
+        }
+    }
+
+    // The broker is only called with the cache unlocked, so that reading balances isn't held up by
+    // orders being placed
+    impl<P: PersistenceService + Send + Sync> Orders<P> {
+        fn lock(&self) -> Result<std::sync::MutexGuard<'_, BalanceCache>, String> {
+            self.balances.lock().map_err(|e| e.to_string())
+        }
+
+        fn refresh(&self) -> Result<Balances, String> {
+            let (ids, stale) = {
+                let cache = self.lock()?;
+                let ids: Vec<i64> = cache.in_flight.keys().copied().collect();
+                let stale =
+                    !matches!(cache.read, Some((read_at, _)) if read_at.elapsed() < BALANCE_TTL);
+                (ids, stale)
+            };
+            // A finished order is reflected in the broker's balances, so its reservation goes once
+            // they're reread
+            let finished: Vec<i64> = ids
+                .into_iter()
+                .filter(|id| matches!(self.broker.status(*id), Ok(state) if !in_flight(&state)))
+                .collect();
+            let read = if stale || !finished.is_empty() {
+                Some((Instant::now(), self.broker.balances()?))
+            } else {
+                None
+            };
+
+            let mut cache = self.lock()?;
+            finished.iter().for_each(|id| {
+                cache.in_flight.remove(id);
+            });
+            if read.is_some() {
+                cache.read = read;
+            }
+            cache.available().ok_or("No balances read".to_string())
+        }
+
+        fn submit(&self, order: Order) -> Result<(Order, OrderState), String> {
+            let (order, submission) = match (&order.side, order.px) {
+                (Side::Buy, Some(price)) if price > 0.0 => self.reserve(order, price)?,
+                _ => (order, None),
+            };
+
+            let submitted = self.broker.submit(&order);
+            let mut cache = self.lock()?;
+            let reserved = submission.and_then(|submission| cache.submitting.remove(&submission));
+            let state = submitted?;
+            match reserved {
+                Some(cost) if in_flight(&state) => {
+                    cache.in_flight.insert(state.id, cost);
+                }
+                // Filled already, so the balances have changed
+                _ if !in_flight(&state) => cache.read = None,
+                _ => {}
+            }
+            Ok((order, state))
+        }
+
+        // Buys are held to the buying power left once that of buys in flight is set aside, and what
+        // they may cost is set aside in turn while they're submitted. Should the balances not be read,
+        // the buy is placed as it is.
+        fn reserve(&self, order: Order, price: f64) -> Result<(Order, Option<u64>), String> {
+            if let Err(e) = self.refresh() {
+                warn!(
+                    "Placing {} {} without checking buying power: {}",
+                    order.quantity, order.symbol, e
+                );
+                return Ok((order, None));
+            }
+            let mut cache = self.lock()?;
+            let available = cache
+                .available()
+                .ok_or("No balances read".to_string())?
+                .buying_power;
+            let order = affordable(order, price, available)?;
+            let submission = cache.next_submission;
+            cache.next_submission += 1;
+            cache
+                .submitting
+                .insert(submission, price * order.quantity as f64);
+            Ok((order, Some(submission)))
+        }
     }
 
-    #[derive(Deserialize, Debug)]
-    struct OrderResponse {
-        order: OrderData,
+    fn affordable(order: Order, price: f64, available: f64) -> Result<Order, String> {
+        match (available.max(0.0) / price) as i64 {
+            n if n >= order.quantity => Ok(order),
+            n if n > 0 => {
+                warn!(
+                    "Buying {} {} rather than {}, with {:.2} buying power available",
+                    n, order.symbol, order.quantity, available
+                );
+                Ok(Order {
+                    quantity: n,
+                    ..order
+                })
+            }
+            _ => Err(format!(
+                "Insufficient buying power for {} {}: {:.2} available",
+                order.quantity, order.symbol, available
+            )),
+        }
     }
 
-    #[derive(Deserialize, Debug)]
-    struct OrderData {
-        id: i64,
-        status: String,
+    fn in_flight(state: &OrderState) -> bool {
+        matches!(
+            state.status,
+            OrderStatus::Open | OrderStatus::PartiallyFilled
+        )
     }
 
     impl<P: PersistenceService + Send + Sync> OrderService for Orders<P> {
         fn create_order(&self, order: Order, strategy: String) -> Result<Order, String> {
-            let url = format!(
-                "https://{}/v1/accounts/{}/orders",
-                self.base_url, self.account_id
-            );
-            let body = format!(
-                "account_id={}&class=equity&symbol={}&side={}&quantity={}&type=market&duration=day",
-                self.account_id, order.symbol, order.side, order.quantity
+            let order = Order {
+                account: self.account.clone(),
+                ..order
+            };
+            let (order, state) = self.submit(order)?;
+            // An order filled as it's placed, as paper orders are, is booked at its fill price
+            let new_order = Order {
+                px: state.avg_fill_price.or(order.px),
+                ..order.with_id(state.id)
+            };
+            match self.persistence.write(Box::new(new_order.clone())) {
+                Ok(_) => {}
+                Err(e) => info!("Error writing order: {}", e),
+            }
+
+            let position = position_after(
+                &new_order,
+                self.get_position(&order.symbol),
+                state.commission,
             );
+            match self.persistence.write(Box::new(position.clone())) {
+                Ok(_) => self.update_position(&position),
+                Err(e) => info!("Error writing position: {}", e),
+            }
 
-            let response = post::<OrderResponse>(&url, &self.access_token, body);
-            match response {
-                Ok(response) => match response.order.status.as_str() {
-                    "ok" => {
-                        info!("Response: {:?}", response);
-                        let new_order = order.with_id(response.order.id);
-                        match self.persistence.write(Box::new(new_order.clone())) {
-                            Ok(_) => {}
-                            Err(e) => info!("Error writing order: {}", e),
-                        }
-
-                        let position = position_from(&new_order, self.get_position(&order.symbol));
-                        match self.persistence.write(Box::new(position.clone())) {
-                            Ok(_) => self.update_position(&position),
-                            Err(e) => info!("Error writing position: {}", e),
-                        }
-
-                        if order.side == Side::Sell {
-                            let pnl = calc_pnl(position, &order, strategy);
-                            match self.persistence.write(Box::new(pnl.clone())) {
-                                Ok(_) => info!("Generated P&L: {:?}", pnl),
-                                Err(e) => info!("Error writing position: {}", e),
-                            }
-                        }
-
-                        Ok(new_order)
-                    }
-                    _ => Err(response.order.status),
-                },
-                Err(e) => Err(e),
+            if order.side == Side::Sell {
+                let pnl = calc_pnl_after(position, &new_order, strategy, state.commission);
+                match self.persistence.write(Box::new(pnl.clone())) {
+                    Ok(_) => info!("Generated P&L: {:?}", pnl),
+                    Err(e) => info!("Error writing position: {}", e),
+                }
             }
+
+            Ok(new_order)
         }
 
         fn get_position(&self, symbol: &str) -> Option<Position> {
@@ -109,6 +253,10 @@ pub mod implementation {
                 .unwrap()
                 .insert(position.symbol.clone(), position.clone());
         }
+
+        fn balances(&self) -> Result<Balances, String> {
+            self.refresh()
+        }
     }
 
     pub fn position_from(order: &Order, existing: Option<Position>) -> Position {
@@ -122,6 +270,12 @@ pub mod implementation {
         match existing {
             Some(position) => Position {
                 quantity: position.quantity + order.quantity,
+                // A flat position's cost basis is left over from the previous round trip
+                cost_basis: if position.quantity > 0 {
+                    position.cost_basis
+                } else {
+                    0.0
+                } + order.px.unwrap_or(0.0) * order.quantity as f64,
                 ..position
             },
             None => {
@@ -133,6 +287,7 @@ pub mod implementation {
                     quantity: order.quantity,
                     cost_basis: order.px.unwrap_or(0.0) * order.quantity as f64, // Estimate
                     date: Local::now(),
+                    account: order.account.clone(),
                 }
             }
         }
@@ -154,6 +309,32 @@ pub mod implementation {
         }
     }
 
+    // The position after a fill, whose commission is part of what a buy cost
+    pub fn position_after(order: &Order, existing: Option<Position>, commission: f64) -> Position {
+        let position = position_from(order, existing);
+        match order.side {
+            Side::Buy => Position {
+                cost_basis: position.cost_basis + commission,
+                ..position
+            },
+            Side::Sell => position,
+        }
+    }
+
+    // The P&L of a sale, whose commission comes out of the proceeds
+    pub fn calc_pnl_after(
+        position: Position,
+        order: &Order,
+        strategy: String,
+        commission: f64,
+    ) -> RealizedPnL {
+        let pnl = calc_pnl(position, order, strategy);
+        RealizedPnL {
+            pnl: pnl.pnl - commission,
+            ..pnl
+        }
+    }
+
     pub fn calc_pnl(position: Position, order: &Order, strategy: String) -> RealizedPnL {
         let price = order.px.unwrap_or(0.0);
         let proceeds = price * order.quantity as f64;
@@ -169,45 +350,29 @@ pub mod implementation {
             date: order.date,
             pnl,
             strategy: strategy.to_string(),
+            account: order.account.clone(),
         }
     }
 
-    #[derive(Deserialize)]
-    struct PositionResponse {
-        positions: Positions,
-    }
-
-    #[derive(Deserialize)]
-    struct Positions {
-        position: Vec<TradierPosition>,
-    }
-
+    // An account whose positions can't be read is taken to have none
     pub fn read_positions(
-        base_url: &str,
-        access_token: &str,
-        account_id: &str,
-    ) -> Result<HashMap<String, Position>, String> {
-        let url = format!("https://{}/v1/accounts/{}/positions", base_url, account_id);
-        info!("url: {}", url);
-        let response = get::<PositionResponse>(&url, access_token);
-
-        match response {
-            Ok(response) => {
-                let mut positions = HashMap::new();
-                response
-                    .positions
-                    .position
-                    .into_iter()
-                    .for_each(|position| {
-                        positions.insert(position.symbol.clone(), position.into());
-                    });
-                Ok(positions)
-            }
-            Err(e) => {
-                info!("Error reading positions - probably there are < 2: {}", e);
-                Ok(HashMap::new())
-            }
-        }
+        account: &str,
+        broker: &(dyn BrokerAdapter + Send + Sync),
+    ) -> HashMap<String, Position> {
+        let positions = broker.positions().unwrap_or_else(|e| {
+            info!("Error reading positions: {}", e);
+            HashMap::new()
+        });
+        positions
+            .into_iter()
+            .map(|(symbol, position)| {
+                let position = Position {
+                    account: account.to_string(),
+                    ..position
+                };
+                (symbol, position)
+            })
+            .collect()
     }
 
     pub fn update_local_positions(
@@ -215,7 +380,6 @@ pub mod implementation {
         positions: &HashMap<String, Position>,
     ) -> Result<(), String> {
         // In the future, we may rec, but for now we'll update all positions from the source of truth
-        persistence.drop_positions()?;
         positions
             .values()
             .try_for_each(|position| persistence.write(Box::new(position.clone())))
@@ -223,5 +387,6 @@ pub mod implementation {
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 #[path = "./tests/orders_test.rs"]
 mod orders_test;
@@ -0,0 +1,114 @@
+use crate::broker::{self, Balances, BrokerAdapter, PaperBook};
+use crate::market_data::MarketDataService;
+use crate::orders::{self, OrderService};
+use crate::persistence::PersistenceService;
+use app_config::app_config::{Backpressure, FillConfig, SubscriptionConfig};
+use chrono::Local;
+use domain::domain::{AccountBalance, Order, Position};
+use log::*;
+use std::sync::Arc;
+
+// Quotes held between orders; beyond this they're reduced to the latest for each symbol
+const QUOTE_CAPACITY: usize = 1024;
+
+// The paper account's book as last stored, so a forward test carries on across restarts, or a new book
+// holding `cash` if nothing was stored
+pub fn restore(
+    account: &str,
+    cash: f64,
+    persistence: &impl PersistenceService,
+) -> Result<PaperBook, String> {
+    match persistence.read_balance(account)? {
+        Some(balance) => {
+            let positions = persistence.read_positions(account)?;
+            let orders = persistence.read_orders(account)?;
+            info!(
+                "Restored paper account {} with {:.2} cash and positions {:?}",
+                account, balance.cash, positions
+            );
+            Ok(broker::restored_paper_book(
+                balance.cash,
+                positions,
+                &orders,
+            ))
+        }
+        None => Ok(broker::paper_book(cash)),
+    }
+}
+
+// Orders for a paper account, filled locally against live quotes, so that strategies can be
+// forward-tested with no broker account. The book carries the account's cash and positions over from
+// one day's market data to the next.
+pub fn new(
+    account: String,
+    book: PaperBook,
+    fills: FillConfig,
+    market_data: Arc<impl MarketDataService>,
+    persistence: Arc<impl PersistenceService + Send + Sync>,
+) -> Result<Arc<impl OrderService>, String> {
+    let quotes = market_data.subscribe_with(SubscriptionConfig {
+        capacity: Some(QUOTE_CAPACITY),
+        policy: Backpressure::Conflate,
+    })?;
+    let broker = broker::paper_with(book, fills, Some(quotes));
+    let orders = orders::with_broker(account.clone(), broker.clone(), persistence.clone())?;
+    let paper = implementation::PaperOrders {
+        account,
+        broker,
+        orders,
+        persistence,
+    };
+    paper.record_balance();
+    Ok(Arc::new(paper))
+}
+
+mod implementation {
+    use super::*;
+
+    pub struct PaperOrders<O: OrderService, P: PersistenceService> {
+        pub account: String,
+        pub broker: Arc<dyn BrokerAdapter + Send + Sync>,
+        pub orders: Arc<O>,
+        pub persistence: Arc<P>,
+    }
+
+    impl<O: OrderService, P: PersistenceService> PaperOrders<O, P> {
+        // The account's cash and equity are stored after every fill
+        pub fn record_balance(&self) {
+            let balance = self.broker.balances().map(|balances| AccountBalance {
+                account: self.account.clone(),
+                cash: balances.cash,
+                equity: balances.equity,
+                date: Local::now(),
+            });
+            match balance.and_then(|balance| self.persistence.write(Box::new(balance))) {
+                Ok(_) => {}
+                Err(e) => info!("Error writing balance of {}: {}", self.account, e),
+            }
+        }
+    }
+
+    impl<O: OrderService, P: PersistenceService> OrderService for PaperOrders<O, P> {
+        fn create_order(&self, order: Order, strategy: String) -> Result<Order, String> {
+            let filled = self.orders.create_order(order, strategy)?;
+            self.record_balance();
+            Ok(filled)
+        }
+
+        fn get_position(&self, symbol: &str) -> Option<Position> {
+            self.orders.get_position(symbol)
+        }
+
+        fn update_position(&self, position: &Position) {
+            self.orders.update_position(position)
+        }
+
+        fn balances(&self) -> Result<Balances, String> {
+            self.orders.balances()
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/paper_orders_test.rs"]
+mod paper_orders_test;
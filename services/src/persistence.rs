@@ -1,5 +1,5 @@
 use crossbeam_channel::{Receiver, Sender};
-use domain::domain::{Order, Persistable, Position};
+use domain::domain::{AccountBalance, Order, Persistable, Position};
 use log::*;
 use mongodb::{
     options::{ClientOptions, ServerApi, ServerApiVersion},
@@ -13,7 +13,13 @@ use std::{
 pub trait PersistenceService {
     fn init(&self, shutdown: Arc<AtomicBool>) -> Result<JoinHandle<()>, String>;
     fn write(&self, p: Box<dyn Persistable + Send>) -> Result<(), String>;
-    fn drop_positions(&self) -> Result<(), String>;
+    // An account's stored positions, to be replaced by those read afresh from its broker
+    fn drop_positions(&self, account: &str) -> Result<(), String>;
+
+    // What was last stored for an account, for those kept only locally
+    fn read_positions(&self, account: &str) -> Result<Vec<Position>, String>;
+    fn read_balance(&self, account: &str) -> Result<Option<AccountBalance>, String>;
+    fn read_orders(&self, account: &str) -> Result<Vec<Order>, String>;
 }
 
 pub fn new(url: String) -> Arc<impl PersistenceService> {
@@ -34,10 +40,10 @@ pub fn new(url: String) -> Arc<impl PersistenceService> {
 mod implementation {
     use super::*;
     use crossbeam_channel::TryRecvError;
-    use domain::domain::RealizedPnL;
+    use domain::domain::{AggregatedBar, RealizedPnL};
     use mongodb::bson::{self, doc, Bson};
     use serde::Serialize;
-    use std::{any::Any, thread, time::Duration};
+    use std::{thread, time::Duration};
 
     pub struct Persistence {
         pub client: Client,
@@ -86,11 +92,48 @@ mod implementation {
             self.sender.send(p).map_err(|e| e.to_string())
         }
 
-        fn drop_positions(&self) -> Result<(), String> {
+        fn drop_positions(&self, account: &str) -> Result<(), String> {
+            // Positions stored before they named their account were all the default account's
+            let filter = match account {
+                "default" => doc! { "$or": [
+                    { "account": account },
+                    { "account": { "$exists": false } }
+                ] },
+                _ => doc! { "account": account },
+            };
             self.client
                 .database("algo-trading")
                 .collection::<bson::Document>("positions")
-                .drop(None)
+                .delete_many(filter, None)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+
+        fn read_positions(&self, account: &str) -> Result<Vec<Position>, String> {
+            self.client
+                .database("algo-trading")
+                .collection::<Position>("positions")
+                .find(doc! { "account": account }, None)
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<Position>, _>>()
+                .map_err(|e| e.to_string())
+        }
+
+        fn read_balance(&self, account: &str) -> Result<Option<AccountBalance>, String> {
+            self.client
+                .database("algo-trading")
+                .collection::<AccountBalance>("balances")
+                .find_one(doc! { "account": account }, None)
+                .map_err(|e| e.to_string())
+        }
+
+        fn read_orders(&self, account: &str) -> Result<Vec<Order>, String> {
+            self.client
+                .database("algo-trading")
+                .collection::<Order>("orders")
+                .find(doc! { "account": account }, None)
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<Order>, _>>()
                 .map_err(|e| e.to_string())
         }
     }
@@ -99,18 +142,38 @@ mod implementation {
         fn write(&self, p: Box<dyn Persistable>) -> Result<(), String> {
             if let Some(order) = p.as_any().downcast_ref::<Order>() {
                 let filter: bson::Document = doc! {
+                    "account": order.account.clone(),
                     "symbol": order.symbol.clone(),
                     "date": order.date.format("%Y-%m-%d").to_string()
                 };
                 self.upsert("orders", order.id(), filter, &order)
             } else if let Some(position) = p.as_any().downcast_ref::<Position>() {
-                let filter: bson::Document = doc! { "symbol": position.symbol.clone() };
+                let filter: bson::Document = doc! {
+                    "account": position.account.clone(),
+                    "symbol": position.symbol.clone()
+                };
                 self.upsert("positions", position.id(), filter, &position)
             } else if let Some(pnl) = p.as_any().downcast_ref::<RealizedPnL>() {
-                let filter: bson::Document = doc! { "id": pnl.id() };
+                let filter: bson::Document = doc! {
+                    "account": pnl.account.clone(),
+                    "id": pnl.id()
+                };
                 self.upsert("pnl", pnl.id(), filter, &pnl)
+            } else if let Some(balance) = p.as_any().downcast_ref::<AccountBalance>() {
+                let filter: bson::Document = doc! { "account": balance.account.clone() };
+                self.upsert("balances", balance.id(), filter, &balance)
+            } else if let Some(bar) = p.as_any().downcast_ref::<AggregatedBar>() {
+                let filter: bson::Document = doc! {
+                    "symbol": bar.bar.symbol.clone(),
+                    "interval": bar.interval.to_string(),
+                    "timestamp": bson::to_bson(&bar.bar.timestamp).map_err(|e| e.to_string())?
+                };
+                self.upsert("bars", bar.id(), filter, &bar)
             } else {
-                Err(format!("Cannot handle unknown type: {:?}", p.type_id()))
+                Err(format!(
+                    "Cannot handle unknown type: {:?}",
+                    p.as_any().type_id()
+                ))
             }
         }
 
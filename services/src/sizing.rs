@@ -0,0 +1,99 @@
+use app_config::app_config::{Sizing, SizingModel, Volatility};
+use domain::domain::{Day, SymbolData};
+use log::*;
+
+// The shares a buy signal orders, given what's already `held`. No position grows beyond what the
+// symbol's `capital` buys. Models sized from equity use the account's, or the symbol's capital where
+// the account's isn't known, as in backtests.
+pub fn shares_to_buy(
+    sizing: &Sizing,
+    price: f64,
+    held: i64,
+    capital: i64,
+    equity: Option<f64>,
+    data: &SymbolData,
+) -> i64 {
+    if price <= 0.0 {
+        return 0;
+    }
+    let equity = equity.unwrap_or(capital as f64);
+    let limit = whole(capital as f64 / price);
+    let target = match sizing.model {
+        SizingModel::Capital => limit,
+        SizingModel::FixedShares => sizing.shares,
+        SizingModel::FixedNotional => whole(sizing.notional / price),
+        SizingModel::PercentOfEquity => whole(equity * sizing.percent / 100.0 / price),
+        SizingModel::VolatilityTarget => {
+            let volatility = match sizing.volatility {
+                Volatility::Atr => atr(&data.history, sizing.atr_days),
+                Volatility::StdDev => data.std_dev,
+            };
+            if volatility > 0.0 {
+                whole(equity * sizing.risk_percent / 100.0 / volatility)
+            } else {
+                0
+            }
+        }
+        SizingModel::Kelly => {
+            let fraction = kelly(sizing.win_rate, sizing.payoff_ratio) * sizing.kelly_fraction;
+            whole(equity * fraction / price)
+        }
+    }
+    .min(limit);
+
+    // Each tranche buys its share of the target, rounded up so that the last isn't left short
+    let tranche = (target + sizing.tranches.max(1) as i64 - 1) / sizing.tranches.max(1) as i64;
+    let shares = tranche.min(target - held);
+    let shares = shares - shares.rem_euclid(sizing.lot_size.max(1));
+    if shares <= 0 {
+        return 0;
+    }
+    if shares < sizing.min_shares || (shares as f64 * price) < sizing.min_notional {
+        info!(
+            "Not buying {} {} at {}, below the minimum order",
+            shares, data.symbol, price
+        );
+        return 0;
+    }
+    shares
+}
+
+// Rounded down, but not by float error, as in 0.6 - 0.4 coming to 0.19999...
+fn whole(shares: f64) -> i64 {
+    (shares + 1e-9).floor() as i64
+}
+
+// The share of equity the Kelly criterion bets, or nothing when the odds are against it
+pub fn kelly(win_rate: f64, payoff_ratio: f64) -> f64 {
+    if payoff_ratio <= 0.0 {
+        return 0.0;
+    }
+    (win_rate - (1.0 - win_rate) / payoff_ratio).max(0.0)
+}
+
+// The mean true range of the last `days` days; the first day of history has no previous close, so
+// its range is its high less its low
+pub fn atr(history: &[Day], days: usize) -> f64 {
+    let ranges: Vec<f64> = history
+        .iter()
+        .enumerate()
+        .map(
+            |(i, day)| match i.checked_sub(1).map(|prev| history[prev].close) {
+                Some(close) => (day.high - day.low)
+                    .max((day.high - close).abs())
+                    .max((day.low - close).abs()),
+                None => day.high - day.low,
+            },
+        )
+        .collect();
+    let recent = &ranges[ranges.len().saturating_sub(days.max(1))..];
+    if recent.is_empty() {
+        0.0
+    } else {
+        recent.iter().sum::<f64>() / recent.len() as f64
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/sizing_test.rs"]
+mod sizing_test;
@@ -0,0 +1,102 @@
+use super::*;
+use chrono::NaiveDate;
+use domain::domain::PriceAdjustment;
+
+fn strategy(name: &str, symbols: &[&str]) -> Strategy {
+    Strategy {
+        name: name.to_string(),
+        symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        capital: symbols.iter().map(|s| (s.to_string(), 1000)).collect(),
+        budget: 30000.0,
+        budget_percent: 10.0,
+        params: HashMap::new(),
+        prices: PriceAdjustment::Raw,
+        account: None,
+        sizing: Default::default(),
+    }
+}
+
+// Closes alternating by the given percent either side of 100
+fn history(swing: f64) -> Vec<Day> {
+    (0..30)
+        .map(|i| {
+            let close = if i % 2 == 0 { 100.0 } else { 100.0 + swing };
+            Day {
+                symbol: None,
+                date: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap() + chrono::Duration::days(i),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 0,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_budgets() {
+    let strategies = vec![strategy("a", &["SPY", "QQQ"]), strategy("b", &["IWM"])];
+    let history = HashMap::new();
+    let by = |method| {
+        allocate(
+            &Allocation {
+                method,
+                ..Allocation::default()
+            },
+            &strategies,
+            200000.0,
+            &history,
+        )
+    };
+
+    // As configured
+    assert_eq!(by(AllocationMethod::Capital)["a"]["SPY"], 1000);
+
+    // Split evenly among the strategy's symbols
+    let fixed = by(AllocationMethod::Fixed);
+    assert_eq!((fixed["a"]["SPY"], fixed["a"]["QQQ"]), (15000, 15000));
+    assert_eq!(fixed["b"]["IWM"], 30000);
+
+    let percent = by(AllocationMethod::PercentOfEquity);
+    assert_eq!(percent["a"]["QQQ"], 10000);
+    assert_eq!(percent["b"]["IWM"], 20000);
+}
+
+#[test]
+fn test_inverse_volatility() {
+    let strategies = vec![strategy("a", &["SPY", "QQQ"]), strategy("b", &["IWM"])];
+    // QQQ and IWM swing twice as much as SPY
+    let history = HashMap::from([
+        ("SPY".to_string(), history(1.0)),
+        ("QQQ".to_string(), history(2.0)),
+        ("IWM".to_string(), history(2.0)),
+    ]);
+    let allocation = Allocation {
+        method: AllocationMethod::InverseVolatility,
+        percent: 50.0,
+        ..Allocation::default()
+    };
+    let capital = allocate(&allocation, &strategies, 200000.0, &history);
+
+    // Of the 100000 shared, SPY carries as much risk as QQQ or IWM with about twice the capital
+    let spy = capital["a"]["SPY"] as f64;
+    let qqq = capital["a"]["QQQ"] as f64;
+    let iwm = capital["b"]["IWM"] as f64;
+    assert!((spy / qqq - 2.0).abs() < 0.05, "{} {}", spy, qqq);
+    assert!((qqq - iwm).abs() < 2.0);
+    assert!((spy + qqq + iwm - 100000.0).abs() < 3.0);
+
+    // A symbol without history gets nothing, and the rest is shared as before
+    let history = HashMap::from([("SPY".to_string(), history["SPY"].clone())]);
+    let capital = allocate(&allocation, &strategies, 200000.0, &history);
+    assert_eq!(capital["a"]["SPY"], 100000);
+    assert!(capital["b"].is_empty());
+}
+
+#[test]
+fn test_volatility() {
+    assert_eq!(volatility(&history(0.0), 20), 0.0);
+    assert!(volatility(&history(2.0), 20) > volatility(&history(1.0), 20));
+    assert_eq!(volatility(&history(1.0)[..2], 20), 0.0);
+}
@@ -0,0 +1,240 @@
+use super::*;
+use crate::market_data_recorder::{Record, RotatingWriter};
+use crate::market_data_replay;
+use app_config::app_config::{ReplayConfig, ReplaySpeed, SubscriptionConfig};
+use chrono::TimeZone;
+use domain::domain::{AccountBalance, Order, Persistable, Position};
+use std::{sync::atomic::Ordering, thread::JoinHandle, time::Duration};
+
+fn at(minute: u32, second: u32) -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(2024, 6, 3, 9, minute, second)
+        .earliest()
+        .unwrap()
+}
+
+fn quote(symbol: &str, time: DateTime<Local>, mid: f64) -> Quote {
+    Quote {
+        symbol: symbol.to_string(),
+        bid: mid - 0.5,
+        ask: mid + 0.5,
+        biddate: time,
+        askdate: time,
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    }
+}
+
+#[test]
+fn test_period_start() {
+    assert_eq!(period_start(at(31, 59), Interval::Minute), at(31, 0));
+    assert_eq!(period_start(at(34, 10), Interval::FiveMinute), at(30, 0));
+    assert_eq!(period_start(at(31, 59), Interval::Second), at(31, 59));
+}
+
+#[test]
+fn test_builder() {
+    let mut builder = Builder::new(Interval::Minute);
+    builder.add("SPY", at(30, 5), 100.0, 0);
+    builder.add("SPY", at(30, 20), 102.0, 10);
+    builder.add("SPY", at(30, 40), 99.0, 0);
+    builder.add("QQQ", at(30, 50), 400.0, 0);
+    builder.add("SPY", at(30, 59), 101.0, 5);
+    assert!(builder.close(at(30, 59)).is_empty());
+
+    let bars = builder.close(at(31, 0));
+    assert_eq!(
+        bars.iter().map(|b| b.symbol.as_str()).collect::<Vec<_>>(),
+        vec!["QQQ", "SPY"]
+    );
+    assert_eq!(
+        bars[1],
+        Bar {
+            symbol: "SPY".to_string(),
+            timestamp: at(30, 0),
+            open: 100.0,
+            high: 102.0,
+            low: 99.0,
+            close: 101.0,
+            volume: 15,
+        }
+    );
+
+    // A stale price is folded into the open bar
+    builder.add("SPY", at(32, 0), 103.0, 0);
+    builder.add("SPY", at(31, 30), 104.0, 0);
+    let bars = builder.close(at(33, 0));
+    assert_eq!(bars[0].timestamp, at(32, 0));
+    assert_eq!(bars[0].high, 104.0);
+}
+
+struct MockPersistenceService {
+    bars: Mutex<Vec<AggregatedBar>>,
+}
+
+impl PersistenceService for MockPersistenceService {
+    fn init(&self, _: Arc<AtomicBool>) -> Result<JoinHandle<()>, String> {
+        unimplemented!()
+    }
+
+    fn write(&self, p: Box<dyn Persistable + Send>) -> Result<(), String> {
+        let bar = p.as_any().downcast_ref::<AggregatedBar>().unwrap();
+        self.bars.lock().unwrap().push(bar.clone());
+        Ok(())
+    }
+
+    fn drop_positions(&self, _: &str) -> Result<(), String> {
+        unimplemented!()
+    }
+
+    fn read_positions(&self, _: &str) -> Result<Vec<Position>, String> {
+        unimplemented!()
+    }
+
+    fn read_balance(&self, _: &str) -> Result<Option<AccountBalance>, String> {
+        unimplemented!()
+    }
+
+    fn read_orders(&self, _: &str) -> Result<Vec<Order>, String> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn test_aggregates_replayed_quotes() {
+    let path = std::env::temp_dir().join(format!("bar_aggregator_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    let quotes = [
+        quote("SPY", at(30, 0), 100.0),
+        quote("SPY", at(30, 30), 101.0),
+        quote("SPY", at(31, 10), 102.0),
+        quote("SPY", at(32, 0), 103.0),
+    ];
+    for quote in quotes.iter() {
+        let raw = serde_json::to_string(&MarketEvent::Quote(quote.clone())).unwrap();
+        writer.write(&Record::new(quote.biddate, raw)).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let market_data = market_data_replay::new(
+        &ReplayConfig {
+            path: path.to_string_lossy().to_string(),
+            speed: ReplaySpeed::AsFastAsPossible,
+            factor: 1.0,
+        },
+        SubscriptionConfig::default(),
+    );
+    let persistence = Arc::new(MockPersistenceService {
+        bars: Mutex::new(vec![]),
+    });
+    let aggregator = new(
+        &BarConfig {
+            intervals: vec![Interval::Minute, Interval::FiveMinute],
+            persist: true,
+        },
+        market_data.clone(),
+        persistence.clone(),
+    );
+    assert!(aggregator.subscribe(Interval::Second).is_err());
+    let minutes = aggregator.subscribe(Interval::Minute).unwrap();
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = aggregator.init(shutdown.clone()).unwrap();
+    market_data
+        .init(shutdown.clone(), vec![])
+        .unwrap()
+        .join()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    let bars: Vec<Bar> = minutes.try_iter().collect();
+    assert_eq!(bars.len(), 2);
+    assert_eq!((bars[0].open, bars[0].close), (100.0, 101.0));
+    assert_eq!((bars[1].timestamp, bars[1].open), (at(31, 0), 102.0));
+    // The 09:32 bar and the 5-minute bar are still open
+    let persisted = persistence.bars.lock().unwrap();
+    assert_eq!(persisted.len(), 2);
+    assert!(persisted.iter().all(|b| b.interval == Interval::Minute));
+
+    aggregator.unsubscribe(&minutes).unwrap();
+    assert!(aggregator.unsubscribe(&minutes).is_err());
+}
+
+#[test]
+fn test_prefers_trades() {
+    let path =
+        std::env::temp_dir().join(format!("bar_aggregator_test_trades_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let trade = |time: DateTime<Local>, price: f64, size: i64| {
+        format!(
+            r#"{{"type":"trade","symbol":"SPY","exch":"J","price":"{}","size":"{}","cvol":"0","date":"{}","last":"{}"}}"#,
+            price,
+            size,
+            time.timestamp_millis(),
+            price
+        )
+    };
+    let messages = [
+        serde_json::to_string(&MarketEvent::Quote(quote("SPY", at(30, 0), 100.0))).unwrap(),
+        trade(at(30, 10), 100.5, 100),
+        // Quotes no longer count once the symbol has traded
+        serde_json::to_string(&MarketEvent::Quote(quote("SPY", at(30, 20), 150.0))).unwrap(),
+        trade(at(30, 30), 99.5, 300),
+        trade(at(31, 0), 101.0, 100),
+    ];
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    for message in messages {
+        writer.write(&Record::new(at(30, 0), message)).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let market_data = market_data_replay::new(
+        &ReplayConfig {
+            path: path.to_string_lossy().to_string(),
+            speed: ReplaySpeed::AsFastAsPossible,
+            factor: 1.0,
+        },
+        SubscriptionConfig::default(),
+    );
+    let aggregator = new(
+        &BarConfig {
+            intervals: vec![Interval::Minute],
+            persist: false,
+        },
+        market_data.clone(),
+        Arc::new(MockPersistenceService {
+            bars: Mutex::new(vec![]),
+        }),
+    );
+    let minutes = aggregator.subscribe(Interval::Minute).unwrap();
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = aggregator.init(shutdown.clone()).unwrap();
+    market_data
+        .init(shutdown.clone(), vec![])
+        .unwrap()
+        .join()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    let bars: Vec<Bar> = minutes.try_iter().collect();
+    assert_eq!(
+        bars,
+        vec![Bar {
+            symbol: "SPY".to_string(),
+            timestamp: at(30, 0),
+            open: 100.0,
+            high: 100.5,
+            low: 99.5,
+            close: 99.5,
+            volume: 400,
+        }]
+    );
+}
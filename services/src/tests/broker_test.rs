@@ -0,0 +1,202 @@
+use super::*;
+use crate::brokerage_stub::BrokerageStub;
+use chrono::NaiveDate;
+
+fn order(symbol: &str, side: Side, quantity: i64, px: f64) -> Order {
+    Order {
+        id: None,
+        date: NaiveDate::from_ymd_opt(2024, 6, 3).unwrap(),
+        symbol: symbol.to_string(),
+        side,
+        quantity,
+        px: Some(px),
+        account: String::new(),
+    }
+}
+
+fn stub_broker(stub: &BrokerageStub) -> Arc<dyn BrokerAdapter + Send + Sync> {
+    tradier(
+        "stub-token".to_string(),
+        "VA000001".to_string(),
+        stub.base_url.clone(),
+    )
+}
+
+#[test]
+fn test_tradier_orders() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    stub.set_cash(10000.0);
+    let broker = stub_broker(&stub);
+
+    let id = broker
+        .submit(&order("SPY", Side::Buy, 10, 100.0))
+        .unwrap()
+        .id;
+    assert_eq!(
+        broker.status(id).unwrap(),
+        OrderState {
+            id,
+            status: OrderStatus::Filled,
+            filled: 10,
+            avg_fill_price: Some(100.0),
+            commission: 0.0
+        }
+    );
+    assert!(broker.cancel(id).unwrap_err().contains("is filled"));
+    assert_eq!(stub.cash(), 9000.0);
+
+    stub.hold_orders(true);
+    let id = broker
+        .submit(&order("SPY", Side::Buy, 5, 100.0))
+        .unwrap()
+        .id;
+    let open = broker.status(id).unwrap();
+    assert_eq!(
+        (open.status, open.filled, open.avg_fill_price),
+        (OrderStatus::Open, 0, None)
+    );
+    broker.cancel(id).unwrap();
+    assert_eq!(broker.status(id).unwrap().status, OrderStatus::Canceled);
+
+    assert!(broker.status(99).is_err());
+    assert_eq!(broker.positions().unwrap()["SPY"].quantity, 10);
+}
+
+#[test]
+fn test_tradier_balances_and_account() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_cash(5000.0);
+    stub.set_price("SPY", 110.0);
+    stub.add_position("SPY", 10, 1000.0);
+    let broker = stub_broker(&stub);
+
+    assert_eq!(
+        broker.balances().unwrap(),
+        Balances {
+            cash: 5000.0,
+            equity: 6100.0,
+            buying_power: 10000.0
+        }
+    );
+    assert_eq!(
+        broker.account().unwrap(),
+        AccountInfo {
+            account_id: "VA000001".to_string(),
+            account_type: "margin".to_string(),
+            status: "active".to_string()
+        }
+    );
+
+    let other = tradier(
+        "stub-token".to_string(),
+        "VA000002".to_string(),
+        stub.base_url.clone(),
+    );
+    assert!(other.account().is_err());
+}
+
+#[test]
+fn test_paper_broker() {
+    let broker = paper(1000.0);
+    let id = broker
+        .submit(&order("SPY", Side::Buy, 5, 100.0))
+        .unwrap()
+        .id;
+    assert_eq!(broker.status(id).unwrap().status, OrderStatus::Filled);
+    assert!(broker.cancel(id).is_err());
+
+    assert!(broker
+        .submit(&order("SPY", Side::Buy, 6, 100.0))
+        .unwrap_err()
+        .contains("Insufficient cash"));
+    assert!(broker
+        .submit(&order("SPY", Side::Sell, 6, 100.0))
+        .unwrap_err()
+        .contains("holding 5"));
+    assert!(broker
+        .submit(&Order {
+            px: None,
+            ..order("SPY", Side::Buy, 1, 0.0)
+        })
+        .is_err());
+
+    broker.submit(&order("SPY", Side::Sell, 2, 110.0)).unwrap();
+    let position = &broker.positions().unwrap()["SPY"];
+    assert_eq!((position.quantity, position.cost_basis), (3, 300.0));
+    assert_eq!(
+        broker.balances().unwrap(),
+        Balances {
+            cash: 720.0,
+            equity: 1050.0,
+            buying_power: 720.0
+        }
+    );
+
+    broker.submit(&order("SPY", Side::Sell, 3, 110.0)).unwrap();
+    assert!(broker.positions().unwrap().is_empty());
+    assert_eq!(broker.account().unwrap().account_type, "paper");
+}
+
+#[test]
+fn test_paper_broker_fills_at_quotes() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let fills = FillConfig {
+        slippage_bps: 50.0,
+        commission: app_config::app_config::CommissionConfig {
+            per_share: 0.1,
+            ..Default::default()
+        },
+    };
+    let broker = paper_with(paper_book(1000.0), fills, Some(receiver));
+    let now = chrono::Local::now();
+    sender
+        .send(Quote {
+            symbol: "SPY".to_string(),
+            bid: 99.0,
+            ask: 100.0,
+            biddate: now,
+            askdate: now,
+            bidsz: 0,
+            asksz: 0,
+            bidexch: String::new(),
+            askexch: String::new(),
+        })
+        .unwrap();
+
+    // The ask plus 50bps, and 10 cents a share
+    let state = broker.submit(&order("SPY", Side::Buy, 5, 90.0)).unwrap();
+    assert_eq!(
+        (state.status, state.avg_fill_price, state.commission),
+        (OrderStatus::Filled, Some(100.5), 0.5)
+    );
+    let balances = broker.balances().unwrap();
+    assert_eq!(balances.cash, 1000.0 - 502.5 - 0.5);
+    // Valued at the quote's midpoint
+    assert_eq!(balances.equity, balances.cash + 5.0 * 99.5);
+
+    // The fill's cost and commission together must be covered
+    assert!(broker
+        .submit(&order("SPY", Side::Buy, 5, 90.0))
+        .unwrap_err()
+        .contains("Insufficient cash"));
+}
+
+#[test]
+fn test_new_from_config() {
+    let paper_account = AccountConfig {
+        broker: BrokerKind::Paper,
+        cash: 500.0,
+        ..AccountConfig::default()
+    };
+    assert_eq!(new(&paper_account).unwrap().balances().unwrap().cash, 500.0);
+
+    let tradier_account = AccountConfig {
+        token_env: "BROKER_TEST_MISSING_TOKEN".to_string(),
+        ..AccountConfig::default()
+    };
+    assert_eq!(
+        new(&tradier_account).err().unwrap(),
+        "BROKER_TEST_MISSING_TOKEN not found"
+    );
+}
@@ -0,0 +1,431 @@
+// A local stand-in for Tradier's brokerage API, serving an account's positions, orders, balances and
+// profile from a simulated book. Market orders fill immediately at the price set for their symbol,
+// unless orders are held open, and failures can be queued for the requests that follow.
+use crate::tradier_stub::{accept_loop, addr, listen};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+#[derive(Debug, Clone)]
+pub enum Failure {
+    // The order comes back with this status and isn't booked
+    Reject(String),
+    // Tradier's error body, with a 400
+    Error(String),
+    Status(u16, String),
+    // Held this long before being served as usual
+    Delay(Duration),
+    // The connection is closed without a response
+    Hangup,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StubOrder {
+    pub id: i64,
+    pub symbol: String,
+    pub side: String,
+    pub quantity: i64,
+    pub price: f64,
+    pub status: String,
+}
+
+#[derive(Default)]
+struct Book {
+    prices: HashMap<String, f64>,
+    // Quantity and cost basis by symbol
+    positions: BTreeMap<String, (i64, f64)>,
+    orders: Vec<StubOrder>,
+    failures: VecDeque<Failure>,
+    requests: usize,
+    cash: f64,
+    // Orders placed are left open rather than filled
+    hold: bool,
+}
+
+pub struct BrokerageStub {
+    pub base_url: String,
+    book: Arc<Mutex<Book>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl BrokerageStub {
+    pub fn start(access_token: &str, account_id: &str) -> BrokerageStub {
+        // Funded well beyond what tests buy, unless set otherwise
+        let book = Arc::new(Mutex::new(Book {
+            cash: 100000.0,
+            ..Book::default()
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let listener = listen();
+        let base_url = format!("http://{}", addr(&listener));
+
+        let served = book.clone();
+        let (token, account) = (access_token.to_string(), account_id.to_string());
+        accept_loop(listener, stopped.clone(), move |stream, _| {
+            serve(stream, &served, &token, &account)
+        });
+
+        BrokerageStub {
+            base_url,
+            book,
+            stopped,
+        }
+    }
+
+    pub fn set_price(&self, symbol: &str, price: f64) {
+        let mut book = self.book.lock().unwrap();
+        book.prices.insert(symbol.to_string(), price);
+    }
+
+    pub fn add_position(&self, symbol: &str, quantity: i64, cost_basis: f64) {
+        let mut book = self.book.lock().unwrap();
+        book.positions
+            .insert(symbol.to_string(), (quantity, cost_basis));
+    }
+
+    // Applied to the next request, then the one after for a second call, and so on
+    pub fn fail_next(&self, failure: Failure) {
+        self.book.lock().unwrap().failures.push_back(failure);
+    }
+
+    pub fn set_cash(&self, cash: f64) {
+        self.book.lock().unwrap().cash = cash;
+    }
+
+    pub fn cash(&self) -> f64 {
+        self.book.lock().unwrap().cash
+    }
+
+    pub fn hold_orders(&self, hold: bool) {
+        self.book.lock().unwrap().hold = hold;
+    }
+
+    pub fn position(&self, symbol: &str) -> Option<(i64, f64)> {
+        self.book.lock().unwrap().positions.get(symbol).copied()
+    }
+
+    pub fn orders(&self) -> Vec<StubOrder> {
+        self.book.lock().unwrap().orders.clone()
+    }
+
+    pub fn requests(&self) -> usize {
+        self.book.lock().unwrap().requests
+    }
+}
+
+impl Drop for BrokerageStub {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: String,
+    body: String,
+}
+
+fn serve(mut stream: TcpStream, book: &Mutex<Book>, access_token: &str, account_id: &str) {
+    let request = match read_request(&stream) {
+        Some(request) => request,
+        None => return,
+    };
+    let failure = {
+        let mut book = book.lock().unwrap();
+        book.requests += 1;
+        book.failures.pop_front()
+    };
+
+    let (status, body) = match failure {
+        Some(Failure::Hangup) => return,
+        Some(Failure::Error(message)) => (400, error(&message)),
+        Some(Failure::Status(status, body)) => (status, body),
+        Some(Failure::Reject(status)) if is_order(&request) => (
+            200,
+            json!({ "order": { "id": 0, "status": status } }).to_string(),
+        ),
+        Some(Failure::Delay(delay)) => {
+            thread::sleep(delay);
+            respond(&request, book, access_token, account_id)
+        }
+        _ => respond(&request, book, access_token, account_id),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        if status == 200 { "OK" } else { "Error" },
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut authorization = String::new();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.to_lowercase().as_str() {
+                "authorization" => authorization = value.trim().to_string(),
+                "content-length" => length = value.trim().parse().unwrap_or(0),
+                _ => (),
+            }
+        }
+    }
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path,
+        authorization,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+fn is_order(request: &Request) -> bool {
+    request.method == "POST" && request.path.ends_with("/orders")
+}
+
+fn error(message: &str) -> String {
+    json!({ "errors": { "error": [message] } }).to_string()
+}
+
+fn respond(
+    request: &Request,
+    book: &Mutex<Book>,
+    access_token: &str,
+    account_id: &str,
+) -> (u16, String) {
+    if request.authorization != format!("Bearer {}", access_token) {
+        return (401, "Invalid Access Token".to_string());
+    }
+    if request.method == "GET" && request.path == "/v1/user/profile" {
+        return (200, profile(account_id).to_string());
+    }
+    let account = format!("/v1/accounts/{}/", account_id);
+    let resource = match request.path.strip_prefix(&account) {
+        Some(resource) => resource,
+        None => return (400, error("Invalid account")),
+    };
+
+    let mut book = book.lock().unwrap();
+    match (request.method.as_str(), resource) {
+        ("GET", "positions") => (200, positions(&book).to_string()),
+        ("GET", "orders") => (200, orders(&book).to_string()),
+        ("GET", "balances") => (200, balances(&book).to_string()),
+        ("GET", resource) if resource.starts_with("orders/") => match find(&book, resource) {
+            Some(order) => (200, json!({ "order": order_json(order) }).to_string()),
+            None => (400, error("Order not found")),
+        },
+        ("DELETE", resource) if resource.starts_with("orders/") => {
+            match find(&book, resource).map(|order| order.id) {
+                Some(id) => match cancel(&mut book, id) {
+                    Ok(()) => (
+                        200,
+                        json!({ "order": { "id": id, "status": "ok" } }).to_string(),
+                    ),
+                    Err(message) => (400, error(&message)),
+                },
+                None => (400, error("Order not found")),
+            }
+        }
+        ("POST", "orders") => match place(&mut book, &request.body) {
+            Ok(id) => (
+                200,
+                json!({ "order": { "id": id, "status": "ok", "partner_id": "stub" } }).to_string(),
+            ),
+            Err(message) => (400, error(&message)),
+        },
+        _ => (404, error("Not found")),
+    }
+}
+
+// As with Tradier, null for none and a lone object for one
+fn positions(book: &Book) -> Value {
+    let mut positions: Vec<Value> = book
+        .positions
+        .iter()
+        .filter(|(_, (quantity, _))| *quantity != 0)
+        .enumerate()
+        .map(|(i, (symbol, (quantity, cost_basis)))| {
+            json!({
+                "id": i + 1,
+                "symbol": symbol,
+                "quantity": *quantity as f64,
+                "cost_basis": cost_basis,
+                "date_acquired": "2024-06-03T13:45:27.304Z"
+            })
+        })
+        .collect();
+    match positions.len() {
+        0 => json!({ "positions": null }),
+        1 => json!({ "positions": { "position": positions.remove(0) } }),
+        _ => json!({ "positions": { "position": positions } }),
+    }
+}
+
+fn order_json(order: &StubOrder) -> Value {
+    let filled = if order.status == "filled" {
+        order.quantity
+    } else {
+        0
+    };
+    json!({
+        "id": order.id,
+        "type": "market",
+        "symbol": order.symbol,
+        "side": order.side,
+        "quantity": order.quantity as f64,
+        "status": order.status,
+        "avg_fill_price": if filled > 0 { order.price } else { 0.0 },
+        "exec_quantity": filled as f64
+    })
+}
+
+fn orders(book: &Book) -> Value {
+    let orders: Vec<Value> = book.orders.iter().map(order_json).collect();
+    if orders.is_empty() {
+        json!({ "orders": null })
+    } else {
+        json!({ "orders": { "order": orders } })
+    }
+}
+
+fn find<'a>(book: &'a Book, resource: &str) -> Option<&'a StubOrder> {
+    let id: i64 = resource.strip_prefix("orders/")?.parse().ok()?;
+    book.orders.iter().find(|order| order.id == id)
+}
+
+fn cancel(book: &mut Book, id: i64) -> Result<(), String> {
+    let order = book.orders.iter_mut().find(|order| order.id == id).unwrap();
+    if order.status != "open" {
+        return Err(format!("Order {} is {}", id, order.status));
+    }
+    order.status = "canceled".to_string();
+    Ok(())
+}
+
+// A margin account, valued at the prices set
+fn balances(book: &Book) -> Value {
+    let value: f64 = book
+        .positions
+        .iter()
+        .map(|(symbol, (quantity, _))| *quantity as f64 * book.prices.get(symbol).unwrap_or(&0.0))
+        .sum();
+    json!({
+        "balances": {
+            "account_type": "margin",
+            "total_cash": book.cash,
+            "total_equity": book.cash + value,
+            "margin": { "stock_buying_power": book.cash * 2.0 }
+        }
+    })
+}
+
+fn profile(account_id: &str) -> Value {
+    json!({
+        "profile": {
+            "id": "id-stub",
+            "name": "Stub",
+            "account": [
+                { "account_number": "VA999999", "type": "cash", "status": "closed" },
+                { "account_number": account_id, "type": "margin", "status": "active" }
+            ]
+        }
+    })
+}
+
+fn place(book: &mut Book, body: &str) -> Result<i64, String> {
+    let params: HashMap<&str, &str> = body
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect();
+    let param = |name: &str| {
+        params
+            .get(name)
+            .copied()
+            .ok_or(format!("Missing parameter {}", name))
+    };
+
+    if param("class")? != "equity" || param("type")? != "market" {
+        return Err("Only market orders for equities are supported".to_string());
+    }
+    let symbol = param("symbol")?.to_string();
+    let side = param("side")?.to_lowercase();
+    let quantity: i64 = param("quantity")?
+        .parse()
+        .map_err(|_| "Invalid quantity".to_string())?;
+    if quantity <= 0 {
+        return Err("Invalid quantity".to_string());
+    }
+    let price = *book
+        .prices
+        .get(&symbol)
+        .ok_or(format!("Unknown symbol {}", symbol))?;
+
+    let id = book.orders.len() as i64 + 1;
+    if book.hold {
+        book.orders.push(StubOrder {
+            id,
+            symbol,
+            side,
+            quantity,
+            price,
+            status: "open".to_string(),
+        });
+        return Ok(id);
+    }
+
+    let (held, cost_basis) = book.positions.get(&symbol).copied().unwrap_or_default();
+    let position = match side.as_str() {
+        "buy" => (held + quantity, cost_basis + price * quantity as f64),
+        "sell" if quantity <= held => (
+            held - quantity,
+            cost_basis * (held - quantity) as f64 / held as f64,
+        ),
+        "sell" => {
+            return Err(format!(
+                "Cannot sell {} {}, holding {}",
+                quantity, symbol, held
+            ))
+        }
+        _ => return Err(format!("Invalid side {}", side)),
+    };
+    book.positions.insert(symbol.clone(), position);
+    book.cash += if side == "buy" { -1.0 } else { 1.0 } * price * quantity as f64;
+
+    book.orders.push(StubOrder {
+        id,
+        symbol,
+        side,
+        quantity,
+        price,
+        status: "filled".to_string(),
+    });
+    Ok(id)
+}
@@ -0,0 +1,214 @@
+use super::*;
+use app_config::app_config::{CorporateActionsConfig, FetchConfig};
+use chrono::{Local, TimeZone};
+use std::fs;
+
+fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+}
+
+fn day(d: u32, close: f64) -> Day {
+    Day {
+        symbol: Some("SPY".to_string()),
+        date: date(d),
+        open: close,
+        high: close,
+        low: close,
+        close,
+        volume: 100,
+    }
+}
+
+fn action(d: u32, action: Action) -> CorporateAction {
+    CorporateAction {
+        symbol: "SPY".to_string(),
+        ex_date: date(d),
+        action,
+    }
+}
+
+#[test]
+fn test_split() {
+    // A 4-for-1 split on the 3rd
+    let days = vec![day(1, 400.0), day(2, 404.0), day(3, 101.0), day(4, 102.0)];
+    let adjustments = adjustments(&days, &[action(3, Action::Split(4.0))]);
+    let adjusted = adjust(&days, &adjustments);
+    assert_eq!(
+        adjusted.iter().map(|d| d.close).collect::<Vec<_>>(),
+        vec![100.0, 101.0, 101.0, 102.0]
+    );
+    assert_eq!(
+        adjusted.iter().map(|d| d.volume).collect::<Vec<_>>(),
+        vec![400, 400, 100, 100]
+    );
+}
+
+#[test]
+fn test_dividend() {
+    // A 2.0 dividend going ex on the 3rd, after a close of 100
+    let days = vec![day(1, 50.0), day(2, 100.0), day(3, 98.0)];
+    let adjustments = adjustments(&days, &[action(3, Action::Dividend(2.0))]);
+    assert_eq!(
+        adjustments,
+        vec![Adjustment {
+            ex_date: date(3),
+            price: 0.98,
+            volume: 1.0
+        }]
+    );
+    let adjusted = adjust(&days, &adjustments);
+    assert_eq!(
+        adjusted.iter().map(|d| d.close).collect::<Vec<_>>(),
+        vec![49.0, 98.0, 98.0]
+    );
+    assert!(adjusted.iter().all(|d| d.volume == 100));
+}
+
+#[test]
+fn test_combined_and_invalid() {
+    let days = vec![day(1, 200.0), day(2, 100.0), day(3, 100.0)];
+    let actions = vec![
+        action(2, Action::Split(2.0)),
+        action(3, Action::Dividend(1.0)),
+        action(3, Action::Split(0.0)),
+        action(3, Action::Dividend(500.0)),
+        // Nothing before it to adjust
+        action(1, Action::Dividend(1.0)),
+    ];
+    let adjustments = adjustments(&days, &actions);
+    assert_eq!(adjustments.len(), 2);
+    let adjusted = adjust(&days, &adjustments);
+    assert_eq!(
+        adjusted.iter().map(|d| d.close).collect::<Vec<_>>(),
+        vec![99.0, 99.0, 100.0]
+    );
+}
+
+#[test]
+fn test_adjust_bars() {
+    let days = vec![day(1, 400.0), day(2, 100.0)];
+    let adjustments = adjustments(&days, &[action(2, Action::Split(4.0))]);
+    let bar = |d: u32, hour: u32, px: f64| Bar {
+        symbol: "SPY".to_string(),
+        timestamp: Local.with_ymd_and_hms(2024, 1, d, hour, 0, 0).unwrap(),
+        open: px,
+        high: px,
+        low: px,
+        close: px,
+        volume: 10,
+    };
+    let bars = vec![bar(1, 10, 400.0), bar(1, 15, 404.0), bar(2, 10, 100.0)];
+    let adjusted = adjust_bars(&bars, &adjustments);
+    assert_eq!(
+        adjusted.iter().map(|b| b.close).collect::<Vec<_>>(),
+        vec![100.0, 101.0, 100.0]
+    );
+    assert_eq!(
+        adjusted.iter().map(|b| b.volume).collect::<Vec<_>>(),
+        vec![40, 40, 10]
+    );
+}
+
+#[test]
+fn test_adjust_quote() {
+    let days = vec![day(1, 400.0), day(2, 100.0)];
+    let adjustments = adjustments(&days, &[action(2, Action::Split(4.0))]);
+    let time = Local.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+    let quote = Quote {
+        symbol: "SPY".to_string(),
+        bid: 399.0,
+        ask: 401.0,
+        biddate: time,
+        askdate: time,
+        bidsz: 2,
+        asksz: 3,
+        bidexch: String::new(),
+        askexch: String::new(),
+    };
+
+    let adjusted = adjust_quote(&quote, date(1), &adjustments);
+    assert_eq!(
+        (adjusted.bid, adjusted.ask, adjusted.bidsz, adjusted.asksz),
+        (99.75, 100.25, 8, 12)
+    );
+    let unadjusted = adjust_quote(&quote, date(2), &adjustments);
+    assert_eq!((unadjusted.bid, unadjusted.asksz), (399.0, 3));
+}
+
+#[test]
+fn test_load_file() {
+    let path =
+        std::env::temp_dir().join(format!("corporate_actions_test_{}.csv", std::process::id()));
+    fs::write(
+        &path,
+        "symbol,ex_date,action,value\n\
+         SPY,2024-01-03,dividend,1.5\n\
+         AAPL,2024-01-02,split,4\n\
+         SPY,2024-01-02,split,2\n\
+         QQQ,2024-01-02,split,3\n",
+    )
+    .unwrap();
+    let config = CorporateActionsConfig {
+        source: CorporateActionSource::File,
+        path: path.to_string_lossy().to_string(),
+    };
+
+    let fetch = FetchConfig::default();
+    let actions = load(
+        &config,
+        &fetch,
+        "",
+        &["SPY".to_string(), "AAPL".to_string()],
+    )
+    .data;
+    assert_eq!(actions.len(), 2);
+    assert_eq!(
+        actions.get("SPY").unwrap(),
+        &vec![
+            action(2, Action::Split(2.0)),
+            action(3, Action::Dividend(1.5))
+        ]
+    );
+
+    fs::write(
+        &path,
+        "symbol,ex_date,action,value\nSPY,2024-01-03,merger,1\n",
+    )
+    .unwrap();
+    assert!(load(&config, &fetch, "", &["SPY".to_string()])
+        .errors
+        .contains_key("SPY"));
+    fs::remove_file(path).unwrap();
+
+    let none = CorporateActionsConfig::default();
+    let loaded = load(&none, &fetch, "", &["SPY".to_string()]);
+    assert!(loaded.data.is_empty() && loaded.errors.is_empty());
+}
+
+#[test]
+fn test_fetch_failures_per_symbol() {
+    let fetch = FetchConfig {
+        requests_per_second: 1000.0,
+        retries: 1,
+        retry_delay_ms: 1,
+        ..FetchConfig::default()
+    };
+    let symbols = ["SPY".to_string(), "BAD".to_string()];
+    let split = r#"{"stock_splits": {"ex_date": "2024-01-02", "split_from": 1, "split_to": 2}}"#;
+
+    // One symbol failing leaves the others' actions in place
+    let fetched = implementation::fetch_all(&fetch, &symbols, |url| {
+        if url.contains("BAD") {
+            Err("Not found".to_string())
+        } else if url.contains("corporate_actions") {
+            Ok(vec![serde_json::from_str(split).unwrap()])
+        } else {
+            Ok(vec![])
+        }
+    });
+    assert_eq!(
+        fetched.data,
+        HashMap::from([("SPY".to_string(), vec![action(2, Action::Split(2.0))])])
+    );
+    assert!(fetched.errors["BAD"].contains("Failed after 2 attempts"));
+}
@@ -0,0 +1,223 @@
+use super::*;
+use crate::market_data_replay;
+use app_config::app_config::{ReplayConfig, ReplaySpeed, SubscriptionConfig};
+use chrono::{Duration, FixedOffset, TimeZone, Utc};
+use std::sync::atomic::Ordering;
+
+// On June 3rd 2024 in New York, wherever the tests run
+fn at(hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+    FixedOffset::west_opt(4 * 3600)
+        .unwrap()
+        .with_ymd_and_hms(2024, 6, 3, hour, minute, second)
+        .unwrap()
+        .with_timezone(&Local)
+}
+
+fn quote(symbol: &str, bid: f64, ask: f64, time: DateTime<Local>) -> Quote {
+    Quote {
+        symbol: symbol.to_string(),
+        bid,
+        ask,
+        biddate: time,
+        askdate: time,
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    }
+}
+
+#[test]
+fn test_assess() {
+    let config = FeedHealthConfig::default();
+    let now = at(10, 0, 0);
+    let assess = |bid, ask, time| assess(&quote("SPY", bid, ask, time), now, &config);
+
+    assert_eq!(assess(100.0, 100.01, now), None);
+    assert_eq!(assess(0.0, 100.01, now), Some(QuoteProblem::Invalid));
+    assert_eq!(assess(100.02, 100.01, now), Some(QuoteProblem::Crossed));
+    assert_eq!(assess(100.0, 100.0, now), Some(QuoteProblem::Locked));
+    assert_eq!(
+        assess(100.0, 100.01, now - Duration::seconds(11)),
+        Some(QuoteProblem::Lagging(Duration::seconds(11)))
+    );
+
+    // Only the newer side need be recent
+    let mut one_sided = quote("SPY", 100.0, 100.01, now - Duration::minutes(5));
+    one_sided.askdate = now;
+    assert_eq!(super::assess(&one_sided, now, &config), None);
+
+    let lenient = FeedHealthConfig {
+        max_lag_secs: 0.0,
+        allow_locked: true,
+        ..config
+    };
+    assert_eq!(
+        super::assess(
+            &quote("SPY", 100.0, 100.0, now - Duration::days(1)),
+            now,
+            &lenient
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_alerts_once_per_change() {
+    let mut tracker = Tracker::new(FeedHealthConfig::default(), at(9, 0, 0));
+    let now = at(10, 0, 0);
+
+    assert_eq!(
+        tracker.observe(&quote("SPY", 100.0, 100.01, now), now),
+        None
+    );
+    assert_eq!(
+        tracker.observe(&quote("SPY", 100.02, 100.01, now), now),
+        Some(Alert::Unhealthy {
+            symbol: "SPY".to_string(),
+            problem: QuoteProblem::Crossed
+        })
+    );
+    assert_eq!(
+        tracker.observe(&quote("SPY", 100.03, 100.01, now), now),
+        None
+    );
+    assert!(matches!(
+        tracker.observe(&quote("SPY", 100.0, 100.0, now), now),
+        Some(Alert::Unhealthy {
+            problem: QuoteProblem::Locked,
+            ..
+        })
+    ));
+    assert_eq!(
+        tracker.observe(&quote("SPY", 100.0, 100.01, now), now),
+        Some(Alert::Recovered {
+            symbol: "SPY".to_string()
+        })
+    );
+    assert_eq!(tracker.last_update("SPY"), Some(now));
+}
+
+#[test]
+fn test_silence() {
+    let config = FeedHealthConfig {
+        max_silence_secs: 60.0,
+        ..FeedHealthConfig::default()
+    };
+    // Started before the open, so silence counts from 9:30
+    let mut tracker = Tracker::new(config, at(8, 0, 0));
+    tracker.add_symbol("SPY");
+    tracker.add_symbol("QQQ");
+
+    assert!(tracker.check(at(9, 0, 0)).is_empty());
+    assert!(tracker.check(at(9, 31, 0)).is_empty());
+    tracker.observe(&quote("SPY", 1.0, 1.01, at(9, 31, 0)), at(9, 31, 0));
+
+    assert_eq!(
+        tracker.check(at(9, 31, 30)),
+        vec![Alert::Silent {
+            symbol: "QQQ".to_string(),
+            since: at(8, 0, 0)
+        }]
+    );
+    // Raised once
+    assert!(tracker.check(at(9, 31, 45)).is_empty());
+    assert_eq!(
+        tracker.check(at(9, 32, 30)),
+        vec![Alert::Silent {
+            symbol: "SPY".to_string(),
+            since: at(9, 31, 0)
+        }]
+    );
+
+    let now = at(9, 33, 0);
+    assert_eq!(
+        tracker.observe(&quote("QQQ", 1.0, 1.01, now), now),
+        Some(Alert::Recovered {
+            symbol: "QQQ".to_string()
+        })
+    );
+    // Nothing is expected after the close
+    assert!(tracker.check(at(16, 30, 0)).is_empty());
+}
+
+#[test]
+fn test_silence_on_a_server_outside_new_york() {
+    let config = FeedHealthConfig {
+        max_silence_secs: 60.0,
+        ..FeedHealthConfig::default()
+    };
+    let utc = |hour, minute| {
+        Utc.with_ymd_and_hms(2024, 6, 3, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    };
+    let mut tracker = Tracker::new(config, utc(9, 0));
+    tracker.add_symbol("SPY");
+
+    // 9:30 on a UTC clock is before the open in New York
+    assert!(tracker.check(utc(9, 35)).is_empty());
+    assert!(tracker.check(utc(13, 30)).is_empty());
+    assert_eq!(
+        tracker.check(utc(13, 32)),
+        vec![Alert::Silent {
+            symbol: "SPY".to_string(),
+            since: utc(9, 0)
+        }]
+    );
+    // Still within the session at 16:00 UTC
+    let now = utc(16, 0);
+    tracker.observe(&quote("SPY", 1.0, 1.01, now), now);
+    assert_eq!(tracker.check(utc(16, 2)).len(), 1);
+}
+
+#[test]
+fn test_monitor_raises_alerts() {
+    let path = std::env::temp_dir().join(format!("feed_health_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = crate::market_data_recorder::RotatingWriter::new(path.clone(), 1024 * 1024);
+    let now = Local::now();
+    for q in [
+        quote("SPY", 100.0, 100.01, now),
+        quote("SPY", 100.02, 100.01, now),
+    ] {
+        let raw = serde_json::to_string(&domain::domain::MarketEvent::Quote(q)).unwrap();
+        writer
+            .write(&crate::market_data_recorder::Record::new(now, raw))
+            .unwrap();
+    }
+    writer.finish().unwrap();
+
+    let market_data = market_data_replay::new(
+        &ReplayConfig {
+            path: path.to_string_lossy().to_string(),
+            speed: ReplaySpeed::AsFastAsPossible,
+            ..ReplayConfig::default()
+        },
+        SubscriptionConfig::default(),
+    );
+    let monitor = new(&FeedHealthConfig::default(), market_data.clone());
+    let alerts = monitor.subscribe().unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = monitor
+        .init(shutdown.clone(), vec!["SPY".to_string()])
+        .unwrap();
+    market_data
+        .init(shutdown.clone(), vec!["SPY".to_string()])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(
+        alerts
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap(),
+        Alert::Unhealthy {
+            symbol: "SPY".to_string(),
+            problem: QuoteProblem::Crossed
+        }
+    );
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+}
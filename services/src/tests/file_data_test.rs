@@ -0,0 +1,208 @@
+use super::*;
+use app_config::app_config::ColumnMapping;
+use parquet::{
+    data_type::{DoubleType, Int32Type, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    record::Field,
+    schema::parser::parse_message_type,
+};
+use std::{fs, path::PathBuf, sync::Arc};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("file_data_test_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(dir: &Path, format: FileFormat) -> HistoricalDataConfig {
+    HistoricalDataConfig {
+        path: dir.to_string_lossy().to_string(),
+        format,
+        ..HistoricalDataConfig::default()
+    }
+}
+
+fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+}
+
+#[test]
+fn test_load_csv() {
+    let dir = temp_dir("csv");
+    fs::write(
+        dir.join("SPY.csv"),
+        "date,open,high,low,close,volume\n\
+         2024-01-03,3.0,3.5,2.5,3.25,300\n\
+         2024-01-01,1.0,1.5,0.5,1.25,100\n\
+         2024-01-02,2.0,2.5,1.5,2.25,200\n\
+         2024-01-04,4.0,4.5,3.5,4.25,400\n",
+    )
+    .unwrap();
+
+    let history = load(
+        &config(&dir, FileFormat::Csv),
+        vec!["SPY".to_string()],
+        date(2),
+        date(3),
+    );
+    let days = history.get("SPY").unwrap();
+    // Filtered to the range and sorted
+    assert_eq!(
+        days.iter().map(|d| d.date).collect::<Vec<_>>(),
+        vec![date(2), date(3)]
+    );
+    assert_eq!(days[0].symbol.as_deref(), Some("SPY"));
+    assert_eq!(days[0].open, 2.0);
+    assert_eq!(days[0].high, 2.5);
+    assert_eq!(days[0].low, 1.5);
+    assert_eq!(days[0].close, 2.25);
+    assert_eq!(days[0].volume, 200);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_csv_with_mapping() {
+    let dir = temp_dir("mapping");
+    fs::write(
+        dir.join("AAPL.csv"),
+        "Timestamp,Vol,Close,Open,High,Low,Extra\n\
+         01/02/2024 16:00,1000,190.5,188.0,191.0,187.5,x\n",
+    )
+    .unwrap();
+    let config = HistoricalDataConfig {
+        date_format: "%m/%d/%Y %H:%M".to_string(),
+        columns: ColumnMapping {
+            date: "Timestamp".to_string(),
+            open: "Open".to_string(),
+            high: "High".to_string(),
+            low: "Low".to_string(),
+            close: "Close".to_string(),
+            volume: "Vol".to_string(),
+        },
+        ..config(&dir, FileFormat::Csv)
+    };
+
+    let days = load_one(&config, "AAPL", date(1), date(31)).unwrap();
+    assert_eq!(days.len(), 1);
+    assert_eq!(days[0].date, date(2));
+    assert_eq!(days[0].open, 188.0);
+    assert_eq!(days[0].close, 190.5);
+    assert_eq!(days[0].volume, 1000);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_errors() {
+    let dir = temp_dir("errors");
+    let config = config(&dir, FileFormat::Csv);
+    assert!(load_one(&config, "MISSING", date(1), date(31)).is_err());
+
+    fs::write(
+        dir.join("SPY.csv"),
+        "date,open,high,low,close\n2024-01-02,1,1,1,1\n",
+    )
+    .unwrap();
+    let err = load_one(&config, "SPY", date(1), date(31)).unwrap_err();
+    assert!(err.contains("No column 'volume'"), "{}", err);
+
+    fs::write(
+        dir.join("SPY.csv"),
+        "date,open,high,low,close,volume\n2024/01/02,1,1,1,1,1\n",
+    )
+    .unwrap();
+    let err = load_one(&config, "SPY", date(1), date(31)).unwrap_err();
+    assert!(err.contains("Invalid date"), "{}", err);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_parquet() {
+    let dir = temp_dir("parquet");
+    let schema = parse_message_type(
+        "message bars {
+            REQUIRED INT32 date (DATE);
+            REQUIRED DOUBLE open;
+            REQUIRED DOUBLE high;
+            REQUIRED DOUBLE low;
+            REQUIRED DOUBLE close;
+            REQUIRED INT64 volume;
+        }",
+    )
+    .unwrap();
+    let file = fs::File::create(dir.join("QQQ.parquet")).unwrap();
+    let mut writer = SerializedFileWriter::new(
+        file,
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )
+    .unwrap();
+    let mut row_group = writer.next_row_group().unwrap();
+    let epoch_days =
+        |day: u32| (date(day) - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+
+    let mut column = row_group.next_column().unwrap().unwrap();
+    column
+        .typed::<Int32Type>()
+        .write_batch(&[epoch_days(2) as i32, epoch_days(3) as i32], None, None)
+        .unwrap();
+    column.close().unwrap();
+    for prices in [[1.0, 2.0], [1.5, 2.5], [0.5, 1.5], [1.25, 2.25]] {
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column
+            .typed::<DoubleType>()
+            .write_batch(&prices, None, None)
+            .unwrap();
+        column.close().unwrap();
+    }
+    let mut column = row_group.next_column().unwrap().unwrap();
+    column
+        .typed::<Int64Type>()
+        .write_batch(&[100, 200], None, None)
+        .unwrap();
+    column.close().unwrap();
+    row_group.close().unwrap();
+    writer.close().unwrap();
+
+    let days = load_one(&config(&dir, FileFormat::Parquet), "QQQ", date(1), date(31)).unwrap();
+    assert_eq!(
+        days.iter().map(|d| d.date).collect::<Vec<_>>(),
+        vec![date(2), date(3)]
+    );
+    assert_eq!(days[1].open, 2.0);
+    assert_eq!(days[1].high, 2.5);
+    assert_eq!(days[1].low, 1.5);
+    assert_eq!(days[1].close, 2.25);
+    assert_eq!(days[1].volume, 200);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_parse_date() {
+    assert_eq!(
+        implementation::parse_date("2024-01-02", "%Y-%m-%d").unwrap(),
+        date(2)
+    );
+    assert_eq!(
+        implementation::parse_date("20240102 09:30:00", "%Y%m%d %H:%M:%S").unwrap(),
+        date(2)
+    );
+    assert!(implementation::parse_date("2024-01-02", "%d/%m/%Y").is_err());
+}
+
+#[test]
+fn test_parquet_timestamps_in_date_format() {
+    // 2024-01-02 14:30:00 UTC
+    let millis = Field::TimestampMillis(1704205800000);
+    assert_eq!(
+        implementation::field_to_string(&millis, "%Y-%m-%d %H:%M:%S"),
+        "2024-01-02 14:30:00"
+    );
+    assert_eq!(
+        implementation::field_to_string(&Field::TimestampMicros(1704205800000000), "%Y%m%d %H:%M"),
+        "20240102 14:30"
+    );
+    assert_eq!(
+        implementation::field_to_string(&Field::Date(19724), "%d/%m/%Y"),
+        "02/01/2024"
+    );
+}
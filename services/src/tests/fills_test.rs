@@ -0,0 +1,38 @@
+use super::*;
+
+fn config() -> FillConfig {
+    FillConfig {
+        slippage_bps: 10.0,
+        commission: CommissionConfig {
+            per_order: 0.5,
+            per_share: 0.005,
+            minimum: 1.0,
+        },
+    }
+}
+
+#[test]
+fn test_slippage_is_against_the_order() {
+    let buy = fill(&config(), &Side::Buy, 100, 100.0);
+    let sell = fill(&config(), &Side::Sell, 100, 100.0);
+    assert!((buy.price - 100.1).abs() < 1e-9);
+    assert!((sell.price - 99.9).abs() < 1e-9);
+
+    let free = fill(&FillConfig::default(), &Side::Buy, 100, 100.0);
+    assert_eq!(
+        free,
+        Fill {
+            price: 100.0,
+            commission: 0.0
+        }
+    );
+}
+
+#[test]
+fn test_commission() {
+    let config = config().commission;
+    // 0.5 + 0.005 * 100 is below the minimum
+    assert_eq!(commission(&config, 100), 1.0);
+    assert!((commission(&config, 1000) - 5.5).abs() < 1e-9);
+    assert_eq!(commission(&CommissionConfig::default(), 1000), 0.0);
+}
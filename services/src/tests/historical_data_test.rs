@@ -1,12 +1,19 @@
 use super::*;
 use chrono::NaiveDate;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[test]
 fn test_fetch() {
     let access_token = std::env::var("TRADIER_ACCESS_TOKEN").unwrap();
     let end = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
-    let service = new(access_token, vec!["SPY".to_string()], 20, end);
-    match service.fetch(end).get("SPY") {
+    let service = new(
+        &HistoricalDataConfig::default(),
+        access_token,
+        vec!["SPY".to_string()],
+        20,
+        end,
+    );
+    match service.fetch_as_of(end).get("SPY") {
         Some(history) => {
             println!("History: {:?}", history);
             assert!(history.len() > 0);
@@ -16,3 +23,150 @@ fn test_fetch() {
         }
     }
 }
+
+#[test]
+fn test_fetch_bars() {
+    let access_token = std::env::var("TRADIER_ACCESS_TOKEN").unwrap();
+    let end = chrono::Local::now().date_naive();
+    let start = end - chrono::Duration::days(5);
+    let bars = fetch_bars(
+        &HistoricalDataConfig::default(),
+        &access_token,
+        vec!["SPY".to_string()],
+        Interval::FiveMinute,
+        start,
+        end,
+    );
+    assert!(bars.errors.is_empty(), "{:?}", bars.errors);
+    let history = bars.data.get("SPY").expect("No bars for SPY");
+    assert!(!history.is_empty());
+    assert!(history.iter().all(|bar| bar.symbol == "SPY"
+        && bar.low <= bar.open.min(bar.close)
+        && bar.high >= bar.open.max(bar.close)
+        && bar.volume >= 0));
+    // Within the range asked for, in order, and at least an interval apart
+    assert!(history.iter().all(|bar| {
+        let date = core::calendar::exchange_time(&bar.timestamp).date();
+        date >= start && date <= end
+    }));
+    assert!(history
+        .windows(2)
+        .all(|w| w[1].timestamp - w[0].timestamp >= Interval::FiveMinute.duration()));
+}
+
+struct SplitHistoricalDataService {}
+impl HistoricalDataService for SplitHistoricalDataService {
+    fn fetch_as_of(&self, as_of: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+        // A 2-for-1 split on the 3rd
+        let days = [(1, 200.0), (2, 202.0), (3, 101.0), (4, 102.0)]
+            .iter()
+            .map(|(d, close)| Day {
+                symbol: Some("SPY".to_string()),
+                date: NaiveDate::from_ymd_opt(2024, 1, *d).unwrap(),
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: 100,
+            })
+            .filter(|day| day.date < as_of)
+            .collect();
+        Arc::new(HashMap::from([("SPY".to_string(), days)]))
+    }
+
+    fn corporate_actions(&self) -> Arc<HashMap<String, Vec<CorporateAction>>> {
+        Arc::new(HashMap::from([(
+            "SPY".to_string(),
+            vec![CorporateAction {
+                symbol: "SPY".to_string(),
+                ex_date: NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+                action: domain::domain::Action::Split(2.0),
+            }],
+        )]))
+    }
+}
+
+#[test]
+fn test_with_prices() {
+    let closes = |service: Arc<dyn HistoricalDataService>, day: u32| -> Vec<f64> {
+        service
+            .fetch_as_of(NaiveDate::from_ymd_opt(2024, 1, day).unwrap())
+            .get("SPY")
+            .unwrap()
+            .iter()
+            .map(|d| d.close)
+            .collect()
+    };
+    let underlying = Arc::new(SplitHistoricalDataService {});
+    let raw = with_prices(underlying.clone(), PriceAdjustment::Raw);
+    let adjusted = with_prices(underlying, PriceAdjustment::Adjusted);
+
+    assert_eq!(closes(raw.clone(), 5), vec![200.0, 202.0, 101.0, 102.0]);
+    assert_eq!(
+        closes(adjusted.clone(), 5),
+        vec![100.0, 101.0, 101.0, 102.0]
+    );
+    // From the ex-date on, history is on the same footing as the day's quotes
+    assert_eq!(closes(adjusted.clone(), 3), vec![100.0, 101.0]);
+    // Before it, the split hasn't happened
+    assert_eq!(closes(adjusted, 2), vec![200.0]);
+    assert_eq!(closes(raw, 3), vec![200.0, 202.0]);
+}
+
+#[test]
+fn test_in_parallel() {
+    let symbols: Vec<String> = (0..20).map(|i| format!("S{}", i)).collect();
+    let running = AtomicUsize::new(0);
+    let most = AtomicUsize::new(0);
+    let fetched = implementation::in_parallel(3, &symbols, |symbol| {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        most.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        running.fetch_sub(1, Ordering::SeqCst);
+        match symbol {
+            "S3" | "S7" => Err(format!("No data for {}", symbol)),
+            _ => Ok(symbol.len()),
+        }
+    });
+
+    assert_eq!(fetched.data.len(), 18);
+    assert_eq!(fetched.errors.len(), 2);
+    assert_eq!(fetched.errors.get("S7").unwrap(), "No data for S7");
+    assert!(most.load(Ordering::SeqCst) <= 3);
+    assert!(implementation::in_parallel(3, &[], |_| Ok(()))
+        .data
+        .is_empty());
+}
+
+#[test]
+fn test_with_retries() {
+    let config = app_config::app_config::FetchConfig {
+        retries: 2,
+        retry_delay_ms: 1,
+        ..Default::default()
+    };
+    let limiter = TokenBucket::new(0.0, 1.0);
+    let attempts = std::cell::Cell::new(0);
+    let flaky = || {
+        attempts.set(attempts.get() + 1);
+        match attempts.get() {
+            n if n < 3 => Err("Timed out".to_string()),
+            n => Ok(n),
+        }
+    };
+    assert_eq!(
+        implementation::with_retries(&config, &limiter, flaky),
+        Ok(3)
+    );
+
+    attempts.set(0);
+    let failing = || -> Result<(), String> {
+        attempts.set(attempts.get() + 1);
+        Err("Timed out".to_string())
+    };
+    assert_eq!(
+        implementation::with_retries(&config, &limiter, failing),
+        Err("Failed after 3 attempts: Timed out".to_string())
+    );
+    assert_eq!(attempts.get(), 3);
+}
@@ -0,0 +1,213 @@
+use super::*;
+use std::cell::RefCell;
+
+fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+}
+
+fn config(name: &str) -> CacheConfig {
+    let path = std::env::temp_dir().join(format!(
+        "history_cache_test_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&path);
+    CacheConfig {
+        enabled: true,
+        path: path.to_string_lossy().to_string(),
+        offline: false,
+    }
+}
+
+fn day(date: NaiveDate) -> Day {
+    Day {
+        symbol: Some("SPY".to_string()),
+        date,
+        open: 1.0,
+        high: 2.0,
+        low: 0.5,
+        close: 1.5,
+        volume: 100,
+    }
+}
+
+// A fetch that serves a day for every date and records the ranges asked for
+fn recording(
+    calls: &RefCell<Vec<(NaiveDate, NaiveDate)>>,
+) -> impl Fn(NaiveDate, NaiveDate) -> Result<Vec<Day>, String> + '_ {
+    move |start, end| {
+        calls.borrow_mut().push((start, end));
+        Ok(start
+            .iter_days()
+            .take_while(|d| *d <= end)
+            .map(day)
+            .collect())
+    }
+}
+
+fn failing(_: NaiveDate, _: NaiveDate) -> Result<Vec<Day>, String> {
+    Err("Network down".to_string())
+}
+
+fn dates(days: &[Day]) -> Vec<NaiveDate> {
+    days.iter().map(|d| d.date).collect()
+}
+
+#[test]
+fn test_fetches_only_missing_ranges() {
+    let config = config("missing");
+    let cache = new(&config, date(31));
+    let calls = RefCell::new(vec![]);
+
+    let days = cache
+        .get("SPY", Interval::Daily, date(5), date(10), recording(&calls))
+        .unwrap();
+    assert_eq!(
+        dates(&days),
+        date(5).iter_days().take(6).collect::<Vec<_>>()
+    );
+
+    let days = cache
+        .get("SPY", Interval::Daily, date(6), date(8), recording(&calls))
+        .unwrap();
+    assert_eq!(dates(&days), vec![date(6), date(7), date(8)]);
+
+    let days = cache
+        .get("SPY", Interval::Daily, date(1), date(15), recording(&calls))
+        .unwrap();
+    assert_eq!(
+        dates(&days),
+        date(1).iter_days().take(15).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        *calls.borrow(),
+        vec![
+            (date(5), date(10)),
+            (date(1), date(4)),
+            (date(11), date(15))
+        ]
+    );
+    fs::remove_dir_all(config.path).unwrap();
+}
+
+#[test]
+fn test_today_is_not_cached() {
+    let config = config("today");
+    let calls = RefCell::new(vec![]);
+    let cache = new(&config, date(12));
+    cache
+        .get("SPY", Interval::Daily, date(1), date(12), recording(&calls))
+        .unwrap();
+
+    let cache = new(&config, date(13));
+    let days = cache
+        .get("SPY", Interval::Daily, date(1), date(13), recording(&calls))
+        .unwrap();
+    assert_eq!(days.len(), 13);
+    assert_eq!(
+        *calls.borrow(),
+        vec![(date(1), date(12)), (date(12), date(13))]
+    );
+    fs::remove_dir_all(config.path).unwrap();
+}
+
+#[test]
+fn test_falls_back_to_cache() {
+    let config = config("fallback");
+    let cache = new(&config, date(31));
+    assert!(cache
+        .get("SPY", Interval::Daily, date(1), date(10), failing)
+        .is_err());
+
+    let calls = RefCell::new(vec![]);
+    cache
+        .get("SPY", Interval::Daily, date(1), date(10), recording(&calls))
+        .unwrap();
+    let days = cache
+        .get("SPY", Interval::Daily, date(5), date(20), failing)
+        .unwrap();
+    assert_eq!(
+        dates(&days),
+        date(5).iter_days().take(6).collect::<Vec<_>>()
+    );
+
+    let offline = new(
+        &CacheConfig {
+            offline: true,
+            ..config.clone()
+        },
+        date(31),
+    );
+    let days = offline
+        .get("SPY", Interval::Daily, date(1), date(3), recording(&calls))
+        .unwrap();
+    assert_eq!(dates(&days), vec![date(1), date(2), date(3)]);
+    assert!(offline
+        .get("QQQ", Interval::Daily, date(1), date(3), recording(&calls))
+        .is_err());
+    assert_eq!(calls.borrow().len(), 1);
+    fs::remove_dir_all(config.path).unwrap();
+}
+
+#[test]
+fn test_invalidate() {
+    let config = config("invalidate");
+    let cache = new(&config, date(31));
+    let calls = RefCell::new(vec![]);
+    for interval in [Interval::Daily, Interval::FiveMinute] {
+        cache
+            .get("SPY", interval, date(1), date(2), recording(&calls))
+            .unwrap();
+        cache
+            .get("SPY", interval, date(1), date(2), recording(&calls))
+            .unwrap();
+    }
+    assert_eq!(calls.borrow().len(), 2);
+
+    cache.invalidate("SPY", Interval::Daily).unwrap();
+    cache
+        .get("SPY", Interval::Daily, date(1), date(2), recording(&calls))
+        .unwrap();
+    cache
+        .get(
+            "SPY",
+            Interval::FiveMinute,
+            date(1),
+            date(2),
+            recording(&calls),
+        )
+        .unwrap();
+    assert_eq!(calls.borrow().len(), 3);
+
+    cache.invalidate_all().unwrap();
+    cache
+        .get(
+            "SPY",
+            Interval::FiveMinute,
+            date(1),
+            date(2),
+            recording(&calls),
+        )
+        .unwrap();
+    assert_eq!(calls.borrow().len(), 4);
+    // Invalidating what isn't there is fine
+    cache.invalidate_all().unwrap();
+    cache.invalidate("QQQ", Interval::Daily).unwrap();
+}
+
+#[test]
+fn test_disabled() {
+    let config = CacheConfig {
+        enabled: false,
+        ..config("disabled")
+    };
+    let cache = new(&config, date(31));
+    let calls = RefCell::new(vec![]);
+    for _ in 0..2 {
+        cache
+            .get("SPY", Interval::Daily, date(1), date(2), recording(&calls))
+            .unwrap();
+    }
+    assert_eq!(calls.borrow().len(), 2);
+    assert!(!Path::new(&config.path).exists());
+}
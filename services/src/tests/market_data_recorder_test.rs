@@ -0,0 +1,108 @@
+use super::*;
+use chrono::TimeZone;
+
+fn dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "market_data_recorder_test_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+fn quote_message(symbol: &str, bid: f64) -> String {
+    format!(
+        "{{\"type\":\"quote\",\"symbol\":\"{}\",\"bid\":{},\"ask\":{},\"biddate\":\"1717421400000\",\"askdate\":\"1717421400000\"}}",
+        symbol,
+        bid,
+        bid + 0.01
+    )
+}
+
+fn at(day: u32, second: u32) -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(2024, 6, day, 10, 0, second)
+        .earliest()
+        .unwrap()
+}
+
+#[test]
+fn test_record_parses_quotes() {
+    let record = Record::new(at(3, 0), quote_message("SPY", 100.0));
+    assert_eq!(record.quote.unwrap().bid, 100.0);
+    let record = Record::new(at(3, 0), "{\"type\":\"heartbeat\"}".to_string());
+    assert!(record.quote.is_none());
+}
+
+#[test]
+fn test_round_trip() {
+    let path = dir("round_trip");
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    let records: Vec<Record> = (0..5)
+        .map(|i| Record::new(at(3, i), quote_message("SPY", 100.0 + i as f64)))
+        .chain([Record::new(
+            at(3, 5),
+            "{\"type\":\"heartbeat\"}".to_string(),
+        )])
+        .collect();
+    records.iter().for_each(|r| writer.write(r).unwrap());
+    writer.finish().unwrap();
+
+    let read = read(&path.to_string_lossy()).unwrap();
+    assert_eq!(read.len(), 6);
+    assert_eq!(read[2].raw, records[2].raw);
+    assert_eq!(read[2].received, records[2].received);
+    let quote = read[2].quote.as_ref().unwrap();
+    assert_eq!(quote.bid, 102.0);
+    assert_eq!(quote.biddate, records[2].quote.as_ref().unwrap().biddate);
+    assert!(read[5].quote.is_none());
+}
+
+#[test]
+fn test_rotates_by_size_and_date() {
+    let path = dir("rotation");
+    // Small enough that every record starts a new file
+    let mut writer = RotatingWriter::new(path.clone(), 10);
+    writer
+        .write(&Record::new(at(3, 0), quote_message("SPY", 100.0)))
+        .unwrap();
+    writer
+        .write(&Record::new(at(3, 1), quote_message("SPY", 101.0)))
+        .unwrap();
+    writer.max_bytes = 1024 * 1024;
+    writer
+        .write(&Record::new(at(3, 2), quote_message("SPY", 102.0)))
+        .unwrap();
+    writer
+        .write(&Record::new(at(4, 0), quote_message("SPY", 103.0)))
+        .unwrap();
+    writer.finish().unwrap();
+
+    assert_eq!(fs::read_dir(&path).unwrap().count(), 3);
+    let bids: Vec<f64> = read(&path.to_string_lossy())
+        .unwrap()
+        .iter()
+        .map(|r| r.quote.as_ref().unwrap().bid)
+        .collect();
+    assert_eq!(bids, vec![100.0, 101.0, 102.0, 103.0]);
+}
+
+#[test]
+fn test_reads_truncated_file() {
+    let path = dir("truncated");
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    (0..1000).for_each(|i| {
+        writer
+            .write(&Record::new(at(3, 0), quote_message("SPY", i as f64)))
+            .unwrap()
+    });
+    writer.finish().unwrap();
+
+    let file = fs::read_dir(&path).unwrap().next().unwrap().unwrap().path();
+    let bytes = fs::read(&file).unwrap();
+    fs::write(&file, &bytes[..bytes.len() / 2]).unwrap();
+
+    let records = read(&file.to_string_lossy()).unwrap();
+    assert!(!records.is_empty() && records.len() < 1000);
+}
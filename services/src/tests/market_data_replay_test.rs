@@ -0,0 +1,218 @@
+use super::*;
+use crate::market_data_recorder::{MarketDataRecorder, RotatingWriter};
+use app_config::app_config::{Backpressure, RecordingConfig};
+use chrono::{Local, TimeZone};
+use std::time::{Duration, Instant};
+
+fn quote_message(symbol: &str, bid: f64) -> String {
+    format!(
+        "{{\"type\":\"quote\",\"symbol\":\"{}\",\"bid\":{},\"ask\":{},\"biddate\":\"1717421400000\",\"askdate\":\"1717421400000\"}}",
+        symbol,
+        bid,
+        bid + 0.01
+    )
+}
+
+// A recording of quotes a second apart, alternating between SPY and QQQ
+fn recording(name: &str, count: u32) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "market_data_replay_test_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    let start = Local
+        .with_ymd_and_hms(2024, 6, 3, 10, 0, 0)
+        .earliest()
+        .unwrap();
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    for i in 0..count {
+        let symbol = if i % 2 == 0 { "SPY" } else { "QQQ" };
+        writer
+            .write(&Record::new(
+                start + chrono::Duration::seconds(i as i64),
+                quote_message(symbol, 100.0 + i as f64),
+            ))
+            .unwrap();
+    }
+    writer.finish().unwrap();
+    path.to_string_lossy().to_string()
+}
+
+fn config(path: String, speed: ReplaySpeed, factor: f64) -> ReplayConfig {
+    ReplayConfig {
+        path,
+        speed,
+        factor,
+    }
+}
+
+#[test]
+fn test_replays_quotes_for_symbols() {
+    let replay = new(
+        &config(recording("symbols", 6), ReplaySpeed::AsFastAsPossible, 1.0),
+        SubscriptionConfig::default(),
+    );
+    let quotes = replay.subscribe().unwrap();
+    let raw = replay.subscribe_raw().unwrap();
+    replay
+        .init(Arc::new(AtomicBool::new(false)), vec!["SPY".to_string()])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    let bids: Vec<f64> = quotes.try_iter().map(|q| q.bid).collect();
+    assert_eq!(bids, vec![100.0, 102.0, 104.0]);
+    assert_eq!(raw.try_iter().count(), 6);
+}
+
+#[test]
+fn test_queues_quotes_as_configured() {
+    let replay = new(
+        &config(
+            recording("configured", 6),
+            ReplaySpeed::AsFastAsPossible,
+            1.0,
+        ),
+        SubscriptionConfig {
+            capacity: Some(2),
+            policy: Backpressure::DropOldest,
+        },
+    );
+    let quotes = replay.subscribe().unwrap();
+    replay
+        .init(Arc::new(AtomicBool::new(false)), vec!["SPY".to_string()])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    // Only room for the latest two, rather than waiting for them to be read
+    let bids: Vec<f64> = quotes.try_iter().map(|q| q.bid).collect();
+    assert_eq!(bids, vec![102.0, 104.0]);
+}
+
+#[test]
+fn test_accelerated_pacing() {
+    // Three seconds of recording at 20x takes about 150ms
+    let replay = new(
+        &config(recording("accelerated", 4), ReplaySpeed::Accelerated, 20.0),
+        SubscriptionConfig::default(),
+    );
+    let quotes = replay.subscribe().unwrap();
+    let started = Instant::now();
+    replay
+        .init(Arc::new(AtomicBool::new(false)), vec![])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    assert_eq!(quotes.try_iter().count(), 4);
+}
+
+#[test]
+fn test_shutdown_stops_replay() {
+    let replay = new(
+        &config(recording("shutdown", 100), ReplaySpeed::RealTime, 1.0),
+        SubscriptionConfig::default(),
+    );
+    let quotes = replay.subscribe().unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = replay.init(shutdown.clone(), vec![]).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    handle.join().unwrap();
+
+    assert_eq!(quotes.try_iter().count(), 1);
+}
+
+#[test]
+fn test_records_replayed_feed() {
+    let source = new(
+        &config(recording("source", 4), ReplaySpeed::AsFastAsPossible, 1.0),
+        SubscriptionConfig::default(),
+    );
+    let path = std::env::temp_dir().join(format!(
+        "market_data_replay_test_recorded_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    let recorder = market_data_recorder::new(
+        &RecordingConfig {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+            max_file_mb: 1,
+        },
+        source.clone(),
+    );
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let recording = recorder.start(shutdown.clone()).unwrap();
+    source
+        .init(shutdown.clone(), vec![])
+        .unwrap()
+        .join()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    recording.join().unwrap();
+
+    let records = market_data_recorder::read(&path.to_string_lossy()).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[3].quote.as_ref().unwrap().symbol, "QQQ");
+}
+
+#[test]
+fn test_subscribes_to_event_types() {
+    let path = std::env::temp_dir().join(format!(
+        "market_data_replay_test_events_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    let messages = [
+        quote_message("SPY", 100.0),
+        r#"{"type":"trade","symbol":"SPY","exch":"J","price":"100.01","size":"200","cvol":"1000","date":"1717421400000","last":"100.01"}"#.to_string(),
+        r#"{"type":"summary","symbol":"SPY","open":"99.5","high":"100.5","low":"99.0","prevClose":"99.2"}"#.to_string(),
+        r#"{"type":"trade","symbol":"QQQ","exch":"J","price":"400.0","size":"100","cvol":"1000","date":"1717421400000","last":"400.0"}"#.to_string(),
+    ];
+    for message in messages {
+        writer.write(&Record::new(Local::now(), message)).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let replay = new(
+        &config(
+            path.to_string_lossy().to_string(),
+            ReplaySpeed::AsFastAsPossible,
+            1.0,
+        ),
+        SubscriptionConfig::default(),
+    );
+    let quotes = replay.subscribe().unwrap();
+    let trades = replay.subscribe_events(&[EventType::Trade]).unwrap();
+    let all = replay.subscribe_events(&EventType::ALL).unwrap();
+    replay
+        .init(Arc::new(AtomicBool::new(false)), vec!["SPY".to_string()])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(quotes.try_iter().count(), 1);
+    let trades: Vec<MarketEvent> = trades.try_iter().collect();
+    assert_eq!(trades.len(), 1);
+    match &trades[0] {
+        MarketEvent::Trade(trade) => assert_eq!((trade.price, trade.size), (100.01, 200)),
+        event => panic!("Expected a trade: {:?}", event),
+    }
+    let types: Vec<EventType> = all.try_iter().map(|e| e.event_type()).collect();
+    assert_eq!(
+        types,
+        vec![EventType::Quote, EventType::Trade, EventType::Summary]
+    );
+
+    replay.unsubscribe_events(&all).unwrap();
+    assert!(replay.unsubscribe_events(&all).is_err());
+}
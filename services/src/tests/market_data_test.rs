@@ -1,9 +1,15 @@
 use super::*;
+use crate::tradier_stub::{Step, TradierStub};
+use app_config::app_config::{Backpressure, SubscriptionConfig};
 
 #[test]
 fn test_subscribe() {
     let access_token = std::env::var("TRADIER_ACCESS_TOKEN").unwrap();
-    let service = new(access_token);
+    let config = MarketDataConfig {
+        events: vec![EventType::Quote],
+        ..MarketDataConfig::default()
+    };
+    let service = new(access_token, &config);
     let symbols = vec!["SPY".to_string()];
     let shutdown = Arc::new(AtomicBool::new(false));
     let _ = service.init(shutdown.clone(), symbols).unwrap();
@@ -27,3 +33,382 @@ fn test_subscribe() {
         Err(e) => panic!("Failed to subscribe to MarketDataService: {}", e),
     }
 }
+
+const FIXTURE: &str = include_str!("./fixtures/tradier_stream.txt");
+
+#[test]
+fn test_classify_fixture() {
+    let incoming: Vec<Incoming> = FIXTURE.lines().flat_map(classify_text).collect();
+    let kinds: Vec<&str> = incoming
+        .iter()
+        .map(|i| match i {
+            Incoming::Event(_) => "event",
+            Incoming::Heartbeat => "heartbeat",
+            Incoming::Unknown(_) => "unknown",
+            Incoming::Malformed(_) => "malformed",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "event",
+            "event",
+            "event",
+            "event",
+            "event",
+            "heartbeat",
+            "event",
+            // A quote with a field we can't read
+            "malformed",
+            "unknown",
+            "unknown",
+            "malformed",
+            "malformed"
+        ]
+    );
+}
+
+#[test]
+fn test_handles_fixture_without_stopping() {
+    let subscribers = implementation::Subscribers::default();
+    let counts = Mutex::new(MessageCounts::default());
+    let quotes = subscribers.subscribe().unwrap();
+    let raw = subscribers.subscribe_raw().unwrap();
+
+    // Sent as one message, as with linebreak set
+    let open =
+        implementation::handle_message(Message::Text(FIXTURE.to_string()), &subscribers, &counts);
+    assert!(open);
+    assert_eq!(
+        *counts.lock().unwrap(),
+        MessageCounts {
+            events: 6,
+            heartbeats: 1,
+            unknown: 2,
+            malformed: 3,
+        }
+    );
+    assert_eq!(
+        quotes.try_iter().map(|q| q.symbol).collect::<Vec<_>>(),
+        vec!["SPY", "AAPL"]
+    );
+    assert_eq!(raw.try_iter().count(), 1);
+}
+
+#[test]
+fn test_control_frames() {
+    let subscribers = implementation::Subscribers::default();
+    let counts = Mutex::new(MessageCounts::default());
+    let handle = |msg: Message| implementation::handle_message(msg, &subscribers, &counts);
+
+    assert!(handle(Message::Ping(vec![1, 2, 3])));
+    assert!(handle(Message::Pong(vec![])));
+    assert!(handle(Message::Binary(vec![0xff, 0xfe])));
+    assert!(handle(Message::Binary(
+        b"{\"type\":\"heartbeat\"}".to_vec()
+    )));
+    assert!(!handle(Message::Close(None)));
+    assert_eq!(
+        *counts.lock().unwrap(),
+        MessageCounts {
+            events: 0,
+            heartbeats: 1,
+            unknown: 0,
+            malformed: 1,
+        }
+    );
+}
+
+fn stub_quote(symbol: &str, bid: f64) -> Step {
+    Step::Send(format!(
+        "{{\"type\":\"quote\",\"symbol\":\"{}\",\"bid\":{},\"biddate\":\"1557757189000\",\"ask\":{},\"askdate\":\"1557757190000\"}}",
+        symbol,
+        bid,
+        bid + 0.01
+    ))
+}
+
+fn stub_service(stub: &TradierStub) -> Arc<impl MarketDataService> {
+    let config = MarketDataConfig {
+        events: vec![EventType::Quote, EventType::Trade],
+        session_url: stub.session_url.clone(),
+        stream_url: stub.stream_url.clone(),
+        ..MarketDataConfig::default()
+    };
+    new("stub-token".to_string(), &config)
+}
+
+fn bids(rx: &Receiver<Quote>, n: usize) -> Vec<f64> {
+    (0..n)
+        .map(|_| {
+            rx.recv_timeout(std::time::Duration::from_secs(10))
+                .expect("Timed out waiting for quote")
+                .bid
+        })
+        .collect()
+}
+
+#[test]
+fn test_stub_subscribes_and_delivers_quotes() {
+    let stub = TradierStub::start(vec![vec![
+        stub_quote("SPY", 100.0),
+        stub_quote("QQQ", 200.0),
+    ]]);
+    let service = stub_service(&stub);
+    let quotes = service.subscribe().unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string(), "QQQ".to_string()])
+        .unwrap();
+
+    assert_eq!(bids(&quotes, 2), vec![100.0, 200.0]);
+    assert_eq!(
+        stub.subscriptions(),
+        vec![serde_json::json!({
+            "symbols": ["SPY", "QQQ"],
+            "sessionid": "stub-session-1",
+            "filter": ["quote", "trade"],
+            "linebreak": true
+        })]
+    );
+
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    drop(stub);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_stub_reconnects_after_disconnect() {
+    let stub = TradierStub::start(vec![
+        vec![stub_quote("SPY", 1.0), Step::Drop],
+        vec![stub_quote("SPY", 2.0), Step::Close],
+        vec![stub_quote("SPY", 3.0)],
+    ]);
+    let service = stub_service(&stub);
+    let quotes = service.subscribe().unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string()])
+        .unwrap();
+
+    // Each reconnection starts a new session
+    assert_eq!(bids(&quotes, 3), vec![1.0, 2.0, 3.0]);
+    assert_eq!(stub.sessions(), 3);
+    assert_eq!(stub.connections(), 3);
+    assert_eq!(stub.subscriptions()[2]["sessionid"], "stub-session-3");
+
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    drop(stub);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_stub_shutdown_stops_reconnecting() {
+    let stub = TradierStub::start(vec![vec![
+        Step::Pause(std::time::Duration::from_millis(200)),
+        Step::Send("{\"type\":\"heartbeat\"}".to_string()),
+        Step::Drop,
+    ]]);
+    let service = stub_service(&stub);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string()])
+        .unwrap();
+    while stub.connections() == 0 {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    // Seen by the latest once the next message arrives, and the service doesn't connect again
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    handle.join().unwrap();
+    assert_eq!(stub.sessions(), 1);
+    assert_eq!(stub.connections(), 1);
+}
+
+fn wait_for(condition: impl Fn() -> bool) {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !condition() {
+        assert!(std::time::Instant::now() < deadline, "Timed out waiting");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_change_symbols() {
+    let symbols = Mutex::new(vec!["SPY".to_string()]);
+    let changed = |added: &[&str], removed: &[&str]| {
+        let added: Vec<String> = added.iter().map(|s| s.to_string()).collect();
+        let removed: Vec<String> = removed.iter().map(|s| s.to_string()).collect();
+        implementation::change_symbols(&symbols, &added, &removed).unwrap()
+    };
+
+    assert!(changed(&["QQQ", "SPY"], &[]));
+    assert!(!changed(&["QQQ"], &["IWM"]));
+    assert!(changed(&["IWM"], &["SPY"]));
+    assert_eq!(*symbols.lock().unwrap(), vec!["QQQ", "IWM"]);
+}
+
+#[test]
+fn test_stub_changes_symbols_without_reconnecting() {
+    let stub = TradierStub::start(vec![]);
+    let service = stub_service(&stub);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string()])
+        .unwrap();
+    wait_for(|| stub.subscriptions().len() == 1);
+
+    service.add_symbols(&["QQQ".to_string()]).unwrap();
+    wait_for(|| stub.subscriptions().len() == 2);
+    service.remove_symbols(&["SPY".to_string()]).unwrap();
+    wait_for(|| stub.subscriptions().len() == 3);
+
+    let subscriptions = stub.subscriptions();
+    assert_eq!(
+        subscriptions[1]["symbols"],
+        serde_json::json!(["SPY", "QQQ"])
+    );
+    assert_eq!(subscriptions[2]["symbols"], serde_json::json!(["QQQ"]));
+    assert_eq!(subscriptions[2]["sessionid"], "stub-session-1");
+    assert_eq!(stub.connections(), 1);
+
+    // Doesn't wait for a message to see shutdown
+    shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+    handle.join().unwrap();
+    assert_eq!(stub.connections(), 1);
+}
+
+fn publish_quote(subscribers: &implementation::Subscribers, symbol: &str, bid: f64) {
+    let quote = Quote {
+        symbol: symbol.to_string(),
+        bid,
+        ask: bid,
+        biddate: chrono::Local::now(),
+        askdate: chrono::Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    };
+    subscribers.publish(&MarketEvent::Quote(quote));
+}
+
+fn bounded(capacity: usize, policy: Backpressure) -> SubscriptionConfig {
+    SubscriptionConfig {
+        capacity: Some(capacity),
+        policy,
+    }
+}
+
+#[test]
+fn test_drop_oldest() {
+    let subscribers = implementation::Subscribers::default();
+    let rx = subscribers
+        .subscribe_with(bounded(2, Backpressure::DropOldest))
+        .unwrap();
+    (1..=4).for_each(|i| publish_quote(&subscribers, "SPY", i as f64));
+
+    let metrics = subscribers.metrics();
+    assert_eq!((metrics[0].depth, metrics[0].dropped), (2, 2));
+    assert_eq!(
+        rx.try_iter().map(|q| q.bid).collect::<Vec<_>>(),
+        vec![3.0, 4.0]
+    );
+}
+
+#[test]
+fn test_conflate() {
+    let subscribers = implementation::Subscribers::default();
+    let rx = subscribers
+        .subscribe_with(bounded(2, Backpressure::Conflate))
+        .unwrap();
+    publish_quote(&subscribers, "SPY", 1.0);
+    publish_quote(&subscribers, "QQQ", 1.0);
+    publish_quote(&subscribers, "SPY", 2.0);
+    publish_quote(&subscribers, "QQQ", 2.0);
+    publish_quote(&subscribers, "SPY", 3.0);
+
+    assert_eq!(subscribers.metrics()[0].dropped, 3);
+    assert_eq!(
+        rx.try_iter().map(|q| (q.symbol, q.bid)).collect::<Vec<_>>(),
+        vec![("SPY".to_string(), 3.0), ("QQQ".to_string(), 2.0)]
+    );
+
+    // More symbols than room: the oldest goes
+    publish_quote(&subscribers, "SPY", 4.0);
+    publish_quote(&subscribers, "QQQ", 4.0);
+    publish_quote(&subscribers, "IWM", 4.0);
+    assert_eq!(
+        rx.try_iter().map(|q| q.symbol).collect::<Vec<_>>(),
+        vec!["QQQ", "IWM"]
+    );
+}
+
+#[test]
+fn test_block_waits_for_subscriber() {
+    let subscribers = implementation::Subscribers {
+        quote_config: bounded(1, Backpressure::Block),
+        ..Default::default()
+    };
+    let rx = subscribers.subscribe().unwrap();
+    let publisher = {
+        let subscribers = subscribers.clone();
+        std::thread::spawn(move || {
+            publish_quote(&subscribers, "SPY", 1.0);
+            publish_quote(&subscribers, "SPY", 2.0);
+        })
+    };
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(!publisher.is_finished());
+    assert_eq!(subscribers.metrics()[0].depth, 1);
+    assert_eq!(bids(&rx, 2), vec![1.0, 2.0]);
+    publisher.join().unwrap();
+    assert_eq!(subscribers.metrics()[0].dropped, 0);
+}
+
+#[test]
+fn test_block_gives_up_on_shutdown() {
+    let subscribers = implementation::Subscribers {
+        quote_config: bounded(1, Backpressure::Block),
+        ..Default::default()
+    };
+    // A subscriber that has gone without unsubscribing
+    drop(subscribers.subscribe().unwrap());
+    let (done, finished) = crossbeam_channel::unbounded();
+    {
+        let subscribers = subscribers.clone();
+        std::thread::spawn(move || {
+            publish_quote(&subscribers, "SPY", 1.0);
+            publish_quote(&subscribers, "SPY", 2.0);
+            done.send(()).unwrap();
+        });
+    }
+
+    // Waiting for room while the feed runs, but not once it shuts down
+    let wait = std::time::Duration::from_millis(300);
+    assert!(finished.recv_timeout(wait).is_err());
+    subscribers
+        .shutdown
+        .store(true, std::sync::atomic::Ordering::Relaxed);
+    finished.recv_timeout(wait * 10).unwrap();
+    assert_eq!(subscribers.metrics()[0].dropped, 1);
+}
+
+#[test]
+fn test_unsubscribe_quotes() {
+    let subscribers = implementation::Subscribers::default();
+    let first = subscribers.subscribe().unwrap();
+    let second = subscribers.subscribe().unwrap();
+    assert!(subscribers
+        .subscribe_with(bounded(0, Backpressure::Block))
+        .is_err());
+
+    // Any handle to the channel will do
+    subscribers.unsubscribe(&second.clone()).unwrap();
+    assert!(subscribers.unsubscribe(&second).is_err());
+    publish_quote(&subscribers, "SPY", 1.0);
+    assert_eq!(first.len(), 1);
+    assert_eq!(subscribers.metrics().len(), 1);
+}
@@ -0,0 +1,253 @@
+use super::*;
+use crate::market_data;
+use crate::tradier_stub::{Step, TradierStub};
+use std::{sync::atomic::Ordering, time::Duration};
+
+// A vendor that batches quotes in arrays, with nested prices as strings and RFC 3339 times
+fn mapping() -> QuoteMapping {
+    QuoteMapping {
+        type_field: Some("T".to_string()),
+        quote_type: "q".to_string(),
+        symbol: "S".to_string(),
+        bid: "p.bid".to_string(),
+        ask: "p.ask".to_string(),
+        bid_size: Some("bs".to_string()),
+        ask_size: Some("as".to_string()),
+        bid_exchange: Some("bx".to_string()),
+        ask_exchange: None,
+        bid_time: Some("t".to_string()),
+        ask_time: Some("t".to_string()),
+        time_format: TimeFormat::Rfc3339,
+    }
+}
+
+fn vendor_quote(symbol: &str, bid: f64) -> String {
+    format!(
+        "{{\"T\":\"q\",\"S\":\"{}\",\"p\":{{\"bid\":\"{}\",\"ask\":{}}},\"bs\":3,\"as\":4,\"bx\":\"Q\",\"t\":\"2019-05-13T14:19:49.123456Z\"}}",
+        symbol,
+        bid,
+        bid + 0.01
+    )
+}
+
+fn config(stub: &TradierStub) -> MarketDataConfig {
+    MarketDataConfig {
+        websocket: Some(WebsocketConfig {
+            url: stub.stream_url.clone(),
+            login: Some("{\"action\":\"auth\",\"key\":\"{token}\"}".to_string()),
+            subscribe: "{\"action\":\"subscribe\",\"quotes\":{symbols}}".to_string(),
+            unsubscribe: Some("{\"action\":\"unsubscribe\",\"quotes\":{symbols}}".to_string()),
+            mapping: mapping(),
+        }),
+        ..MarketDataConfig::default()
+    }
+}
+
+fn recv(rx: &Receiver<Quote>) -> Quote {
+    rx.recv_timeout(Duration::from_secs(10))
+        .expect("Timed out waiting for quote")
+}
+
+// The stub records messages sent after the first only once its script has run
+fn wait_for(stub: &TradierStub, count: usize) -> Vec<Value> {
+    for _ in 0..500 {
+        if stub.subscriptions().len() >= count {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    stub.subscriptions()
+}
+
+#[test]
+fn test_map_text() {
+    let received = Local::now();
+    let batch = format!(
+        "[{}, {}, {{\"T\":\"success\",\"msg\":\"authenticated\"}}]",
+        vendor_quote("SPY", 100.0),
+        vendor_quote("QQQ", 200.0)
+    );
+    let incoming = map_text(&batch, &mapping(), received);
+    assert_eq!(incoming.len(), 3);
+    match &incoming[0] {
+        Incoming::Event(MarketEvent::Quote(quote)) => {
+            assert_eq!(quote.symbol, "SPY");
+            assert_eq!((quote.bid, quote.ask), (100.0, 100.01));
+            assert_eq!((quote.bidsz, quote.asksz), (3, 4));
+            assert_eq!((quote.bidexch.as_str(), quote.askexch.as_str()), ("Q", ""));
+            assert_eq!(quote.biddate.timestamp_millis(), 1557757189123);
+        }
+        other => panic!("Expected a quote, got {:?}", other),
+    }
+    assert!(matches!(&incoming[1], Incoming::Event(MarketEvent::Quote(q)) if q.symbol == "QQQ"));
+    assert!(matches!(incoming[2], Incoming::Unknown(_)));
+
+    // A quote missing its price is malformed, as is anything that isn't JSON
+    let incoming = map_text(
+        "{\"T\":\"q\",\"S\":\"SPY\",\"p\":{\"ask\":1.0}}",
+        &mapping(),
+        received,
+    );
+    assert!(matches!(incoming[0], Incoming::Malformed(_)));
+    assert!(matches!(
+        map_text("nope", &mapping(), received)[0],
+        Incoming::Malformed(_)
+    ));
+
+    // Without a type field, a message with a symbol is a quote; times default to arrival
+    let plain = QuoteMapping::default();
+    match &map_text(
+        "{\"symbol\":\"SPY\",\"bid\":1.0,\"ask\":1.01}",
+        &plain,
+        received,
+    )[0]
+    {
+        Incoming::Event(MarketEvent::Quote(quote)) => {
+            assert_eq!(quote.biddate, received);
+            assert_eq!(quote.bidsz, 0);
+        }
+        other => panic!("Expected a quote, got {:?}", other),
+    }
+    assert!(matches!(
+        map_text("{\"status\":\"connected\"}", &plain, received)[0],
+        Incoming::Unknown(_)
+    ));
+    let millis = QuoteMapping {
+        bid_time: Some("ts".to_string()),
+        ..QuoteMapping::default()
+    };
+    match &map_text(
+        "{\"symbol\":\"SPY\",\"bid\":1.0,\"ask\":1.01,\"ts\":\"1557757189000\"}",
+        &millis,
+        received,
+    )[0]
+    {
+        Incoming::Event(MarketEvent::Quote(quote)) => {
+            assert_eq!(quote.biddate.timestamp_millis(), 1557757189000)
+        }
+        other => panic!("Expected a quote, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_requires_websocket_config() {
+    assert!(new("token".to_string(), &MarketDataConfig::default()).is_err());
+}
+
+#[test]
+fn test_streams_quotes_from_stub() {
+    let stub = TradierStub::start(vec![vec![
+        Step::Send(format!(
+            "[{},{}]",
+            vendor_quote("SPY", 100.0),
+            vendor_quote("QQQ", 200.0)
+        )),
+        Step::Send("{\"T\":\"subscription\",\"quotes\":[\"SPY\",\"QQQ\"]}".to_string()),
+        Step::Send(vendor_quote("SPY", 100.5)),
+    ]]);
+    let service = new("vendor-key".to_string(), &config(&stub)).unwrap();
+    let quotes = service.subscribe().unwrap();
+    let raw = service.subscribe_raw().unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string(), "QQQ".to_string()])
+        .unwrap();
+
+    let received: Vec<(String, f64)> = (0..3)
+        .map(|_| recv(&quotes))
+        .map(|quote| (quote.symbol, quote.bid))
+        .collect();
+    assert_eq!(
+        received,
+        vec![
+            ("SPY".to_string(), 100.0),
+            ("QQQ".to_string(), 200.0),
+            ("SPY".to_string(), 100.5)
+        ]
+    );
+    assert_eq!(
+        wait_for(&stub, 2),
+        vec![
+            serde_json::json!({"action": "auth", "key": "vendor-key"}),
+            serde_json::json!({"action": "subscribe", "quotes": ["SPY", "QQQ"]})
+        ]
+    );
+
+    // Raw messages are the quotes in Tradier's form
+    let first = raw.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert!(matches!(
+        serde_json::from_str::<MarketEvent>(&first).unwrap(),
+        MarketEvent::Quote(q) if q.symbol == "SPY"
+    ));
+    let counts = service.message_counts();
+    assert_eq!((counts.events, counts.unknown), (3, 1));
+
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_changes_symbols_on_the_same_connection() {
+    let stub = TradierStub::start(vec![vec![]]);
+    let service = new("vendor-key".to_string(), &config(&stub)).unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handle = service
+        .init(shutdown.clone(), vec!["SPY".to_string(), "QQQ".to_string()])
+        .unwrap();
+    wait_for(&stub, 2);
+
+    service.add_symbols(&["IWM".to_string()]).unwrap();
+    wait_for(&stub, 3);
+    service.remove_symbols(&["QQQ".to_string()]).unwrap();
+    let sent = wait_for(&stub, 4);
+
+    assert_eq!(
+        sent[2..],
+        [
+            serde_json::json!({"action": "subscribe", "quotes": ["IWM"]}),
+            serde_json::json!({"action": "unsubscribe", "quotes": ["QQQ"]})
+        ]
+    );
+    assert_eq!(stub.connections(), 1);
+
+    shutdown.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_same_quotes_whichever_provider() {
+    let tradier = TradierStub::start(vec![vec![Step::Send(
+        "{\"type\":\"quote\",\"symbol\":\"SPY\",\"bid\":100.0,\"bidsz\":3,\"bidexch\":\"Q\",\"biddate\":\"1557757189123\",\"ask\":100.01,\"asksz\":4,\"askexch\":\"\",\"askdate\":\"1557757189123\"}"
+            .to_string(),
+    )]]);
+    let vendor = TradierStub::start(vec![vec![Step::Send(vendor_quote("SPY", 100.0))]]);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let symbols = vec!["SPY".to_string()];
+
+    let from_tradier = market_data::new(
+        "stub-token".to_string(),
+        &MarketDataConfig {
+            session_url: tradier.session_url.clone(),
+            stream_url: tradier.stream_url.clone(),
+            ..MarketDataConfig::default()
+        },
+    );
+    let from_vendor = new("vendor-key".to_string(), &config(&vendor)).unwrap();
+    let (a, b) = (
+        from_tradier.subscribe().unwrap(),
+        from_vendor.subscribe().unwrap(),
+    );
+    let handles = [
+        from_tradier
+            .init(shutdown.clone(), symbols.clone())
+            .unwrap(),
+        from_vendor.init(shutdown.clone(), symbols).unwrap(),
+    ];
+
+    assert_eq!(
+        serde_json::to_value(recv(&a)).unwrap(),
+        serde_json::to_value(recv(&b)).unwrap()
+    );
+    shutdown.store(true, Ordering::Relaxed);
+    handles.into_iter().for_each(|h| h.join().unwrap());
+}
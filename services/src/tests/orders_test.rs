@@ -1,6 +1,9 @@
 use super::*;
+use crate::brokerage_stub::{BrokerageStub, Failure};
 use crate::persistence;
 use chrono::Local;
+use domain::domain::AccountBalance;
+use std::{sync::atomic::AtomicBool, thread::JoinHandle};
 
 #[test]
 fn test_create_order() {
@@ -21,6 +24,7 @@ fn test_create_order() {
         side: Side::Buy,
         quantity: 1,
         px: Some(100.0),
+        account: String::new(),
     };
 
     match service.create_order(order.clone(), "mean-reversion".to_string()) {
@@ -31,3 +35,350 @@ fn test_create_order() {
         }
     }
 }
+
+#[derive(Default)]
+struct MockPersistenceService {
+    orders: Mutex<Vec<Order>>,
+    positions: Mutex<Vec<Position>>,
+    pnl: Mutex<Vec<RealizedPnL>>,
+}
+
+impl PersistenceService for MockPersistenceService {
+    fn init(&self, _: Arc<AtomicBool>) -> Result<JoinHandle<()>, String> {
+        unimplemented!()
+    }
+
+    fn write(&self, p: Box<dyn Persistable + Send>) -> Result<(), String> {
+        let any = p.as_any();
+        if let Some(order) = any.downcast_ref::<Order>() {
+            self.orders.lock().unwrap().push(order.clone());
+        } else if let Some(position) = any.downcast_ref::<Position>() {
+            self.positions.lock().unwrap().push(position.clone());
+        } else if let Some(pnl) = any.downcast_ref::<RealizedPnL>() {
+            self.pnl.lock().unwrap().push(pnl.clone());
+        }
+        Ok(())
+    }
+
+    fn drop_positions(&self, account: &str) -> Result<(), String> {
+        self.positions
+            .lock()
+            .unwrap()
+            .retain(|position| position.account != account);
+        Ok(())
+    }
+
+    fn read_positions(&self, _: &str) -> Result<Vec<Position>, String> {
+        unimplemented!()
+    }
+
+    fn read_balance(&self, _: &str) -> Result<Option<AccountBalance>, String> {
+        unimplemented!()
+    }
+
+    fn read_orders(&self, _: &str) -> Result<Vec<Order>, String> {
+        unimplemented!()
+    }
+}
+
+fn order(symbol: &str, side: Side, quantity: i64, px: f64) -> Order {
+    Order {
+        id: None,
+        date: Local::now().naive_local().date(),
+        symbol: symbol.to_string(),
+        side,
+        quantity,
+        px: Some(px),
+        account: String::new(),
+    }
+}
+
+fn stub_orders(stub: &BrokerageStub) -> (Arc<impl OrderService>, Arc<MockPersistenceService>) {
+    let persistence = Arc::new(MockPersistenceService::default());
+    let service = new(
+        "stub-token".to_string(),
+        "VA000001".to_string(),
+        stub.base_url.clone(),
+        persistence.clone(),
+    )
+    .expect("Failed to create OrdersService");
+    (service, persistence)
+}
+
+#[test]
+fn test_url() {
+    assert_eq!(
+        implementation::url("api.tradier.com", "/v1/accounts"),
+        "https://api.tradier.com/v1/accounts"
+    );
+    assert_eq!(
+        implementation::url("http://127.0.0.1:8080/", "/v1/accounts"),
+        "http://127.0.0.1:8080/v1/accounts"
+    );
+}
+
+#[test]
+fn test_reads_positions_from_broker() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    let (service, _) = stub_orders(&stub);
+    assert!(service.get_position("SPY").is_none());
+
+    // A lone position comes as an object, several as an array
+    stub.add_position("SPY", 10, 1000.0);
+    let (service, persistence) = stub_orders(&stub);
+    assert_eq!(service.get_position("SPY").unwrap().quantity, 10);
+    assert_eq!(persistence.positions.lock().unwrap().len(), 1);
+
+    stub.add_position("QQQ", 5, 2000.0);
+    let (service, _) = stub_orders(&stub);
+    assert_eq!(service.get_position("SPY").unwrap().quantity, 10);
+    assert_eq!(service.get_position("QQQ").unwrap().cost_basis, 2000.0);
+}
+
+#[test]
+fn test_round_trip_against_stub() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    let (service, persistence) = stub_orders(&stub);
+
+    let bought = service
+        .create_order(order("SPY", Side::Buy, 10, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(bought.id, Some(1));
+    assert_eq!(stub.position("SPY"), Some((10, 1000.0)));
+    assert_eq!(service.get_position("SPY").unwrap().quantity, 10);
+
+    stub.set_price("SPY", 110.0);
+    service
+        .create_order(order("SPY", Side::Sell, 10, 110.0), "test".to_string())
+        .unwrap();
+    assert_eq!(stub.position("SPY"), Some((0, 0.0)));
+    assert_eq!(service.get_position("SPY").unwrap().quantity, 0);
+
+    // Each order is placed once
+    assert_eq!(
+        stub.orders()
+            .iter()
+            .map(|o| (o.side.as_str(), o.quantity, o.price))
+            .collect::<Vec<_>>(),
+        vec![("buy", 10, 100.0), ("sell", 10, 110.0)]
+    );
+    assert_eq!(persistence.orders.lock().unwrap().len(), 2);
+    let pnl = persistence.pnl.lock().unwrap();
+    assert_eq!(pnl.len(), 1);
+    assert_eq!(pnl[0].pnl, 100.0);
+}
+
+#[test]
+fn test_broker_failures() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    let (service, persistence) = stub_orders(&stub);
+    let buy = || service.create_order(order("SPY", Side::Buy, 1, 100.0), "test".to_string());
+    // Read now, so that the failures below meet the orders rather than the balances
+    service.balances().unwrap();
+
+    stub.fail_next(Failure::Reject("rejected".to_string()));
+    assert_eq!(buy().unwrap_err(), "rejected");
+
+    stub.fail_next(Failure::Error("Account is restricted".to_string()));
+    assert!(buy().unwrap_err().contains("Account is restricted"));
+
+    stub.fail_next(Failure::Status(503, "Service Unavailable".to_string()));
+    assert!(buy().is_err());
+
+    // Unknown to the broker
+    assert!(service
+        .create_order(order("XYZ", Side::Buy, 1, 1.0), "test".to_string())
+        .unwrap_err()
+        .contains("Unknown symbol"));
+
+    assert!(stub.orders().is_empty());
+    assert!(persistence.orders.lock().unwrap().is_empty());
+    assert!(service.get_position("SPY").is_none());
+}
+
+#[test]
+fn test_retries_after_hangup_and_waits_out_delay() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    let (service, _) = stub_orders(&stub);
+    service.balances().unwrap();
+    let requests = stub.requests();
+
+    stub.fail_next(Failure::Hangup);
+    stub.fail_next(Failure::Delay(std::time::Duration::from_millis(300)));
+    service
+        .create_order(order("SPY", Side::Buy, 1, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(stub.requests() - requests, 2);
+    assert_eq!(stub.orders().len(), 1);
+}
+
+#[test]
+fn test_bad_credentials() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    let persistence = Arc::new(MockPersistenceService::default());
+    let service = new(
+        "wrong-token".to_string(),
+        "VA000001".to_string(),
+        stub.base_url.clone(),
+        persistence,
+    )
+    .unwrap();
+    assert!(service
+        .create_order(order("SPY", Side::Buy, 1, 100.0), "test".to_string())
+        .is_err());
+    assert!(stub.orders().is_empty());
+}
+
+#[test]
+fn test_accounts_keep_their_own_positions() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    let persistence = Arc::new(MockPersistenceService::default());
+    let tradier = with_broker(
+        "live".to_string(),
+        broker::tradier(
+            "stub-token".to_string(),
+            "VA000001".to_string(),
+            stub.base_url.clone(),
+        ),
+        persistence.clone(),
+    )
+    .unwrap();
+    let paper = with_broker(
+        "paper".to_string(),
+        broker::paper(10000.0),
+        persistence.clone(),
+    )
+    .unwrap();
+
+    tradier
+        .create_order(order("SPY", Side::Buy, 10, 100.0), "a".to_string())
+        .unwrap();
+    paper
+        .create_order(order("SPY", Side::Buy, 3, 101.0), "b".to_string())
+        .unwrap();
+
+    assert_eq!(tradier.get_position("SPY").unwrap().quantity, 10);
+    assert_eq!(paper.get_position("SPY").unwrap().quantity, 3);
+    assert_eq!(stub.orders().len(), 1);
+    assert_eq!(persistence.orders.lock().unwrap().len(), 2);
+
+    // Stored under each account's name, so the same symbol's positions are kept apart
+    let stored = |account: &str| {
+        persistence
+            .positions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| p.account == account && p.symbol == "SPY")
+            .map(|p| p.quantity)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!((stored("live"), stored("paper")), (vec![10], vec![3]));
+    assert!(persistence
+        .orders
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|o| o.account == "paper" && o.quantity == 3));
+
+    // Both accounts' first orders have id 1, so their P&L is kept apart by account
+    paper
+        .create_order(order("SPY", Side::Sell, 3, 102.0), "b".to_string())
+        .unwrap();
+    let pnl = persistence.pnl.lock().unwrap();
+    assert_eq!(
+        pnl.iter()
+            .map(|p| (p.account.as_str(), p.id))
+            .collect::<Vec<_>>(),
+        vec![("paper", 2)]
+    );
+}
+
+#[test]
+fn test_buys_held_to_buying_power() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    // A margin account, with twice its cash to spend
+    stub.set_cash(1000.0);
+    stub.hold_orders(true);
+    let (service, _) = stub_orders(&stub);
+    assert_eq!(service.balances().unwrap().buying_power, 2000.0);
+
+    let bought = service
+        .create_order(order("SPY", Side::Buy, 30, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(bought.quantity, 20);
+
+    // What the open order may cost is set aside until the broker is done with it
+    assert_eq!(service.balances().unwrap().buying_power, 0.0);
+    assert!(service
+        .create_order(order("SPY", Side::Buy, 1, 100.0), "test".to_string())
+        .unwrap_err()
+        .contains("Insufficient buying power"));
+
+    broker::tradier(
+        "stub-token".to_string(),
+        "VA000001".to_string(),
+        stub.base_url.clone(),
+    )
+    .cancel(bought.id.unwrap())
+    .unwrap();
+    let bought = service
+        .create_order(order("SPY", Side::Buy, 5, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(bought.quantity, 5);
+    assert_eq!(service.balances().unwrap().buying_power, 1500.0);
+
+    // Sells need none
+    service
+        .create_order(order("SPY", Side::Buy, 15, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(service.balances().unwrap().buying_power, 0.0);
+    stub.hold_orders(false);
+    stub.add_position("SPY", 5, 500.0);
+    assert!(service
+        .create_order(order("SPY", Side::Sell, 5, 100.0), "test".to_string())
+        .is_ok());
+}
+
+#[test]
+fn test_balances_read_while_order_is_placed() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    stub.set_cash(1000.0);
+    let (service, _) = stub_orders(&stub);
+    service.balances().unwrap();
+
+    // The broker takes its time over the order, which is set aside for meanwhile
+    stub.fail_next(Failure::Delay(std::time::Duration::from_millis(500)));
+    std::thread::scope(|scope| {
+        let buying = scope
+            .spawn(|| service.create_order(order("SPY", Side::Buy, 5, 100.0), "test".to_string()));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        assert_eq!(service.balances().unwrap().buying_power, 1500.0);
+        assert!(started.elapsed() < std::time::Duration::from_millis(300));
+        assert_eq!(buying.join().unwrap().unwrap().quantity, 5);
+    });
+}
+
+#[test]
+fn test_buys_placed_when_balances_unread() {
+    let stub = BrokerageStub::start("stub-token", "VA000001");
+    stub.set_price("SPY", 100.0);
+    stub.set_cash(100.0);
+    let (service, _) = stub_orders(&stub);
+
+    // Not sized down, since the buying power isn't known
+    stub.fail_next(Failure::Error("Balances unavailable".to_string()));
+    let bought = service
+        .create_order(order("SPY", Side::Buy, 5, 100.0), "test".to_string())
+        .unwrap();
+    assert_eq!(bought.quantity, 5);
+    assert_eq!(stub.orders().len(), 1);
+}
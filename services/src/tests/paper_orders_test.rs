@@ -0,0 +1,242 @@
+use super::*;
+use crate::market_data_recorder::{Record, RotatingWriter};
+use crate::market_data_replay;
+use app_config::app_config::{CommissionConfig, ReplayConfig, ReplaySpeed, SubscriptionConfig};
+use domain::domain::{MarketEvent, Persistable, Quote, Side};
+use std::sync::{atomic::AtomicBool, Mutex};
+use std::thread::JoinHandle;
+
+#[derive(Default)]
+struct MockPersistenceService {
+    balances: Mutex<Vec<AccountBalance>>,
+    positions: Mutex<Vec<Position>>,
+    orders: Mutex<Vec<Order>>,
+}
+
+impl PersistenceService for MockPersistenceService {
+    fn init(&self, _: Arc<AtomicBool>) -> Result<JoinHandle<()>, String> {
+        unimplemented!()
+    }
+
+    fn write(&self, p: Box<dyn Persistable + Send>) -> Result<(), String> {
+        if let Some(balance) = p.as_any().downcast_ref::<AccountBalance>() {
+            self.balances.lock().unwrap().push(balance.clone());
+        } else if let Some(position) = p.as_any().downcast_ref::<Position>() {
+            self.positions.lock().unwrap().push(position.clone());
+        } else if let Some(order) = p.as_any().downcast_ref::<Order>() {
+            self.orders.lock().unwrap().push(order.clone());
+        }
+        Ok(())
+    }
+
+    fn drop_positions(&self, _: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn read_positions(&self, account: &str) -> Result<Vec<Position>, String> {
+        let positions = self.positions.lock().unwrap();
+        Ok(positions
+            .iter()
+            .filter(|p| p.account == account)
+            .cloned()
+            .collect())
+    }
+
+    fn read_balance(&self, account: &str) -> Result<Option<AccountBalance>, String> {
+        let balances = self.balances.lock().unwrap();
+        Ok(balances
+            .iter()
+            .rev()
+            .find(|b| b.account == account)
+            .cloned())
+    }
+
+    fn read_orders(&self, account: &str) -> Result<Vec<Order>, String> {
+        let orders = self.orders.lock().unwrap();
+        Ok(orders
+            .iter()
+            .filter(|o| o.account == account)
+            .cloned()
+            .collect())
+    }
+}
+
+fn quote(symbol: &str, bid: f64, ask: f64) -> Quote {
+    let now = Local::now();
+    Quote {
+        symbol: symbol.to_string(),
+        bid,
+        ask,
+        biddate: now,
+        askdate: now,
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
+    }
+}
+
+fn order(side: Side, quantity: i64, px: f64) -> Order {
+    Order {
+        id: None,
+        date: Local::now().naive_local().date(),
+        symbol: "SPY".to_string(),
+        side,
+        quantity,
+        px: Some(px),
+        account: String::new(),
+    }
+}
+
+#[test]
+fn test_fills_against_live_quotes() {
+    let path = std::env::temp_dir().join(format!("paper_orders_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = RotatingWriter::new(path.clone(), 1024 * 1024);
+    for q in [quote("SPY", 99.0, 99.5), quote("SPY", 100.0, 100.5)] {
+        let raw = serde_json::to_string(&MarketEvent::Quote(q)).unwrap();
+        writer.write(&Record::new(Local::now(), raw)).unwrap();
+    }
+    writer.finish().unwrap();
+    let market_data = market_data_replay::new(
+        &ReplayConfig {
+            path: path.to_string_lossy().to_string(),
+            speed: ReplaySpeed::AsFastAsPossible,
+            ..ReplayConfig::default()
+        },
+        SubscriptionConfig::default(),
+    );
+
+    let persistence = Arc::new(MockPersistenceService::default());
+    let fills = FillConfig {
+        slippage_bps: 0.0,
+        commission: CommissionConfig {
+            per_order: 1.0,
+            ..CommissionConfig::default()
+        },
+    };
+    let book = broker::paper_book(10000.0);
+    let orders = new(
+        "paper".to_string(),
+        book.clone(),
+        fills,
+        market_data.clone(),
+        persistence.clone(),
+    )
+    .unwrap();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    market_data
+        .init(shutdown, vec!["SPY".to_string()])
+        .unwrap()
+        .join()
+        .unwrap();
+
+    // Bought at the latest ask rather than the price the strategy saw
+    let bought = orders
+        .create_order(order(Side::Buy, 10, 90.0), "test".into())
+        .unwrap();
+    assert_eq!(bought.px, Some(100.5));
+    assert_eq!(orders.get_position("SPY").unwrap().cost_basis, 1006.0);
+    assert_eq!(
+        orders.balances().unwrap(),
+        Balances {
+            cash: 8994.0,
+            equity: 8994.0 + 10.0 * 100.25,
+            buying_power: 8994.0
+        }
+    );
+
+    let sold = orders
+        .create_order(order(Side::Sell, 10, 110.0), "test".into())
+        .unwrap();
+    assert_eq!(sold.px, Some(100.0));
+    assert_eq!(orders.balances().unwrap().cash, 9993.0);
+    let recorded: Vec<f64> = persistence
+        .balances
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|balance| balance.cash)
+        .collect();
+    assert_eq!(recorded, vec![10000.0, 8994.0, 9993.0]);
+
+    // The book outlives the day's market data, and the next day's orders carry on from it
+    let next_day = new(
+        "paper".to_string(),
+        book,
+        fills,
+        market_data,
+        persistence.clone(),
+    )
+    .unwrap();
+    assert_eq!(next_day.balances().unwrap().cash, 9993.0);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_restores_book_after_restart() {
+    let persistence = MockPersistenceService::default();
+    let position = |account: &str, symbol: &str, quantity: i64| Position {
+        broker_id: Some(1),
+        symbol: symbol.to_string(),
+        quantity,
+        cost_basis: quantity as f64 * 100.0,
+        date: Local::now(),
+        account: account.to_string(),
+    };
+    let stored = [
+        position("paper", "SPY", 10),
+        // Sold out before the restart
+        position("paper", "QQQ", 0),
+        position("live", "IWM", 5),
+    ];
+    for p in stored {
+        persistence.write(Box::new(p)).unwrap();
+    }
+    persistence
+        .write(Box::new(Order {
+            id: Some(7),
+            account: "paper".to_string(),
+            ..order(Side::Buy, 10, 100.0)
+        }))
+        .unwrap();
+    persistence
+        .write(Box::new(AccountBalance {
+            account: "paper".to_string(),
+            cash: 9000.0,
+            equity: 10000.0,
+            date: Local::now(),
+        }))
+        .unwrap();
+
+    // Carried on from what was stored, with positions valued at cost until quoted
+    let book = restore("paper", 10000.0, &persistence).unwrap();
+    let restored = broker::paper_with(book, FillConfig::default(), None);
+    let positions = restored.positions().unwrap();
+    assert_eq!(positions.keys().collect::<Vec<_>>(), vec!["SPY"]);
+    assert_eq!(positions["SPY"].quantity, 10);
+    assert_eq!(
+        restored.balances().unwrap(),
+        Balances {
+            cash: 9000.0,
+            equity: 10000.0,
+            buying_power: 9000.0
+        }
+    );
+
+    // Ids carry on from those stored rather than starting again from 1
+    let bought = restored
+        .submit(&Order {
+            symbol: "IWM".to_string(),
+            ..order(Side::Buy, 1, 100.0)
+        })
+        .unwrap();
+    assert_eq!(bought.id, 8);
+    assert_eq!(restored.positions().unwrap()["IWM"].broker_id, Some(2));
+
+    // Nothing stored, so a new account
+    let book = restore("new", 500.0, &persistence).unwrap();
+    let fresh = broker::paper_with(book, FillConfig::default(), None);
+    assert_eq!(fresh.balances().unwrap().cash, 500.0);
+    assert!(fresh.positions().unwrap().is_empty());
+}
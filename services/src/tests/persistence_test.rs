@@ -13,6 +13,7 @@ fn test_persistence() {
         symbol: "SPY".to_string(),
         quantity: 100,
         px: Some(100.0),
+        account: String::new(),
     };
 
     let position = Position {
@@ -21,6 +22,7 @@ fn test_persistence() {
         quantity: 100,
         cost_basis: 1000.0,
         date: Local::now(),
+        account: String::new(),
     };
 
     let pnl = RealizedPnL {
@@ -29,6 +31,7 @@ fn test_persistence() {
         date: NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
         pnl: 100.0,
         strategy: "mean-reversion".to_string(),
+        account: String::new(),
     };
 
     let db = persistence::new("mongodb://localhost:27017".to_string());
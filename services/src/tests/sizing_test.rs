@@ -0,0 +1,138 @@
+use super::*;
+use chrono::NaiveDate;
+
+fn day(i: u32, high: f64, low: f64, close: f64) -> Day {
+    Day {
+        symbol: Some("SPY".to_string()),
+        date: NaiveDate::from_ymd_opt(2024, 6, i).unwrap(),
+        open: close,
+        high,
+        low,
+        close,
+        volume: 0,
+    }
+}
+
+// True ranges of 2, 4 (a gap up from 100 to a high of 104) and 3
+fn data() -> SymbolData {
+    SymbolData {
+        symbol: "SPY".to_string(),
+        history: vec![
+            day(3, 101.0, 99.0, 100.0),
+            day(4, 104.0, 102.0, 103.0),
+            day(5, 105.0, 102.0, 103.0),
+        ],
+        mean: 102.0,
+        std_dev: 5.0,
+    }
+}
+
+fn size(sizing: Sizing, held: i64, equity: Option<f64>) -> i64 {
+    shares_to_buy(&sizing, 100.0, held, 100000, equity, &data())
+}
+
+#[test]
+fn test_models() {
+    // All the capital, less what's held
+    assert_eq!(size(Sizing::default(), 0, None), 1000);
+    assert_eq!(size(Sizing::default(), 400, None), 600);
+
+    let fixed_shares = Sizing {
+        model: SizingModel::FixedShares,
+        shares: 50,
+        ..Sizing::default()
+    };
+    assert_eq!(size(fixed_shares, 0, None), 50);
+    assert_eq!(size(fixed_shares, 50, None), 0);
+
+    let fixed_notional = Sizing {
+        model: SizingModel::FixedNotional,
+        notional: 2550.0,
+        ..Sizing::default()
+    };
+    assert_eq!(size(fixed_notional, 0, None), 25);
+
+    // Of the account's equity, or of the symbol's capital without it
+    let percent = Sizing {
+        model: SizingModel::PercentOfEquity,
+        percent: 10.0,
+        ..Sizing::default()
+    };
+    assert_eq!(size(percent, 0, Some(50000.0)), 50);
+    assert_eq!(size(percent, 0, None), 100);
+
+    // 1% of 50000 is 500 at risk, over an ATR of 3 or a standard deviation of 5
+    let volatility = Sizing {
+        model: SizingModel::VolatilityTarget,
+        risk_percent: 1.0,
+        atr_days: 3,
+        ..Sizing::default()
+    };
+    assert_eq!(size(volatility, 0, Some(50000.0)), 166);
+    let std_dev = Sizing {
+        volatility: Volatility::StdDev,
+        ..volatility
+    };
+    assert_eq!(size(std_dev, 0, Some(50000.0)), 100);
+
+    // Full Kelly at 60% and even odds is 20% of equity, of which half is bet
+    let kelly = Sizing {
+        model: SizingModel::Kelly,
+        win_rate: 0.6,
+        payoff_ratio: 1.0,
+        kelly_fraction: 0.5,
+        ..Sizing::default()
+    };
+    assert_eq!(size(kelly, 0, Some(50000.0)), 50);
+    let losing = Sizing {
+        win_rate: 0.4,
+        ..kelly
+    };
+    assert_eq!(size(losing, 0, Some(50000.0)), 0);
+
+    // Never more than the capital buys
+    let huge = Sizing {
+        shares: 5000,
+        ..fixed_shares
+    };
+    assert_eq!(size(huge, 0, None), 1000);
+}
+
+#[test]
+fn test_tranches_lots_and_minimums() {
+    let tranches = Sizing {
+        model: SizingModel::FixedShares,
+        shares: 100,
+        tranches: 3,
+        ..Sizing::default()
+    };
+    assert_eq!(size(tranches, 0, None), 34);
+    assert_eq!(size(tranches, 68, None), 32);
+    assert_eq!(size(tranches, 100, None), 0);
+
+    let lots = Sizing {
+        lot_size: 10,
+        ..tranches
+    };
+    assert_eq!(size(lots, 0, None), 30);
+    assert_eq!(size(lots, 95, None), 0);
+
+    let minimum = Sizing {
+        min_shares: 5,
+        ..tranches
+    };
+    assert_eq!(size(minimum, 97, None), 0);
+    let min_notional = Sizing {
+        min_notional: 3500.0,
+        ..tranches
+    };
+    assert_eq!(size(min_notional, 0, None), 0);
+}
+
+#[test]
+fn test_atr() {
+    assert_eq!(atr(&data().history, 3), 3.0);
+    assert_eq!(atr(&data().history, 2), 3.5);
+    assert_eq!(atr(&data().history, 1), 3.0);
+    assert_eq!(atr(&[], 14), 0.0);
+}
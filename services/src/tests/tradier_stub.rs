@@ -0,0 +1,196 @@
+// A local stand-in for Tradier's streaming API: an HTTP endpoint that creates sessions and a websocket
+// endpoint that records each subscription and then plays a script, one script per connection in order.
+// The websocket endpoint serves as any other vendor's stream too, as it doesn't check what it's sent.
+use serde_json::Value;
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tungstenite::Message;
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Send(String),
+    Pause(Duration),
+    // Closes with a close frame
+    Close,
+    // Drops the connection without a close frame
+    Drop,
+}
+
+// Connections outlasting their script are held open until the stub is dropped, recording any further
+// subscription messages
+pub struct TradierStub {
+    pub session_url: String,
+    pub stream_url: String,
+    sessions: Arc<AtomicUsize>,
+    connections: Arc<AtomicUsize>,
+    subscriptions: Arc<Mutex<Vec<Value>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl TradierStub {
+    pub fn start(scripts: Vec<Vec<Step>>) -> TradierStub {
+        let sessions = Arc::new(AtomicUsize::new(0));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let subscriptions = Arc::new(Mutex::new(vec![]));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let http = listen();
+        let session_url = format!("http://{}/v1/markets/events/session", addr(&http));
+        let sessions_served = sessions.clone();
+        accept_loop(http, stopped.clone(), move |mut stream, _| {
+            let id = sessions_served.fetch_add(1, Ordering::SeqCst) + 1;
+            serve_session(&mut stream, id);
+        });
+
+        let ws = listen();
+        let stream_url = format!("ws://{}/v1/markets/events", addr(&ws));
+        let (recorded, connected) = (subscriptions.clone(), connections.clone());
+        accept_loop(ws, stopped.clone(), move |stream, stopped| {
+            let index = connected.fetch_add(1, Ordering::SeqCst);
+            let script = scripts.get(index).cloned().unwrap_or_default();
+            serve_stream(stream, script, &recorded, &stopped);
+        });
+
+        TradierStub {
+            session_url,
+            stream_url,
+            sessions,
+            connections,
+            subscriptions,
+            stopped,
+        }
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.sessions.load(Ordering::SeqCst)
+    }
+
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    // The subscription messages sent, in order, across connections
+    pub fn subscriptions(&self) -> Vec<Value> {
+        self.subscriptions.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TradierStub {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn listen() -> TcpListener {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind stub listener");
+    listener
+        .set_nonblocking(true)
+        .expect("Failed to set stub listener nonblocking");
+    listener
+}
+
+pub fn addr(listener: &TcpListener) -> String {
+    listener.local_addr().unwrap().to_string()
+}
+
+// Each connection is served on its own thread, until the stub is stopped
+pub fn accept_loop(
+    listener: TcpListener,
+    stopped: Arc<AtomicBool>,
+    serve: impl Fn(TcpStream, Arc<AtomicBool>) + Send + Sync + 'static,
+) {
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        while !stopped.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false).unwrap();
+                    let (serve, stopped) = (serve.clone(), stopped.clone());
+                    thread::spawn(move || serve(stream, stopped));
+                }
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    });
+}
+
+fn serve_session(stream: &mut TcpStream, id: usize) {
+    // The request has no body, so reading up to the end of its headers is enough
+    let mut request = vec![];
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => request.push(byte[0]),
+            _ => return,
+        }
+    }
+    let body = format!(
+        "{{\"stream\":{{\"url\":\"https://stream.tradier.com/v1/markets/events\",\"sessionid\":\"stub-session-{}\"}}}}",
+        id
+    );
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn serve_stream(
+    stream: TcpStream,
+    script: Vec<Step>,
+    subscriptions: &Mutex<Vec<Value>>,
+    stopped: &AtomicBool,
+) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    match socket.read() {
+        Ok(Message::Text(text)) => subscriptions
+            .lock()
+            .unwrap()
+            .push(serde_json::from_str(&text).unwrap_or(Value::String(text))),
+        _ => return,
+    }
+
+    for step in script {
+        match step {
+            Step::Send(text) => {
+                if socket.send(Message::Text(text)).is_err() {
+                    return;
+                }
+            }
+            Step::Pause(duration) => thread::sleep(duration),
+            Step::Close => {
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                return;
+            }
+            Step::Drop => return,
+        }
+    }
+
+    let _ = socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(10)));
+    while !stopped.load(Ordering::SeqCst) {
+        match socket.read() {
+            Ok(Message::Text(text)) => subscriptions
+                .lock()
+                .unwrap()
+                .push(serde_json::from_str(&text).unwrap_or(Value::String(text))),
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(_)) => (),
+            Err(_) => return,
+        }
+    }
+}
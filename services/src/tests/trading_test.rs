@@ -5,7 +5,7 @@ use implementation::*;
 
 struct MockHistoricalDataService {}
 impl HistoricalDataService for MockHistoricalDataService {
-    fn fetch(&self, _: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
+    fn fetch_as_of(&self, _: NaiveDate) -> Arc<HashMap<String, Vec<Day>>> {
         let days = vec![
             Day {
                 symbol: Some("SPY".to_string()),
@@ -52,6 +52,16 @@ fn test_load_history() {
     assert_eq!(spy.std_dev, 4.714045207910316);
 }
 
+#[test]
+fn test_load_history_without_data() {
+    let symbols = vec!["SPY".to_string(), "QQQ".to_string()];
+    let historical_data_service = Arc::new(MockHistoricalDataService {});
+    let date = Local::now().naive_local().date();
+    let data = load_history(date, &symbols, historical_data_service);
+    assert!(data.contains_key("SPY"));
+    assert!(!data.contains_key("QQQ"));
+}
+
 struct MockOrderService {}
 impl OrderService for MockOrderService {
     fn create_order(&self, order: Order, _: String) -> Result<Order, String> {
@@ -66,6 +76,7 @@ impl OrderService for MockOrderService {
                 broker_id: None,
                 cost_basis: 10000.0,
                 date: Local::now(),
+                account: String::new(),
             }),
             "AMZN" => None,
             _ => None,
@@ -88,9 +99,32 @@ fn test_handle_quote() {
         ask: 80.0,
         biddate: Local::now(),
         askdate: Local::now(),
+        bidsz: 0,
+        asksz: 0,
+        bidexch: String::new(),
+        askexch: String::new(),
     };
 
-    match maybe_create_order(date, Signal::Buy, orders.get_position("SPY"), &quote, 10000) {
+    let data = load_history(
+        date,
+        &["SPY".to_string()],
+        Arc::new(MockHistoricalDataService {}),
+    );
+    let shares = crate::sizing::shares_to_buy(
+        &Sizing::default(),
+        quote.ask,
+        100,
+        10000,
+        None,
+        &data["SPY"],
+    );
+    match maybe_create_order(
+        date,
+        Signal::Buy,
+        orders.get_position("SPY"),
+        &quote,
+        shares,
+    ) {
         Some(order) => {
             assert_eq!(order.symbol, "SPY");
             // Capital of $10K - 100 shares * 80 = $2000 remaining capital = 25 shares at $80
@@ -100,13 +134,7 @@ fn test_handle_quote() {
         None => panic!("Expected an order"),
     }
 
-    match maybe_create_order(
-        date,
-        Signal::Sell,
-        orders.get_position("SPY"),
-        &quote,
-        10000,
-    ) {
+    match maybe_create_order(date, Signal::Sell, orders.get_position("SPY"), &quote, 0) {
         Some(order) => {
             assert_eq!(order.symbol, "SPY");
             // We always unwind completely and have 100 shares, so any Sell signal should sell all
@@ -116,13 +144,7 @@ fn test_handle_quote() {
         None => panic!("Expected an order"),
     }
 
-    match maybe_create_order(
-        date,
-        Signal::None,
-        orders.get_position("SPY"),
-        &quote,
-        10000,
-    ) {
+    match maybe_create_order(date, Signal::None, orders.get_position("SPY"), &quote, 0) {
         Some(_) => panic!("Expected no order"),
         None => {}
     }
@@ -0,0 +1,181 @@
+use super::*;
+
+fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 7, day).unwrap()
+}
+
+fn day(d: u32, close: f64) -> Day {
+    Day {
+        symbol: Some("SPY".to_string()),
+        date: date(d),
+        open: close,
+        high: close,
+        low: close,
+        close,
+        volume: 100,
+    }
+}
+
+fn config(policy: ValidationPolicy) -> ValidationConfig {
+    ValidationConfig {
+        policy,
+        ..ValidationConfig::default()
+    }
+}
+
+// Trading days July 8th to 12th 2024, with the 10th missing, the 9th duplicated, a spike on the 11th
+// and a bad bar on the 12th
+fn history() -> Vec<Day> {
+    vec![
+        day(12, 0.0),
+        day(8, 100.0),
+        day(9, 101.0),
+        day(9, 999.0),
+        day(11, 250.0),
+        day(15, 102.0),
+    ]
+}
+
+#[test]
+fn test_issues() {
+    let (_, issues) = validate(
+        &config(ValidationPolicy::Drop),
+        history(),
+        date(8),
+        date(16),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        issues,
+        vec![
+            Issue::DuplicateDate(date(9)),
+            Issue::NonPositivePrice(date(12)),
+            Issue::Outlier(date(11)),
+            Issue::MissingDay(date(10)),
+        ]
+    );
+
+    let inverted = Day {
+        high: 99.0,
+        low: 101.0,
+        ..day(8, 100.0)
+    };
+    let (days, issues) = validate(
+        &config(ValidationPolicy::Drop),
+        vec![inverted],
+        date(8),
+        date(9),
+        &[],
+    )
+    .unwrap();
+    assert!(days.is_empty());
+    assert_eq!(issues, vec![Issue::HighBelowLow(date(8))]);
+}
+
+#[test]
+fn test_drop() {
+    let (days, _) = validate(
+        &config(ValidationPolicy::Drop),
+        history(),
+        date(8),
+        date(16),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        days.iter().map(|d| (d.date, d.close)).collect::<Vec<_>>(),
+        vec![(date(8), 100.0), (date(9), 101.0), (date(15), 102.0)]
+    );
+}
+
+#[test]
+fn test_forward_fill() {
+    let (days, _) = validate(
+        &config(ValidationPolicy::ForwardFill),
+        history(),
+        date(8),
+        date(16),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        days.iter().map(|d| (d.date, d.close)).collect::<Vec<_>>(),
+        vec![
+            (date(8), 100.0),
+            (date(9), 101.0),
+            (date(10), 101.0),
+            (date(11), 101.0),
+            (date(12), 101.0),
+            (date(15), 102.0)
+        ]
+    );
+    assert_eq!(days[2].volume, 0);
+    assert_eq!(days[2].symbol.as_deref(), Some("SPY"));
+}
+
+#[test]
+fn test_fail() {
+    let err = validate(
+        &config(ValidationPolicy::Fail),
+        history(),
+        date(8),
+        date(16),
+        &[],
+    )
+    .unwrap_err();
+    assert!(err.contains("missing trading day 2024-07-10"), "{}", err);
+
+    // Weekends and the July 4th holiday aren't missing, and the end date is excluded
+    let clean: Vec<Day> = [1, 2, 3, 5, 8].iter().map(|d| day(*d, 100.0)).collect();
+    let (days, issues) = validate(
+        &config(ValidationPolicy::Fail),
+        clean.clone(),
+        date(1),
+        date(9),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(days, clean);
+    assert!(issues.is_empty());
+}
+
+#[test]
+fn test_splits_are_not_outliers() {
+    let days = vec![day(8, 400.0), day(9, 100.0), day(10, 400.0)];
+    let (_, issues) = validate(
+        &config(ValidationPolicy::Drop),
+        days.clone(),
+        date(8),
+        date(11),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(issues, vec![Issue::Outlier(date(9))]);
+
+    let (_, issues) = validate(
+        &config(ValidationPolicy::Drop),
+        days,
+        date(8),
+        date(11),
+        &[date(9)],
+    )
+    .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[test]
+fn test_validate_all() {
+    let history = HashMap::from([
+        ("SPY".to_string(), history()),
+        ("QQQ".to_string(), vec![day(8, 100.0), day(9, 100.0)]),
+    ]);
+    let validated = validate_all(
+        &config(ValidationPolicy::Fail),
+        history,
+        date(8),
+        date(10),
+        &HashMap::new(),
+    );
+    assert_eq!(validated.keys().collect::<Vec<_>>(), vec!["QQQ"]);
+}
@@ -1,7 +1,10 @@
+use crate::feed_health;
 use crate::historical_data::HistoricalDataService;
 use crate::market_data::MarketDataService;
 use crate::orders::OrderService;
-use chrono::NaiveDate;
+use crate::sizing;
+use app_config::app_config::{FeedHealthConfig, Sizing};
+use chrono::{Local, NaiveDate};
 use domain::domain::*;
 use log::*;
 use std::collections::HashMap;
@@ -13,31 +16,34 @@ pub trait TradingService {
     fn shutdown(&mut self) -> Result<(), String>;
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn new(
     today: NaiveDate, // The date we're trading for - if backtesting, this is not the current date
-    strategy_name: String,
-    symbols: Vec<String>,
+    strategy: Strategy,
     capital: HashMap<String, i64>,
+    sizing: Sizing,
     market_data: Arc<impl MarketDataService + 'static + Send + Sync>,
     historical_data: Arc<impl HistoricalDataService + 'static + Send + Sync>,
     orders: Arc<impl OrderService + 'static + Send + Sync>,
+    health: FeedHealthConfig, // Quotes unfit to trade on are skipped
     shutdown: Arc<AtomicBool>,
 ) -> impl TradingService + 'static {
     implementation::Trading {
         today,
-        strategy_name,
-        symbols,
+        strategy,
         capital,
+        sizing,
         market_data,
         historical_data,
         orders,
+        health,
         thread_handle: None,
         rx: None,
         shutdown,
     }
 }
 
-mod implementation {
+pub mod implementation {
     use super::*;
     use crossbeam_channel::{Receiver, TryRecvError};
     use std::{
@@ -52,12 +58,13 @@ mod implementation {
         O: OrderService + 'static + Send + Sync,
     > {
         pub today: NaiveDate,
-        pub strategy_name: String,
-        pub symbols: Vec<String>,
+        pub strategy: Strategy,
         pub capital: HashMap<String, i64>,
+        pub sizing: Sizing,
         pub market_data: Arc<M>,
         pub historical_data: Arc<H>,
         pub orders: Arc<O>,
+        pub health: FeedHealthConfig,
         pub thread_handle: Option<JoinHandle<()>>,
         pub rx: Option<Receiver<Quote>>,
         pub shutdown: Arc<AtomicBool>,
@@ -70,18 +77,25 @@ mod implementation {
         > TradingService for Trading<M, H, O>
     {
         fn run(&mut self) -> Result<(), String> {
-            info!("Running with strategy: {:?}", self.strategy_name);
-            let symbol_data: HashMap<String, SymbolData> =
-                load_history(self.today, &self.symbols, self.historical_data.clone());
+            info!("Running with strategy: {:?}", self.strategy);
+            let symbol_data: HashMap<String, SymbolData> = load_history(
+                self.today,
+                self.strategy.symbols(),
+                self.historical_data.clone(),
+            );
             let orders: Arc<O> = self.orders.clone();
 
             match self.market_data.subscribe() {
                 Ok(rx) => {
                     info!("Subscribed to MarketDataService");
-                    let strategy = Strategy::new(&self.strategy_name, self.symbols.clone());
+                    // Kept so the subscription is dropped on shutdown
+                    self.rx = Some(rx.clone());
+                    let strategy = self.strategy.clone();
                     let capital = self.capital.clone();
+                    let sizing = self.sizing;
                     let date = self.today;
                     let shutdown = self.shutdown.clone();
+                    let health = self.health;
 
                     self.thread_handle = Some(std::thread::spawn(move || {
                         while !shutdown.load(std::sync::atomic::Ordering::Relaxed) {
@@ -89,11 +103,18 @@ mod implementation {
                                 Ok(quote) => {
                                     let symbol_capital = capital.get(&quote.symbol).unwrap_or(&0);
                                     info!("Received quote:\n{:?}", quote);
+                                    if let Some(problem) =
+                                        feed_health::assess(&quote, Local::now(), &health)
+                                    {
+                                        info!("Ignoring quote for {}: {}", quote.symbol, problem);
+                                        continue;
+                                    }
                                     handle_quote(
                                         date,
                                         &symbol_data,
                                         &quote,
                                         *symbol_capital,
+                                        &sizing,
                                         &strategy,
                                         orders.clone(),
                                     )
@@ -120,9 +141,9 @@ mod implementation {
         }
 
         fn shutdown(&mut self) -> Result<(), String> {
-            self.rx
-                .as_ref()
-                .map(|rx| (self.market_data.unsubscribe(rx).unwrap()));
+            if let Some(rx) = self.rx.as_ref() {
+                self.market_data.unsubscribe(rx).unwrap();
+            }
 
             self.thread_handle
                 .take()
@@ -137,6 +158,7 @@ mod implementation {
         symbol_data: &HashMap<String, SymbolData>,
         quote: &Quote,
         capital: i64,
+        sizing: &Sizing,
         strategy: &Strategy,
         orders: Arc<impl OrderService + 'static>,
     ) {
@@ -144,8 +166,19 @@ mod implementation {
             let maybe_position = orders.get_position(&quote.symbol);
             match strategy.handle(quote, symbol_data) {
                 Ok(signal) => {
+                    let shares = match signal {
+                        Signal::Buy => sizing::shares_to_buy(
+                            sizing,
+                            quote.ask,
+                            maybe_position.as_ref().map(|p| p.quantity).unwrap_or(0),
+                            capital,
+                            orders.balances().ok().map(|balances| balances.equity),
+                            symbol_data,
+                        ),
+                        _ => 0,
+                    };
                     if let Some(order) =
-                        maybe_create_order(date, signal, maybe_position, quote, capital)
+                        maybe_create_order(date, signal, maybe_position, quote, shares)
                     {
                         match orders.create_order(order.clone(), strategy.to_string()) {
                             Ok(o) => info!("Order created: {:?}", o),
@@ -165,19 +198,17 @@ mod implementation {
         signal: Signal,
         maybe_position: Option<Position>,
         quote: &Quote,
-        capital: i64,
+        // As sized for a buy signal
+        shares: i64,
     ) -> Option<Order> {
         match signal {
             Signal::Buy => {
-                // If position market value < capital, buy up to the limit
-                let present_market_value = maybe_position
-                    .map(|p| p.quantity as f64 * quote.ask)
-                    .unwrap_or(0.0) as i64;
-                let remaining_capital = capital - present_market_value;
-                let shares = (remaining_capital as f64 / quote.ask) as i64;
                 info!(
-                    "Buy signal for {} at {}; present_market_value: {}; remaining_capital: {}; shares to buy: {}",
-                    quote.symbol, quote.ask, present_market_value, remaining_capital, shares
+                    "Buy signal for {} at {}; held: {}; shares to buy: {}",
+                    quote.symbol,
+                    quote.ask,
+                    maybe_position.map(|p| p.quantity).unwrap_or(0),
+                    shares
                 );
 
                 match shares {
@@ -188,9 +219,10 @@ mod implementation {
                         side: Side::Buy,
                         id: None,
                         px: Some(quote.ask),
+                        account: String::new(),
                     }),
                     _ => {
-                        info!("Buy signal for {}, but no capital", quote.symbol);
+                        info!("Buy signal for {}, but nothing to buy", quote.symbol);
                         None
                     }
                 }
@@ -206,6 +238,7 @@ mod implementation {
                         side: Side::Sell,
                         id: None,
                         px: Some(quote.bid),
+                        account: String::new(),
                     }),
                     _ => {
                         info!(
@@ -221,17 +254,18 @@ mod implementation {
         }
     }
 
+    // Symbols without history are left out, and so won't be traded
     pub fn load_history(
-        end: NaiveDate,
+        as_of: NaiveDate,
         symbols: &[String],
         historical_data_service: Arc<impl HistoricalDataService + 'static>,
     ) -> HashMap<String, SymbolData> {
-        let data = historical_data_service.fetch(end);
+        let data = historical_data_service.fetch_as_of(as_of);
         symbols
             .iter()
-            .map(|symbol| -> (String, SymbolData) {
-                let var_name = match data.get(symbol) {
-                    Some(history) => {
+            .filter_map(|symbol| -> Option<(String, SymbolData)> {
+                match data.get(symbol) {
+                    Some(history) if !history.is_empty() => {
                         let sum = history.iter().map(|day| day.close).sum::<f64>();
                         let len = history.len() as f64;
                         let mean = sum / len;
@@ -248,16 +282,19 @@ mod implementation {
                             std_dev,
                         };
                         info!("Initted history for {}", symbol);
-                        (symbol.to_owned(), data)
+                        Some((symbol.to_owned(), data))
                     }
-                    None => panic!("No history for {}", symbol),
-                };
-                var_name
+                    _ => {
+                        error!("No history for {}, so it won't be traded", symbol);
+                        None
+                    }
+                }
             })
             .collect()
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_to_owned, clippy::single_match)]
 #[path = "./tests/trading_test.rs"]
 mod trading_test;
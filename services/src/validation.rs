@@ -0,0 +1,201 @@
+use app_config::app_config::{ValidationConfig, ValidationPolicy};
+use chrono::NaiveDate;
+use core::calendar;
+use domain::domain::{CorporateAction, Day};
+use log::*;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Display, Formatter},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    MissingDay(NaiveDate),
+    NonPositivePrice(NaiveDate),
+    HighBelowLow(NaiveDate),
+    Outlier(NaiveDate),
+    DuplicateDate(NaiveDate),
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Issue::MissingDay(date) => write!(f, "missing trading day {}", date),
+            Issue::NonPositivePrice(date) => write!(f, "zero or negative price on {}", date),
+            Issue::HighBelowLow(date) => write!(f, "high below low on {}", date),
+            Issue::Outlier(date) => write!(f, "outlier close on {}", date),
+            Issue::DuplicateDate(date) => write!(f, "duplicate bar for {}", date),
+        }
+    }
+}
+
+// Validates each symbol's history, logging what's found. Symbols failing validation under the fail
+// policy are left out, so only they go untraded.
+pub fn validate_all(
+    config: &ValidationConfig,
+    history: HashMap<String, Vec<Day>>,
+    start: NaiveDate,
+    end: NaiveDate,
+    actions: &HashMap<String, Vec<CorporateAction>>,
+) -> HashMap<String, Vec<Day>> {
+    history
+        .into_iter()
+        .filter_map(|(symbol, days)| {
+            let ex_dates: Vec<NaiveDate> = actions
+                .get(&symbol)
+                .map(|actions| actions.iter().map(|a| a.ex_date).collect())
+                .unwrap_or_default();
+            match validate(config, days, start, end, &ex_dates) {
+                Ok((days, issues)) => {
+                    issues
+                        .iter()
+                        .for_each(|issue| warn!("History for {}: {}", symbol, issue));
+                    Some((symbol, days))
+                }
+                Err(e) => {
+                    error!("Excluding {}, history failed validation: {}", symbol, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Checks a symbol's history for [start, end) against the exchange calendar and for bad bars, then
+// applies the policy. `end` is excluded as its bar may not be complete yet. Closes on ex-dates are not
+// outliers, as splits move raw prices legitimately.
+pub fn validate(
+    config: &ValidationConfig,
+    mut days: Vec<Day>,
+    start: NaiveDate,
+    end: NaiveDate,
+    ex_dates: &[NaiveDate],
+) -> Result<(Vec<Day>, Vec<Issue>), String> {
+    let mut issues = vec![];
+
+    days.sort_by_key(|day| day.date);
+    let mut seen = BTreeSet::new();
+    days.retain(|day| {
+        let first = seen.insert(day.date);
+        if !first {
+            issues.push(Issue::DuplicateDate(day.date));
+        }
+        first
+    });
+
+    let mut good: Vec<bool> = days
+        .iter()
+        .map(|day| {
+            if [day.open, day.high, day.low, day.close]
+                .iter()
+                .any(|px| *px <= 0.0)
+            {
+                issues.push(Issue::NonPositivePrice(day.date));
+                false
+            } else if day.high < day.low {
+                issues.push(Issue::HighBelowLow(day.date));
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    for i in implementation::outliers(&days, &good, config.max_return, ex_dates) {
+        issues.push(Issue::Outlier(days[i].date));
+        good[i] = false;
+    }
+
+    let missing: Vec<NaiveDate> = end
+        .pred_opt()
+        .map(|last| calendar::trading_days(start, last))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|date| !seen.contains(date))
+        .collect();
+    issues.extend(missing.iter().map(|date| Issue::MissingDay(*date)));
+
+    let days = match config.policy {
+        ValidationPolicy::Fail if !issues.is_empty() => {
+            let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+            return Err(issues.join(", "));
+        }
+        ValidationPolicy::Fail | ValidationPolicy::Drop => days
+            .into_iter()
+            .zip(good)
+            .filter_map(|(day, good)| good.then_some(day))
+            .collect(),
+        ValidationPolicy::ForwardFill => implementation::forward_fill(days, &good, &missing),
+    };
+    Ok((days, issues))
+}
+
+mod implementation {
+    use super::*;
+
+    // Indices of isolated spikes: closes more than max_return away from both neighbouring good closes,
+    // in the same direction
+    pub fn outliers(
+        days: &[Day],
+        good: &[bool],
+        max_return: f64,
+        ex_dates: &[NaiveDate],
+    ) -> Vec<usize> {
+        let indices: Vec<usize> = (0..days.len()).filter(|i| good[*i]).collect();
+        indices
+            .windows(3)
+            .filter_map(|window| {
+                let (previous, current, next) =
+                    (&days[window[0]], &days[window[1]], &days[window[2]]);
+                if ex_dates.contains(&current.date) || ex_dates.contains(&next.date) {
+                    return None;
+                }
+                let into = current.close / previous.close - 1.0;
+                let out = next.close / current.close - 1.0;
+                (into.abs() > max_return && out.abs() > max_return && into.signum() != out.signum())
+                    .then_some(window[1])
+            })
+            .collect()
+    }
+
+    // Bad and missing days become flat days at the previous good close, with no volume. There's nothing
+    // to fill from before the first good day.
+    pub fn forward_fill(days: Vec<Day>, good: &[bool], missing: &[NaiveDate]) -> Vec<Day> {
+        let symbol = days.first().and_then(|day| day.symbol.clone());
+        let by_date: HashMap<NaiveDate, &Day> = days
+            .iter()
+            .zip(good)
+            .filter_map(|(day, good)| good.then_some((day.date, day)))
+            .collect();
+        let dates: BTreeSet<NaiveDate> = days
+            .iter()
+            .map(|day| day.date)
+            .chain(missing.iter().cloned())
+            .collect();
+
+        let mut filled: Vec<Day> = Vec::with_capacity(dates.len());
+        for date in dates {
+            match by_date.get(&date) {
+                Some(day) => filled.push((*day).clone()),
+                None => {
+                    if let Some(close) = filled.last().map(|day| day.close) {
+                        filled.push(Day {
+                            symbol: symbol.clone(),
+                            date,
+                            open: close,
+                            high: close,
+                            low: close,
+                            close,
+                            volume: 0,
+                        });
+                    }
+                }
+            }
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+#[path = "./tests/validation_test.rs"]
+mod validation_test;